@@ -0,0 +1,277 @@
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand, ValueEnum};
+
+/// offline tools working on dumps written by the agent
+#[derive(Parser)]
+#[command(name = "b_cli")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// compare two dumps (directories, .jar or .tar) at class, member and bytecode level
+    Diff {
+        old: PathBuf,
+        new: PathBuf,
+        #[arg(long, value_enum, default_value_t = Format::Text)]
+        format: Format,
+    },
+    /// query supertypes, subtypes, implementors and overrides of dumped classes
+    Hierarchy {
+        dump: PathBuf,
+        #[command(subcommand)]
+        query: HierarchyQuery,
+        #[arg(long, value_enum, default_value_t = Format::Text)]
+        format: Format,
+    },
+    /// save root classes and their dependency closure from jars and class directories,
+    /// in the layout and with the reports of an agent dump
+    Closure {
+        #[arg(long = "class-path", short = 'c', required = true)]
+        class_path: Vec<PathBuf>,
+        #[arg(long)]
+        out: PathBuf,
+        #[arg(required = true)]
+        roots: Vec<String>,
+    },
+    /// write the classes of a store session as a dump directory, .jar or .tar
+    Export {
+        store: PathBuf,
+        /// defaults to the latest session
+        #[arg(long)]
+        session: Option<String>,
+        #[arg(long)]
+        out: PathBuf,
+    },
+    /// check a dump directory, .jar or .tar against the session.json in it
+    Verify { output: PathBuf },
+    /// write the classes and metadata of a dump with a session.json into a sqlite file
+    Sqlite {
+        dump: PathBuf,
+        #[arg(long)]
+        out: PathBuf,
+    },
+    /// build a runnable jar and launch scripts from a dump with a session.json
+    Runnable {
+        dump: PathBuf,
+        #[arg(long)]
+        out: PathBuf,
+        /// defaults to the main class of the dumped jvm's command
+        #[arg(long)]
+        main_class: Option<String>,
+        /// keep -agentpath, -agentlib, -javaagent and -Xrun flags in the launch scripts
+        #[arg(long)]
+        keep_agents: bool,
+    },
+    /// ask an agent in watch mode to stop and write its report
+    Stop,
+}
+
+#[derive(Subcommand)]
+enum HierarchyQuery {
+    /// superclass chain and every implemented interface
+    Supertypes { class: String },
+    /// every dumped class extending or implementing the type
+    Subtypes { class: String },
+    /// classes implementing an interface, directly or through supertypes
+    Implementors { interface: String },
+    /// supertypes and subtypes declaring a method, given as name or name(descriptor)
+    Overrides { class: String, method: String },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    Text,
+    Json,
+}
+
+fn main() -> Result<(), b_agent::error::Error> {
+    match Cli::parse().command {
+        Command::Diff { old, new, format } => {
+            let diff = b_agent::diff::diff_dirs(&old, &new)?;
+            match format {
+                Format::Text => print!("{}", diff.to_text()),
+                Format::Json => println!("{}", serde_json::to_string_pretty(&diff)?),
+            }
+        }
+        Command::Hierarchy {
+            dump,
+            query,
+            format,
+        } => {
+            let index = b_agent::hierarchy::HierarchyIndex::load_or_build(&dump)?;
+            let result = match query {
+                HierarchyQuery::Supertypes { class } => serde_json::json!({
+                    "superclasses": index.superclass_chain(&class),
+                    "interfaces": index.interfaces(&class),
+                }),
+                HierarchyQuery::Subtypes { class } => serde_json::json!(index.subtypes(&class)),
+                HierarchyQuery::Implementors { interface } => {
+                    serde_json::json!(index.implementors(&interface))
+                }
+                HierarchyQuery::Overrides { class, method } => {
+                    serde_json::to_value(index.overrides(&class, &method))?
+                }
+            };
+            match format {
+                Format::Text => print_text(&result, ""),
+                Format::Json => println!("{}", serde_json::to_string_pretty(&result)?),
+            }
+        }
+        Command::Closure {
+            class_path,
+            out,
+            roots,
+        } => {
+            let mut class_path = b_agent::classpath::ClassPath::open(&class_path)?;
+            // the sink kind comes from the config, the location from the command line
+            let config = b_agent::config::Config::load()?;
+            let output = b_agent::config::OutputConfig {
+                path: Some(out.clone()),
+                ..config.output.clone()
+            };
+            let sink = b_agent::sink::open(&output, &out)?;
+            let mut recorder = b_agent::recorder::DumpRecorder::new(sink, config);
+            let report = b_agent::closure::extract(&mut class_path, &roots, &mut recorder)?;
+            println!(
+                "saved {} classes, {} unresolved, {} failed",
+                report.saved,
+                report.unresolved.len(),
+                report.failed.len()
+            );
+        }
+        Command::Export {
+            store,
+            session,
+            out,
+        } => {
+            let sessions = b_agent::store::sessions(&store)?;
+            let Some(session) = session.or_else(|| sessions.last().cloned()) else {
+                println!("no sessions in {}", store.display());
+                return Ok(());
+            };
+            if !sessions.contains(&session) {
+                println!("unknown session {session}, sessions in the store:");
+                sessions.iter().for_each(|session| println!("  {session}"));
+                return Ok(());
+            }
+
+            let kind = match out.extension().and_then(|extension| extension.to_str()) {
+                Some("jar") => b_agent::config::OutputKind::Jar,
+                Some("tar") => b_agent::config::OutputKind::Tar,
+                _ => b_agent::config::OutputKind::Directory,
+            };
+            let output = b_agent::config::OutputConfig {
+                kind,
+                path: Some(out.clone()),
+                ..Default::default()
+            };
+            let mut sink = b_agent::sink::open(&output, &out)?;
+            let summary = b_agent::store::export(&store, &session, sink.as_mut())?;
+            println!(
+                "exported {} classes of session {session}, {} shadowed by a class of the same name",
+                summary.classes,
+                summary.shadowed.len()
+            );
+            for entry in &summary.shadowed {
+                println!(
+                    "  {} from {}",
+                    entry.class,
+                    entry.source.loader.as_deref().unwrap_or("unknown loader")
+                );
+            }
+        }
+        Command::Stop => {
+            let stop_path = b_agent::config::Config::load()?.watch.stop_path();
+            std::fs::write(&stop_path, b"")?;
+            println!("wrote {}", stop_path.display());
+        }
+        Command::Verify { output } => {
+            let verification = b_agent::manifest::verify(&output)?;
+            println!(
+                "checked {} files, {} missing, {} changed",
+                verification.checked,
+                verification.missing.len(),
+                verification.mismatched.len()
+            );
+            for path in &verification.missing {
+                println!("  missing: {path}");
+            }
+            for path in &verification.mismatched {
+                println!("  changed: {path}");
+            }
+            if !verification.is_ok() {
+                std::process::exit(1);
+            }
+        }
+        Command::Sqlite { dump, out } => {
+            let summary = b_agent::sqlite::export(&dump, &out)?;
+            println!(
+                "wrote {}: {} classes, {} members, {} references, {} dependencies",
+                out.display(),
+                summary.classes,
+                summary.members,
+                summary.references,
+                summary.dependencies
+            );
+            if summary.unparsed > 0 {
+                println!(
+                    "  {} classes didn't parse, see classes.parse_error",
+                    summary.unparsed
+                );
+            }
+        }
+        Command::Runnable {
+            dump,
+            out,
+            main_class,
+            keep_agents,
+        } => {
+            let summary = b_agent::runnable::export(&dump, &out, main_class, keep_agents)?;
+            println!(
+                "wrote {} with main class {}: {} classes, {} resources, {} service files",
+                out.display(),
+                summary.main_class,
+                summary.classes,
+                summary.resources,
+                summary.services
+            );
+            for script in &summary.scripts {
+                println!("  launch script: {}", script.display());
+            }
+            for entry in &summary.missing_entries {
+                println!("  class path entry not found, resources missing: {entry}");
+            }
+            for provider in &summary.dropped_providers {
+                println!("  service provider not in the dump: {provider}");
+            }
+            for argument in &summary.dropped_arguments {
+                println!("  agent left out of the launch scripts: {argument}");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// one name per line, grouped under their keys
+fn print_text(value: &serde_json::Value, indent: &str) {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (key, value) in map {
+                println!("{indent}{key}:");
+                print_text(value, &format!("{indent}  "));
+            }
+        }
+        serde_json::Value::Array(values) => {
+            for value in values {
+                print_text(value, indent);
+            }
+        }
+        serde_json::Value::String(value) => println!("{indent}{value}"),
+        value => println!("{indent}{value}"),
+    }
+}
@@ -0,0 +1,109 @@
+use std::path::PathBuf;
+
+use b_agent::sink::socket::{self, Frame};
+use clap::{Parser, ValueEnum};
+
+/// receives dumps streamed by agents with output.socket set and writes them like the agent
+/// would, so a dump survives the target being killed
+#[derive(Parser)]
+#[command(name = "b_collector")]
+struct Cli {
+    /// "tcp:<host>:<port>" or "unix:<path>"
+    #[arg(long, default_value = "tcp:127.0.0.1:7070")]
+    listen: String,
+    /// directory every received dump gets its own output in
+    #[arg(long)]
+    out: PathBuf,
+    #[arg(long, value_enum, default_value_t = Kind::Directory)]
+    kind: Kind,
+    /// one jar per jar or directory the classes were loaded from, instead of one output of kind
+    #[arg(long)]
+    group_by_code_source: bool,
+    /// stop after this many dumps, 0 keeps accepting
+    #[arg(long, default_value_t = 0)]
+    count: usize,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Kind {
+    Directory,
+    Jar,
+    Tar,
+}
+
+fn main() -> Result<(), b_agent::error::Error> {
+    let cli = Cli::parse();
+    let kind = match cli.kind {
+        Kind::Directory => b_agent::config::OutputKind::Directory,
+        Kind::Jar => b_agent::config::OutputKind::Jar,
+        Kind::Tar => b_agent::config::OutputKind::Tar,
+    };
+    let listener = socket::Listener::bind(&cli.listen)?;
+    println!("listening on {}", cli.listen);
+
+    let started = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    let mut receivers = Vec::new();
+    for number in 1.. {
+        if cli.count != 0 && number > cli.count {
+            break;
+        }
+
+        let (stream, peer) = listener.accept()?;
+        // one output per dump, agents may stream at the same time
+        let location = cli.out.join(format!("{started}-{number}"));
+        let output = b_agent::config::OutputConfig {
+            kind,
+            group_by_code_source: cli.group_by_code_source,
+            ..Default::default()
+        };
+        receivers.push(std::thread::spawn(move || {
+            if let Err(e) = collect(stream, &peer, &output, &location) {
+                println!("failed to collect the dump from {peer}: {e}");
+            }
+        }));
+    }
+
+    for receiver in receivers {
+        let _ = receiver.join();
+    }
+    Ok(())
+}
+
+// write one stream into a new output, completing it even when the stream was cut off
+fn collect(
+    mut stream: Box<dyn std::io::Read + Send>,
+    peer: &str,
+    output: &b_agent::config::OutputConfig,
+    location: &std::path::Path,
+) -> Result<(), b_agent::error::Error> {
+    socket::read_header(&mut stream)?;
+    let mut sink = b_agent::sink::open(output, location)?;
+    println!("receiving a dump from {peer} into {}", location.display());
+
+    let mut files = 0;
+    let complete = loop {
+        match socket::read_frame(&mut stream) {
+            Ok(Some(Frame::File { path, data })) => sink.write(&path, &data)?,
+            Ok(Some(Frame::Class { origin, path, data })) => {
+                sink.write_class(&origin, &path, &data)?
+            }
+            Ok(Some(Frame::End)) => break true,
+            Ok(None) => break false,
+            Err(e) => {
+                println!("stream from {peer} broke off: {e}");
+                break false;
+            }
+        }
+        files += 1;
+    };
+    sink.finish()?;
+
+    println!(
+        "{} dump from {peer}: {files} files in {}",
+        if complete { "complete" } else { "incomplete" },
+        location.display()
+    );
+    Ok(())
+}
@@ -0,0 +1,66 @@
+use std::collections::HashMap;
+
+use crate::classfile::{binary_name, frames::Hierarchy, internal_name};
+
+// answers hierarchy queries for frame computation by looking classes up in the target jvm,
+// through the loader defining the transformed class
+pub struct JvmHierarchy<'a, 'b> {
+    env: &'b mut jni::JNIEnv<'a>,
+    loader: &'b jni::objects::JObject<'a>,
+    super_classes: HashMap<String, Option<String>>,
+    interfaces: HashMap<String, bool>,
+}
+
+impl<'a, 'b> JvmHierarchy<'a, 'b> {
+    pub fn new(env: &'b mut jni::JNIEnv<'a>, loader: &'b jni::objects::JObject<'a>) -> Self {
+        JvmHierarchy {
+            env,
+            loader,
+            super_classes: HashMap::new(),
+            interfaces: HashMap::new(),
+        }
+    }
+
+    fn lookup(&mut self, class_name: &str) -> Result<(), crate::error::Error> {
+        if self.super_classes.contains_key(class_name) {
+            return Ok(());
+        }
+
+        let loader = self.loader;
+        let (super_class, is_interface) = self.env.with_local_frame(8, |env| {
+            let class = crate::jvm::load_class(env, &binary_name(class_name), loader)?;
+
+            let is_interface = env.call_method(&class, "isInterface", "()Z", &[])?.z()?;
+            let super_class = match env.get_superclass(&class)? {
+                Some(super_class) => {
+                    let name = env
+                        .call_method(&super_class, "getName", "()Ljava/lang/String;", &[])?
+                        .l()?;
+                    let name = jni::objects::JString::from(name);
+                    let name: String = env.get_string(&name)?.into();
+                    Some(internal_name(&name))
+                }
+                None => None,
+            };
+
+            Ok::<_, crate::error::Error>((super_class, is_interface))
+        })?;
+
+        self.super_classes
+            .insert(class_name.to_string(), super_class);
+        self.interfaces.insert(class_name.to_string(), is_interface);
+        Ok(())
+    }
+}
+
+impl Hierarchy for JvmHierarchy<'_, '_> {
+    fn super_class(&mut self, class_name: &str) -> Result<Option<String>, crate::error::Error> {
+        self.lookup(class_name)?;
+        Ok(self.super_classes[class_name].clone())
+    }
+
+    fn is_interface(&mut self, class_name: &str) -> Result<bool, crate::error::Error> {
+        self.lookup(class_name)?;
+        Ok(self.interfaces[class_name])
+    }
+}
@@ -1,9 +1,7 @@
-use std::{
-    collections::HashSet,
-    path::{Path, PathBuf},
-};
+use std::path::Path;
 
 mod cache;
+pub mod hierarchy;
 
 // save location of dumped classes
 fn get_save_location() -> String {
@@ -17,86 +15,143 @@ fn get_save_location() -> String {
 pub struct JavaBridge {
     cache: cache::ClassCache,
     jvm: jni::JavaVM,
-    saved_classes: HashSet<String>,
+    recorder: crate::recorder::DumpRecorder,
 }
 
 impl JavaBridge {
-    pub fn new(jvm: jni::JavaVM) -> Self {
-        JavaBridge {
+    pub fn new(
+        jvm: jni::JavaVM,
+        config: crate::config::Config,
+    ) -> Result<Self, crate::error::Error> {
+        Ok(JavaBridge {
             cache: cache::ClassCache::new(),
             jvm,
-            saved_classes: HashSet::new(),
-        }
+            recorder: crate::recorder::DumpRecorder::open(config, Path::new(&get_save_location()))?,
+        })
     }
 
     pub fn insert_cache(
         &mut self,
         class_name: String,
-        class: jni::objects::JClass<'static>,
+        class: jni::objects::GlobalRef,
     ) -> Result<(), crate::error::Error> {
         self.cache.insert(class_name, class)
     }
 
-    // retransform class using the retransformer class from java side program
-    pub fn on_classfile_load_hook(
+    // save a hooked class, false when it was saved before and needs no analysis
+    pub fn save(
         &mut self,
         class_name: &str,
-        class_data: Vec<u8>,
-        client: &mut Box<dyn crate::injector::ClientTrait>,
-    ) -> Result<Vec<String>, crate::error::Error> {
-        match self.saved_classes.get(class_name) {
-            Some(_) => {
-                // class already saved, no need to retransform
-                return Ok(vec![]);
-            }
-            None => {
-                // save class
-                let save_path =
-                    PathBuf::from(get_save_location()).join(format!("{}.class", (class_name.replace('.', "\\"))));
-                std::fs::create_dir_all(save_path.parent().unwrap())?;
-                std::fs::write(save_path, &class_data)?;
-                println!("saved class: {class_name}");
-
-                self.saved_classes.insert(class_name.to_string());
-            }
+        class_data: &[u8],
+        original: Option<&[u8]>,
+        source: &crate::recorder::ClassSource,
+    ) -> Result<bool, crate::error::Error> {
+        if !self.recorder.save(class_name, class_data, source)? {
+            return Ok(false);
         }
-        let mut env = self.jvm.get_env()?;
+        self.recorder
+            .record_original(class_name, original, class_data, source)?;
+        Ok(true)
+    }
+
+    // what finding the dependencies of a class needs, used without holding the bridge
+    pub fn analyzer(
+        &self,
+        client: &dyn crate::injector::ClientTrait,
+    ) -> Result<Analyzer, crate::error::Error> {
         let Some(retransformer) = self.cache.get(client.retransformer_class_name()) else {
             return Err(crate::error::Error::XValueNotOfType(
                 "retransformer class not found",
             ));
         };
+        Ok(Analyzer {
+            jvm: unsafe { jni::JavaVM::from_raw(self.jvm.get_java_vm_pointer())? },
+            retransformer: retransformer.clone(),
+            // prefer the edge method so the graph knows why a class is needed
+            method_name: client
+                .dependency_edges_method_name()
+                .unwrap_or(client.retransform_method_name())
+                .to_string(),
+            edges: client.dependency_edges_method_name().is_some(),
+        })
+    }
+
+    pub fn recorder(&mut self) -> &mut crate::recorder::DumpRecorder {
+        &mut self.recorder
+    }
 
-        unsafe {
-            let method_id = env.get_static_method_id(
+    // write reports collected over the session next to the dumped classes
+    pub fn finish(&mut self) -> Result<(), crate::error::Error> {
+        self.recorder.finish()
+    }
+}
+
+// finds dependencies of saved classes through the retransformer class from java side program.
+// classes it loads re-enter the load hook, so no agent lock may be held while it runs.
+pub struct Analyzer {
+    jvm: jni::JavaVM,
+    retransformer: jni::objects::GlobalRef,
+    method_name: String,
+    // whether the method returns "<kind> <class name>" entries
+    edges: bool,
+}
+
+impl Analyzer {
+    pub fn dependency_edges(
+        &self,
+        class_data: &[u8],
+    ) -> Result<Vec<(crate::graph::EdgeKind, String)>, crate::error::Error> {
+        // pipeline workers are attached on their first class
+        let mut env = match self.jvm.get_env() {
+            Ok(env) => env,
+            Err(_) => self.jvm.attach_current_thread_as_daemon()?,
+        };
+        let retransformer = <&jni::objects::JClass>::from(self.retransformer.as_obj());
+        let dependencies =
+            call_retransformer(&mut env, retransformer, &self.method_name, class_data)?;
+        Ok(dependencies
+            .into_iter()
+            .map(|dependency| match dependency.split_once(' ') {
+                Some((kind, name)) if self.edges => {
+                    (crate::graph::EdgeKind::parse(kind), name.to_string())
+                }
+                _ => (crate::graph::EdgeKind::Unknown, dependency),
+            })
+            .collect())
+    }
+}
+
+// call a static `([B)[Ljava/lang/String;` method of the retransformer class
+fn call_retransformer(
+    env: &mut jni::JNIEnv,
+    retransformer: &jni::objects::JClass,
+    method_name: &str,
+    class_data: &[u8],
+) -> Result<Vec<String>, crate::error::Error> {
+    unsafe {
+        let method_id =
+            env.get_static_method_id(retransformer, method_name, "([B)[Ljava/lang/String;")?;
+
+        let res = env
+            .call_static_method_unchecked(
                 retransformer,
-                client.retransform_method_name(),
-                "([B)[Ljava/lang/String;",
-            )?;
-
-            let res = env
-                .call_static_method_unchecked(
-                    retransformer,
-                    method_id,
-                    jni::signature::ReturnType::Array,
-                    &[jni::sys::jvalue {
-                        l: env.byte_array_from_slice(&class_data)?.as_raw(),
-                    }],
-                )?
-                .l()?;
-
-            let byte_array = jni::objects::JObjectArray::from_raw(res.as_raw());
-            let classes = (0..env.get_array_length(&byte_array)?)
-                .map(|i| {
-                    let class_name = env.get_object_array_element(&byte_array, i)?;
-                    let binding = jni::objects::JString::from(class_name);
-                    let class_name_str = env.get_string(&binding)?;
-                    Ok(class_name_str.to_string_lossy().into_owned())
-                })
-                .collect::<Result<Vec<String>, crate::error::Error>>()?;
-
-            Ok(classes)
-        }
+                method_id,
+                jni::signature::ReturnType::Array,
+                &[jni::sys::jvalue {
+                    l: env.byte_array_from_slice(class_data)?.as_raw(),
+                }],
+            )?
+            .l()?;
+
+        let byte_array = jni::objects::JObjectArray::from_raw(res.as_raw());
+        (0..env.get_array_length(&byte_array)?)
+            .map(|i| {
+                let class_name = env.get_object_array_element(&byte_array, i)?;
+                let binding = jni::objects::JString::from(class_name);
+                let class_name_str = env.get_string(&binding)?;
+                Ok(class_name_str.to_string_lossy().into_owned())
+            })
+            .collect()
     }
 }
 
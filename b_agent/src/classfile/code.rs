@@ -0,0 +1,242 @@
+use super::Attribute;
+use super::opcode;
+use super::reader::{ByteReader, put_u16, put_u32, read_i32, read_u16};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExceptionHandler {
+    pub start_pc: u16,
+    pub end_pc: u16,
+    pub handler_pc: u16,
+    pub catch_type: u16,
+}
+
+// decoded body of a Code attribute
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CodeAttribute {
+    pub max_stack: u16,
+    pub max_locals: u16,
+    pub code: Vec<u8>,
+    pub exception_table: Vec<ExceptionHandler>,
+    pub attributes: Vec<Attribute>,
+}
+
+impl CodeAttribute {
+    pub fn parse(info: &[u8]) -> Result<Self, crate::error::Error> {
+        let mut reader = ByteReader::new(info);
+        let max_stack = reader.u16()?;
+        let max_locals = reader.u16()?;
+        let code_length = reader.u32()? as usize;
+        if code_length == 0 || code_length >= 65536 {
+            return Err(crate::error::Error::ClassFormat(format!(
+                "invalid code length {code_length}"
+            )));
+        }
+        let code = reader.bytes(code_length)?.to_vec();
+
+        let exception_table = (0..reader.u16()?)
+            .map(|_| {
+                Ok(ExceptionHandler {
+                    start_pc: reader.u16()?,
+                    end_pc: reader.u16()?,
+                    handler_pc: reader.u16()?,
+                    catch_type: reader.u16()?,
+                })
+            })
+            .collect::<Result<Vec<_>, crate::error::Error>>()?;
+
+        let attributes = Attribute::parse_all(&mut reader)?;
+        if reader.remaining() != 0 {
+            return Err(crate::error::Error::ClassFormat(format!(
+                "{} trailing bytes in Code attribute",
+                reader.remaining()
+            )));
+        }
+
+        Ok(CodeAttribute {
+            max_stack,
+            max_locals,
+            code,
+            exception_table,
+            attributes,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        put_u16(&mut out, self.max_stack);
+        put_u16(&mut out, self.max_locals);
+        put_u32(&mut out, self.code.len() as u32);
+        out.extend_from_slice(&self.code);
+        put_u16(&mut out, self.exception_table.len() as u16);
+        for handler in &self.exception_table {
+            put_u16(&mut out, handler.start_pc);
+            put_u16(&mut out, handler.end_pc);
+            put_u16(&mut out, handler.handler_pc);
+            put_u16(&mut out, handler.catch_type);
+        }
+        Attribute::write_all(&self.attributes, &mut out);
+        out
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Instruction {
+    pub offset: usize,
+    pub opcode: u8,
+    pub length: usize,
+    // set for instructions prefixed by `wide`, opcode then holds the modified instruction
+    pub wide: bool,
+}
+
+impl Instruction {
+    // local variable index of a load, store, iinc or ret instruction
+    pub fn local_index(&self, code: &[u8]) -> Result<u16, crate::error::Error> {
+        if self.wide {
+            read_u16(code, self.offset + 2)
+        } else {
+            Ok(code[self.offset + 1] as u16)
+        }
+    }
+
+    pub fn u16_operand(&self, code: &[u8]) -> Result<u16, crate::error::Error> {
+        read_u16(code, self.offset + 1)
+    }
+
+    // absolute offsets this instruction may jump to, not including fall through
+    pub fn branch_targets(&self, code: &[u8]) -> Result<Vec<usize>, crate::error::Error> {
+        let relative = match self.opcode {
+            opcode::IFEQ..=opcode::JSR | opcode::IFNULL | opcode::IFNONNULL => {
+                vec![read_u16(code, self.offset + 1)? as i16 as i64]
+            }
+            opcode::GOTO_W | opcode::JSR_W => vec![read_i32(code, self.offset + 1)? as i64],
+            opcode::TABLESWITCH => {
+                let base = switch_operands(self.offset);
+                let low = read_i32(code, base + 4)? as i64;
+                let high = read_i32(code, base + 8)? as i64;
+                let mut targets = vec![read_i32(code, base)? as i64];
+                for i in 0..(high - low + 1).max(0) as usize {
+                    targets.push(read_i32(code, base + 12 + i * 4)? as i64);
+                }
+                targets
+            }
+            opcode::LOOKUPSWITCH => {
+                let base = switch_operands(self.offset);
+                let pairs = read_i32(code, base + 4)?.max(0) as usize;
+                let mut targets = vec![read_i32(code, base)? as i64];
+                for i in 0..pairs {
+                    targets.push(read_i32(code, base + 12 + i * 8)? as i64);
+                }
+                targets
+            }
+            _ => vec![],
+        };
+
+        relative
+            .into_iter()
+            .map(|delta| {
+                let target = self.offset as i64 + delta;
+                if target < 0 || target >= code.len() as i64 {
+                    return Err(crate::error::Error::ClassFormat(format!(
+                        "branch at {} jumps outside of the code array to {target}",
+                        self.offset
+                    )));
+                }
+                Ok(target as usize)
+            })
+            .collect()
+    }
+}
+
+// switch operands start at the next 4 byte aligned offset after the opcode
+fn switch_operands(offset: usize) -> usize {
+    (offset + 4) & !3
+}
+
+// split a code array into instructions
+pub fn instructions(code: &[u8]) -> Result<Vec<Instruction>, crate::error::Error> {
+    let mut instructions = Vec::new();
+    let mut offset = 0;
+    while offset < code.len() {
+        let instruction = decode(code, offset)?;
+        offset += instruction.length;
+        instructions.push(instruction);
+    }
+
+    if offset != code.len() {
+        return Err(crate::error::Error::ClassFormat(
+            "last instruction runs past the end of the code array".to_string(),
+        ));
+    }
+
+    Ok(instructions)
+}
+
+fn decode(code: &[u8], offset: usize) -> Result<Instruction, crate::error::Error> {
+    let opcode = code[offset];
+    let (opcode, length, wide) = match opcode {
+        opcode::TABLESWITCH => {
+            let base = switch_operands(offset);
+            let low = read_i32(code, base + 4)? as i64;
+            let high = read_i32(code, base + 8)? as i64;
+            if high < low {
+                return Err(crate::error::Error::ClassFormat(format!(
+                    "tableswitch at {offset} has high {high} below low {low}"
+                )));
+            }
+            (
+                opcode,
+                base + 12 + (high - low + 1) as usize * 4 - offset,
+                false,
+            )
+        }
+        opcode::LOOKUPSWITCH => {
+            let base = switch_operands(offset);
+            let pairs = read_i32(code, base + 4)?;
+            if pairs < 0 {
+                return Err(crate::error::Error::ClassFormat(format!(
+                    "lookupswitch at {offset} has negative pair count"
+                )));
+            }
+            (opcode, base + 8 + pairs as usize * 8 - offset, false)
+        }
+        opcode::WIDE => {
+            let Some(&modified) = code.get(offset + 1) else {
+                return Err(crate::error::Error::ClassFormat(format!(
+                    "truncated wide instruction at {offset}"
+                )));
+            };
+            match modified {
+                opcode::IINC => (modified, 6, true),
+                opcode::ILOAD..=opcode::ALOAD | opcode::ISTORE..=opcode::ASTORE | opcode::RET => {
+                    (modified, 4, true)
+                }
+                _ => {
+                    return Err(crate::error::Error::ClassFormat(format!(
+                        "wide applied to invalid opcode {modified} at {offset}"
+                    )));
+                }
+            }
+        }
+        _ => match opcode::fixed_length(opcode) {
+            Some(length) => (opcode, length, false),
+            None => {
+                return Err(crate::error::Error::ClassFormat(format!(
+                    "invalid opcode {opcode} at {offset}"
+                )));
+            }
+        },
+    };
+
+    if offset + length > code.len() {
+        return Err(crate::error::Error::ClassFormat(format!(
+            "instruction at {offset} runs past the end of the code array"
+        )));
+    }
+
+    Ok(Instruction {
+        offset,
+        opcode,
+        length,
+        wide,
+    })
+}
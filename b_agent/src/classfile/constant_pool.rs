@@ -0,0 +1,398 @@
+use super::reader::{ByteReader, put_u16, put_u32};
+
+pub const CONSTANT_UTF8: u8 = 1;
+pub const CONSTANT_INTEGER: u8 = 3;
+pub const CONSTANT_FLOAT: u8 = 4;
+pub const CONSTANT_LONG: u8 = 5;
+pub const CONSTANT_DOUBLE: u8 = 6;
+pub const CONSTANT_CLASS: u8 = 7;
+pub const CONSTANT_STRING: u8 = 8;
+pub const CONSTANT_FIELDREF: u8 = 9;
+pub const CONSTANT_METHODREF: u8 = 10;
+pub const CONSTANT_INTERFACE_METHODREF: u8 = 11;
+pub const CONSTANT_NAME_AND_TYPE: u8 = 12;
+pub const CONSTANT_METHOD_HANDLE: u8 = 15;
+pub const CONSTANT_METHOD_TYPE: u8 = 16;
+pub const CONSTANT_DYNAMIC: u8 = 17;
+pub const CONSTANT_INVOKE_DYNAMIC: u8 = 18;
+pub const CONSTANT_MODULE: u8 = 19;
+pub const CONSTANT_PACKAGE: u8 = 20;
+
+// floating point values are kept as raw bits so that constants compare and hash exactly
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Constant {
+    Utf8(Vec<u8>),
+    Integer(i32),
+    Float(u32),
+    Long(i64),
+    Double(u64),
+    Class(u16),
+    String(u16),
+    FieldRef(u16, u16),
+    MethodRef(u16, u16),
+    InterfaceMethodRef(u16, u16),
+    NameAndType(u16, u16),
+    MethodHandle(u8, u16),
+    MethodType(u16),
+    Dynamic(u16, u16),
+    InvokeDynamic(u16, u16),
+    Module(u16),
+    Package(u16),
+}
+
+impl Constant {
+    pub fn tag(&self) -> u8 {
+        match self {
+            Constant::Utf8(_) => CONSTANT_UTF8,
+            Constant::Integer(_) => CONSTANT_INTEGER,
+            Constant::Float(_) => CONSTANT_FLOAT,
+            Constant::Long(_) => CONSTANT_LONG,
+            Constant::Double(_) => CONSTANT_DOUBLE,
+            Constant::Class(_) => CONSTANT_CLASS,
+            Constant::String(_) => CONSTANT_STRING,
+            Constant::FieldRef(..) => CONSTANT_FIELDREF,
+            Constant::MethodRef(..) => CONSTANT_METHODREF,
+            Constant::InterfaceMethodRef(..) => CONSTANT_INTERFACE_METHODREF,
+            Constant::NameAndType(..) => CONSTANT_NAME_AND_TYPE,
+            Constant::MethodHandle(..) => CONSTANT_METHOD_HANDLE,
+            Constant::MethodType(_) => CONSTANT_METHOD_TYPE,
+            Constant::Dynamic(..) => CONSTANT_DYNAMIC,
+            Constant::InvokeDynamic(..) => CONSTANT_INVOKE_DYNAMIC,
+            Constant::Module(_) => CONSTANT_MODULE,
+            Constant::Package(_) => CONSTANT_PACKAGE,
+        }
+    }
+
+    // long and double constants take up two constant pool slots
+    pub fn is_wide(&self) -> bool {
+        matches!(self, Constant::Long(_) | Constant::Double(_))
+    }
+
+    pub fn parse(reader: &mut ByteReader) -> Result<Self, crate::error::Error> {
+        let position = reader.position();
+        let constant = match reader.u8()? {
+            CONSTANT_UTF8 => {
+                let len = reader.u16()? as usize;
+                Constant::Utf8(reader.bytes(len)?.to_vec())
+            }
+            CONSTANT_INTEGER => Constant::Integer(reader.u32()? as i32),
+            CONSTANT_FLOAT => Constant::Float(reader.u32()?),
+            CONSTANT_LONG => Constant::Long(reader.u64()? as i64),
+            CONSTANT_DOUBLE => Constant::Double(reader.u64()?),
+            CONSTANT_CLASS => Constant::Class(reader.u16()?),
+            CONSTANT_STRING => Constant::String(reader.u16()?),
+            CONSTANT_FIELDREF => Constant::FieldRef(reader.u16()?, reader.u16()?),
+            CONSTANT_METHODREF => Constant::MethodRef(reader.u16()?, reader.u16()?),
+            CONSTANT_INTERFACE_METHODREF => {
+                Constant::InterfaceMethodRef(reader.u16()?, reader.u16()?)
+            }
+            CONSTANT_NAME_AND_TYPE => Constant::NameAndType(reader.u16()?, reader.u16()?),
+            CONSTANT_METHOD_HANDLE => Constant::MethodHandle(reader.u8()?, reader.u16()?),
+            CONSTANT_METHOD_TYPE => Constant::MethodType(reader.u16()?),
+            CONSTANT_DYNAMIC => Constant::Dynamic(reader.u16()?, reader.u16()?),
+            CONSTANT_INVOKE_DYNAMIC => Constant::InvokeDynamic(reader.u16()?, reader.u16()?),
+            CONSTANT_MODULE => Constant::Module(reader.u16()?),
+            CONSTANT_PACKAGE => Constant::Package(reader.u16()?),
+            tag => {
+                return Err(crate::error::Error::ClassFormat(format!(
+                    "unknown constant pool tag {tag} at offset {position}"
+                )));
+            }
+        };
+
+        Ok(constant)
+    }
+
+    // constant pool indices this constant refers to
+    pub fn references(&self) -> Vec<u16> {
+        match self {
+            Constant::Class(index)
+            | Constant::String(index)
+            | Constant::MethodType(index)
+            | Constant::Module(index)
+            | Constant::Package(index)
+            | Constant::MethodHandle(_, index)
+            | Constant::Dynamic(_, index)
+            | Constant::InvokeDynamic(_, index) => vec![*index],
+            Constant::FieldRef(a, b)
+            | Constant::MethodRef(a, b)
+            | Constant::InterfaceMethodRef(a, b)
+            | Constant::NameAndType(a, b) => vec![*a, *b],
+            Constant::Utf8(_)
+            | Constant::Integer(_)
+            | Constant::Float(_)
+            | Constant::Long(_)
+            | Constant::Double(_) => vec![],
+        }
+    }
+
+    // copy of this constant with every constant pool index passed through `map`,
+    // bootstrap method indices of dynamic constants are left alone
+    pub fn map_references(&self, mut map: impl FnMut(u16) -> u16) -> Constant {
+        match self.clone() {
+            Constant::Class(index) => Constant::Class(map(index)),
+            Constant::String(index) => Constant::String(map(index)),
+            Constant::MethodType(index) => Constant::MethodType(map(index)),
+            Constant::Module(index) => Constant::Module(map(index)),
+            Constant::Package(index) => Constant::Package(map(index)),
+            Constant::MethodHandle(kind, index) => Constant::MethodHandle(kind, map(index)),
+            Constant::Dynamic(bootstrap, index) => Constant::Dynamic(bootstrap, map(index)),
+            Constant::InvokeDynamic(bootstrap, index) => {
+                Constant::InvokeDynamic(bootstrap, map(index))
+            }
+            Constant::FieldRef(a, b) => Constant::FieldRef(map(a), map(b)),
+            Constant::MethodRef(a, b) => Constant::MethodRef(map(a), map(b)),
+            Constant::InterfaceMethodRef(a, b) => Constant::InterfaceMethodRef(map(a), map(b)),
+            Constant::NameAndType(a, b) => Constant::NameAndType(map(a), map(b)),
+            other => other,
+        }
+    }
+
+    pub fn write(&self, out: &mut Vec<u8>) {
+        out.push(self.tag());
+        match self {
+            Constant::Utf8(bytes) => {
+                put_u16(out, bytes.len() as u16);
+                out.extend_from_slice(bytes);
+            }
+            Constant::Integer(value) => put_u32(out, *value as u32),
+            Constant::Float(bits) => put_u32(out, *bits),
+            Constant::Long(value) => out.extend_from_slice(&value.to_be_bytes()),
+            Constant::Double(bits) => out.extend_from_slice(&bits.to_be_bytes()),
+            Constant::Class(index)
+            | Constant::String(index)
+            | Constant::MethodType(index)
+            | Constant::Module(index)
+            | Constant::Package(index) => put_u16(out, *index),
+            Constant::FieldRef(a, b)
+            | Constant::MethodRef(a, b)
+            | Constant::InterfaceMethodRef(a, b)
+            | Constant::NameAndType(a, b)
+            | Constant::Dynamic(a, b)
+            | Constant::InvokeDynamic(a, b) => {
+                put_u16(out, *a);
+                put_u16(out, *b);
+            }
+            Constant::MethodHandle(kind, index) => {
+                out.push(*kind);
+                put_u16(out, *index);
+            }
+        }
+    }
+}
+
+// constant pool with 1-based indexing, the slot after a long or double is left empty
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ConstantPool {
+    entries: Vec<Option<Constant>>,
+}
+
+impl ConstantPool {
+    pub fn new() -> Self {
+        ConstantPool {
+            entries: vec![None],
+        }
+    }
+
+    pub fn parse(reader: &mut ByteReader) -> Result<Self, crate::error::Error> {
+        let count = reader.u16()? as usize;
+        if count == 0 {
+            return Err(crate::error::Error::ClassFormat(
+                "constant pool count is zero".to_string(),
+            ));
+        }
+
+        let mut entries = Vec::with_capacity(count);
+        entries.push(None);
+        while entries.len() < count {
+            let constant = Constant::parse(reader)?;
+            let wide = constant.is_wide();
+            entries.push(Some(constant));
+            if wide {
+                if entries.len() >= count {
+                    return Err(crate::error::Error::ClassFormat(
+                        "long or double constant occupies the last constant pool slot".to_string(),
+                    ));
+                }
+                entries.push(None);
+            }
+        }
+
+        Ok(ConstantPool { entries })
+    }
+
+    pub fn write(&self, out: &mut Vec<u8>) {
+        put_u16(out, self.entries.len() as u16);
+        for constant in self.entries.iter().flatten() {
+            constant.write(out);
+        }
+    }
+
+    // the constant_pool_count value, one more than the highest valid index
+    pub fn count(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (u16, &Constant)> {
+        self.entries
+            .iter()
+            .enumerate()
+            .filter_map(|(index, constant)| constant.as_ref().map(|c| (index as u16, c)))
+    }
+
+    pub fn get(&self, index: u16) -> Result<&Constant, crate::error::Error> {
+        match self.entries.get(index as usize) {
+            Some(Some(constant)) => Ok(constant),
+            _ => Err(crate::error::Error::ClassFormat(format!(
+                "invalid constant pool index {index}"
+            ))),
+        }
+    }
+
+    pub fn utf8(&self, index: u16) -> Result<String, crate::error::Error> {
+        match self.get(index)? {
+            Constant::Utf8(bytes) => decode_modified_utf8(bytes),
+            other => Err(unexpected(index, "Utf8", other)),
+        }
+    }
+
+    pub fn class_name(&self, index: u16) -> Result<String, crate::error::Error> {
+        match self.get(index)? {
+            Constant::Class(name_index) => self.utf8(*name_index),
+            other => Err(unexpected(index, "Class", other)),
+        }
+    }
+
+    pub fn name_and_type(&self, index: u16) -> Result<(String, String), crate::error::Error> {
+        match self.get(index)? {
+            Constant::NameAndType(name_index, descriptor_index) => {
+                Ok((self.utf8(*name_index)?, self.utf8(*descriptor_index)?))
+            }
+            other => Err(unexpected(index, "NameAndType", other)),
+        }
+    }
+
+    // owner, name and descriptor of a field, method or interface method reference
+    pub fn member_ref(&self, index: u16) -> Result<(String, String, String), crate::error::Error> {
+        match self.get(index)? {
+            Constant::FieldRef(class_index, nat_index)
+            | Constant::MethodRef(class_index, nat_index)
+            | Constant::InterfaceMethodRef(class_index, nat_index) => {
+                let (name, descriptor) = self.name_and_type(*nat_index)?;
+                Ok((self.class_name(*class_index)?, name, descriptor))
+            }
+            other => Err(unexpected(index, "member reference", other)),
+        }
+    }
+
+    // add a constant, reusing an identical existing entry
+    pub fn add(&mut self, constant: Constant) -> Result<u16, crate::error::Error> {
+        if let Some((index, _)) = self.iter().find(|(_, existing)| **existing == constant) {
+            return Ok(index);
+        }
+
+        self.push(constant)
+    }
+
+    // append a constant without looking for an existing copy
+    pub fn push(&mut self, constant: Constant) -> Result<u16, crate::error::Error> {
+        let needed = if constant.is_wide() { 2 } else { 1 };
+        if self.entries.len() + needed > u16::MAX as usize {
+            return Err(crate::error::Error::ClassFormat(
+                "constant pool is full".to_string(),
+            ));
+        }
+
+        let index = self.entries.len() as u16;
+        let wide = constant.is_wide();
+        self.entries.push(Some(constant));
+        if wide {
+            self.entries.push(None);
+        }
+
+        Ok(index)
+    }
+
+    pub fn add_utf8(&mut self, value: &str) -> Result<u16, crate::error::Error> {
+        self.add(Constant::Utf8(encode_modified_utf8(value)))
+    }
+
+    pub fn add_class(&mut self, name: &str) -> Result<u16, crate::error::Error> {
+        let name_index = self.add_utf8(name)?;
+        self.add(Constant::Class(name_index))
+    }
+
+    pub fn find_utf8(&self, value: &str) -> Option<u16> {
+        let encoded = encode_modified_utf8(value);
+        self.iter()
+            .find(|(_, constant)| matches!(constant, Constant::Utf8(bytes) if *bytes == encoded))
+            .map(|(index, _)| index)
+    }
+}
+
+fn unexpected(index: u16, expected: &str, found: &Constant) -> crate::error::Error {
+    crate::error::Error::ClassFormat(format!(
+        "constant pool index {index} is not {expected} (tag {})",
+        found.tag()
+    ))
+}
+
+// class files store strings in java's modified utf-8
+pub fn decode_modified_utf8(bytes: &[u8]) -> Result<String, crate::error::Error> {
+    cesu8::from_java_cesu8(bytes)
+        .map(|s| s.into_owned())
+        .map_err(|_| crate::error::Error::ClassFormat("invalid modified utf-8".to_string()))
+}
+
+// decode for display, lone surrogates are written as \uXXXX escapes instead of failing
+pub fn decode_modified_utf8_escaped(bytes: &[u8]) -> String {
+    let mut units = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let (unit, len) = match bytes[i] {
+            b @ 0xC0..=0xDF if i + 1 < bytes.len() => {
+                ((b as u16 & 0x1F) << 6 | (bytes[i + 1] as u16 & 0x3F), 2)
+            }
+            b @ 0xE0..=0xEF if i + 2 < bytes.len() => (
+                (b as u16 & 0x0F) << 12
+                    | (bytes[i + 1] as u16 & 0x3F) << 6
+                    | (bytes[i + 2] as u16 & 0x3F),
+                3,
+            ),
+            b => (b as u16, 1),
+        };
+        units.push(unit);
+        i += len;
+    }
+
+    char::decode_utf16(units)
+        .map(|c| match c {
+            Ok(c) => c.to_string(),
+            Err(e) => format!("\\u{:04X}", e.unpaired_surrogate()),
+        })
+        .collect()
+}
+
+// byte level check of modified utf-8, lone surrogates are allowed in string constants
+// even though they can't be decoded into a rust string
+pub fn is_modified_utf8(bytes: &[u8]) -> bool {
+    let mut i = 0;
+    while i < bytes.len() {
+        let len = match bytes[i] {
+            0x01..=0x7F => 1,
+            0xC0..=0xDF => 2,
+            0xE0..=0xEF => 3,
+            _ => return false,
+        };
+        if i + len > bytes.len() || !bytes[i + 1..i + len].iter().all(|b| b & 0xC0 == 0x80) {
+            return false;
+        }
+        i += len;
+    }
+
+    true
+}
+
+pub fn encode_modified_utf8(value: &str) -> Vec<u8> {
+    cesu8::to_java_cesu8(value).into_owned()
+}
@@ -0,0 +1,283 @@
+use std::collections::BTreeSet;
+
+use super::{
+    ClassFile, Constant, ConstantPool, Member, binary_name, code, descriptor, opcode,
+    reader::ByteReader,
+};
+use crate::graph::EdgeKind;
+
+// classes a class refers to and why, by binary name. this is the relation the java side
+// retransformer computes, so offline extraction follows the same classes as the agent:
+// arrays are reduced to their element type and java.lang.Object is left out.
+pub fn dependency_edges(
+    class_file: &ClassFile,
+) -> Result<BTreeSet<(EdgeKind, String)>, crate::error::Error> {
+    let mut collector = Collector {
+        class_file,
+        constant_pool: &class_file.constant_pool,
+        edges: BTreeSet::new(),
+    };
+
+    if let Some(super_name) = class_file.super_name()? {
+        collector.name(EdgeKind::Extends, &super_name);
+    }
+    for interface in class_file.interface_names()? {
+        collector.name(EdgeKind::Implements, &interface);
+    }
+    collector.annotations(&class_file.attributes)?;
+
+    for field in &class_file.fields {
+        collector.field_descriptor(EdgeKind::FieldType, &class_file.member_descriptor(field)?);
+        collector.annotations(&field.attributes)?;
+    }
+    for method in &class_file.methods {
+        collector.method(method)?;
+    }
+
+    Ok(collector.edges)
+}
+
+struct Collector<'a> {
+    class_file: &'a ClassFile,
+    constant_pool: &'a ConstantPool,
+    edges: BTreeSet<(EdgeKind, String)>,
+}
+
+impl Collector<'_> {
+    fn name(&mut self, kind: EdgeKind, internal_name: &str) {
+        if internal_name.starts_with('[') || internal_name == "java/lang/Object" {
+            return;
+        }
+        self.edges.insert((kind, binary_name(internal_name)));
+    }
+
+    fn field_descriptor(&mut self, kind: EdgeKind, field_descriptor: &str) {
+        if let Some(class_name) = descriptor::referenced_class(field_descriptor) {
+            self.name(kind, class_name);
+        }
+    }
+
+    fn method_descriptor(
+        &mut self,
+        kind: EdgeKind,
+        method_descriptor: &str,
+    ) -> Result<(), crate::error::Error> {
+        let (parameters, return_type) = descriptor::parse_method(method_descriptor)?;
+        self.field_descriptor(kind, &return_type);
+        for parameter in &parameters {
+            self.field_descriptor(kind, parameter);
+        }
+        Ok(())
+    }
+
+    // a class constant names either a class or an array descriptor
+    fn class_constant(&mut self, kind: EdgeKind, index: u16) -> Result<(), crate::error::Error> {
+        let class_name = self.constant_pool.class_name(index)?;
+        if class_name.starts_with('[') {
+            self.field_descriptor(kind, &class_name);
+        } else {
+            self.name(kind, &class_name);
+        }
+        Ok(())
+    }
+
+    fn method(&mut self, method: &Member) -> Result<(), crate::error::Error> {
+        self.method_descriptor(
+            EdgeKind::Signature,
+            &self.class_file.member_descriptor(method)?,
+        )?;
+        if let Some(attribute) = self
+            .class_file
+            .find_attribute(&method.attributes, "Exceptions")
+        {
+            let mut reader = ByteReader::new(&attribute.info);
+            for _ in 0..reader.u16()? {
+                let exception = self.constant_pool.class_name(reader.u16()?)?;
+                self.name(EdgeKind::Throws, &exception);
+            }
+        }
+        self.annotations(&method.attributes)?;
+
+        let Some(code) = self.class_file.code(method)? else {
+            return Ok(());
+        };
+        for instruction in code::instructions(&code.code)? {
+            match instruction.opcode {
+                opcode::NEW => {
+                    let index = instruction.u16_operand(&code.code)?;
+                    let class_name = self.constant_pool.class_name(index)?;
+                    self.name(EdgeKind::Instantiates, &class_name);
+                }
+                opcode::ANEWARRAY | opcode::CHECKCAST | opcode::INSTANCEOF => {
+                    let index = instruction.u16_operand(&code.code)?;
+                    let class_name = self.constant_pool.class_name(index)?;
+                    self.name(EdgeKind::TypeCheck, &class_name);
+                }
+                opcode::MULTIANEWARRAY => {
+                    let index = instruction.u16_operand(&code.code)?;
+                    self.class_constant(EdgeKind::Instantiates, index)?;
+                }
+                opcode::GETSTATIC..=opcode::PUTFIELD => {
+                    let index = instruction.u16_operand(&code.code)?;
+                    let (owner, _, field_descriptor) = self.constant_pool.member_ref(index)?;
+                    self.name(EdgeKind::FieldAccess, &owner);
+                    self.field_descriptor(EdgeKind::FieldAccess, &field_descriptor);
+                }
+                opcode::INVOKEVIRTUAL..=opcode::INVOKEINTERFACE => {
+                    let index = instruction.u16_operand(&code.code)?;
+                    let (owner, _, method_descriptor) = self.constant_pool.member_ref(index)?;
+                    self.name(EdgeKind::Calls, &owner);
+                    self.method_descriptor(EdgeKind::Calls, &method_descriptor)?;
+                }
+                opcode::LDC | opcode::LDC_W => {
+                    let index = match instruction.opcode {
+                        opcode::LDC => code.code[instruction.offset + 1] as u16,
+                        _ => instruction.u16_operand(&code.code)?,
+                    };
+                    if let Constant::Class(_) = self.constant_pool.get(index)? {
+                        self.class_constant(EdgeKind::ClassLiteral, index)?;
+                    }
+                }
+                _ => {}
+            }
+        }
+        for handler in &code.exception_table {
+            if handler.catch_type != 0 {
+                let class_name = self.constant_pool.class_name(handler.catch_type)?;
+                self.name(EdgeKind::Catches, &class_name);
+            }
+        }
+
+        Ok(())
+    }
+
+    // types of the annotations on a class, field, method or method parameter.
+    // annotation values and type annotations are not followed.
+    fn annotations(&mut self, attributes: &[super::Attribute]) -> Result<(), crate::error::Error> {
+        for attribute in attributes {
+            let parameters = match self.class_file.attribute_name(attribute)?.as_str() {
+                "RuntimeVisibleAnnotations" | "RuntimeInvisibleAnnotations" => false,
+                "RuntimeVisibleParameterAnnotations" | "RuntimeInvisibleParameterAnnotations" => {
+                    true
+                }
+                _ => continue,
+            };
+
+            let mut reader = ByteReader::new(&attribute.info);
+            let lists = if parameters { reader.u8()? } else { 1 };
+            for _ in 0..lists {
+                for _ in 0..reader.u16()? {
+                    let annotation_type = self.constant_pool.utf8(reader.u16()?)?;
+                    self.field_descriptor(EdgeKind::Annotation, &annotation_type);
+                    skip_element_values(&mut reader)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// skip the element value pairs of an annotation whose type was already read
+fn skip_element_values(reader: &mut ByteReader) -> Result<(), crate::error::Error> {
+    for _ in 0..reader.u16()? {
+        reader.u16()?;
+        skip_element_value(reader)?;
+    }
+    Ok(())
+}
+
+fn skip_element_value(reader: &mut ByteReader) -> Result<(), crate::error::Error> {
+    match reader.u8()? {
+        b'B' | b'C' | b'D' | b'F' | b'I' | b'J' | b'S' | b'Z' | b's' | b'c' => {
+            reader.u16()?;
+        }
+        b'e' => {
+            reader.u32()?;
+        }
+        b'@' => {
+            reader.u16()?;
+            skip_element_values(reader)?;
+        }
+        b'[' => {
+            for _ in 0..reader.u16()? {
+                skip_element_value(reader)?;
+            }
+        }
+        tag => {
+            return Err(crate::error::Error::ClassFormat(format!(
+                "invalid element value tag {tag}"
+            )));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::classfile::{ACC_PUBLIC, Attribute};
+
+    #[test]
+    fn test_edges() {
+        // class a/B implementing a/I with an annotated a/C[] field and a throwing method
+        let mut constant_pool = ConstantPool::new();
+        let this_class = constant_pool.add_class("a/B").unwrap();
+        let super_class = constant_pool.add_class("java/lang/Object").unwrap();
+        let interface = constant_pool.add_class("a/I").unwrap();
+        let exception = constant_pool.add_class("a/E").unwrap();
+        let name = constant_pool.add_utf8("value").unwrap();
+        let field_descriptor = constant_pool.add_utf8("[[La/C;").unwrap();
+        let method_descriptor = constant_pool
+            .add_utf8("(ILjava/lang/Object;)La/R;")
+            .unwrap();
+        let annotations = constant_pool.add_utf8("RuntimeVisibleAnnotations").unwrap();
+        let annotation_type = constant_pool.add_utf8("La/A;").unwrap();
+        let exceptions = constant_pool.add_utf8("Exceptions").unwrap();
+
+        let mut annotation_info = vec![0, 1];
+        annotation_info.extend(annotation_type.to_be_bytes());
+        annotation_info.extend([0, 0]);
+        let mut exceptions_info = vec![0, 1];
+        exceptions_info.extend(exception.to_be_bytes());
+
+        let class_file = ClassFile {
+            minor_version: 0,
+            major_version: 52,
+            constant_pool,
+            access_flags: ACC_PUBLIC,
+            this_class,
+            super_class,
+            interfaces: vec![interface],
+            fields: vec![Member {
+                access_flags: ACC_PUBLIC,
+                name_index: name,
+                descriptor_index: field_descriptor,
+                attributes: vec![Attribute {
+                    name_index: annotations,
+                    info: annotation_info,
+                }],
+            }],
+            methods: vec![Member {
+                access_flags: ACC_PUBLIC,
+                name_index: name,
+                descriptor_index: method_descriptor,
+                attributes: vec![Attribute {
+                    name_index: exceptions,
+                    info: exceptions_info,
+                }],
+            }],
+            attributes: vec![],
+        };
+
+        let edges = dependency_edges(&class_file).unwrap();
+        let expected = [
+            (EdgeKind::Implements, "a.I"),
+            (EdgeKind::FieldType, "a.C"),
+            (EdgeKind::Annotation, "a.A"),
+            (EdgeKind::Signature, "a.R"),
+            (EdgeKind::Throws, "a.E"),
+        ]
+        .map(|(kind, name)| (kind, name.to_string()));
+        assert_eq!(edges, BTreeSet::from(expected));
+    }
+}
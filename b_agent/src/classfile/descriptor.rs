@@ -0,0 +1,61 @@
+// split a method descriptor into its parameter descriptors and return descriptor
+pub fn parse_method(descriptor: &str) -> Result<(Vec<String>, String), crate::error::Error> {
+    let invalid =
+        || crate::error::Error::ClassFormat(format!("invalid method descriptor {descriptor}"));
+
+    let Some(rest) = descriptor.strip_prefix('(') else {
+        return Err(invalid());
+    };
+    let Some((params, ret)) = rest.split_once(')') else {
+        return Err(invalid());
+    };
+
+    let mut parameters = Vec::new();
+    let mut remaining = params;
+    while !remaining.is_empty() {
+        let len = field_length(remaining).ok_or_else(invalid)?;
+        parameters.push(remaining[..len].to_string());
+        remaining = &remaining[len..];
+    }
+
+    if ret != "V" && field_length(ret) != Some(ret.len()) {
+        return Err(invalid());
+    }
+
+    Ok((parameters, ret.to_string()))
+}
+
+// length of the field descriptor at the start of the input
+fn field_length(input: &str) -> Option<usize> {
+    let bytes = input.as_bytes();
+    let dims = bytes.iter().take_while(|&&b| b == b'[').count();
+    match bytes.get(dims)? {
+        b'B' | b'C' | b'D' | b'F' | b'I' | b'J' | b'S' | b'Z' => Some(dims + 1),
+        b'L' => input[dims..]
+            .find(';')
+            .filter(|&end| end > 1)
+            .map(|end| dims + end + 1),
+        _ => None,
+    }
+}
+
+pub fn is_valid_field(descriptor: &str) -> bool {
+    field_length(descriptor) == Some(descriptor.len())
+}
+
+// number of local variable slots / stack words a value of this type takes
+pub fn size(descriptor: &str) -> usize {
+    match descriptor {
+        "J" | "D" => 2,
+        "V" => 0,
+        _ => 1,
+    }
+}
+
+// class referenced by a field descriptor, with array dimensions stripped
+pub fn referenced_class(descriptor: &str) -> Option<&str> {
+    descriptor
+        .trim_start_matches('[')
+        .strip_prefix('L')
+        .and_then(|rest| rest.strip_suffix(';'))
+}
@@ -0,0 +1,215 @@
+use std::collections::BTreeSet;
+
+use super::code::{CodeAttribute, Instruction, instructions};
+use super::constant_pool::{Constant, decode_modified_utf8_escaped};
+use super::reader::{ByteReader, read_i32, read_u16};
+use super::{ClassFile, opcode};
+
+// textual listing of a method body with constant pool operands resolved and branch targets
+// replaced by labels, so two builds of the same method compare equal even if offsets or
+// constant pool indices moved
+pub fn disassemble(
+    class_file: &ClassFile,
+    code: &CodeAttribute,
+) -> Result<Vec<String>, crate::error::Error> {
+    let instructions = instructions(&code.code)?;
+
+    let mut targets = BTreeSet::new();
+    for instruction in &instructions {
+        targets.extend(instruction.branch_targets(&code.code)?);
+    }
+    for handler in &code.exception_table {
+        targets.extend([
+            handler.start_pc as usize,
+            handler.end_pc as usize,
+            handler.handler_pc as usize,
+        ]);
+    }
+    let labels = targets.into_iter().collect::<Vec<_>>();
+    let label = |offset: usize| match labels.binary_search(&offset) {
+        Ok(index) => format!("L{index}"),
+        Err(_) => format!("@{offset}"),
+    };
+
+    let mut lines = Vec::new();
+    for instruction in &instructions {
+        if labels.binary_search(&instruction.offset).is_ok() {
+            lines.push(format!("{}:", label(instruction.offset)));
+        }
+        lines.push(render(class_file, code, instruction, &label)?);
+    }
+    if labels.binary_search(&code.code.len()).is_ok() {
+        lines.push(format!("{}:", label(code.code.len())));
+    }
+
+    for handler in &code.exception_table {
+        let catch_type = match handler.catch_type {
+            0 => "any".to_string(),
+            index => class_file.constant_pool.class_name(index)?,
+        };
+        lines.push(format!(
+            "catch {catch_type} {} {} -> {}",
+            label(handler.start_pc as usize),
+            label(handler.end_pc as usize),
+            label(handler.handler_pc as usize)
+        ));
+    }
+
+    Ok(lines)
+}
+
+fn render(
+    class_file: &ClassFile,
+    code: &CodeAttribute,
+    instruction: &Instruction,
+    label: &dyn Fn(usize) -> String,
+) -> Result<String, crate::error::Error> {
+    let bytes = &code.code;
+    let offset = instruction.offset;
+    let name = opcode::name(instruction.opcode).unwrap_or("?");
+    let targets = || instruction.branch_targets(bytes);
+
+    let operands = match instruction.opcode {
+        opcode::BIPUSH => (bytes[offset + 1] as i8).to_string(),
+        opcode::SIPUSH => (read_u16(bytes, offset + 1)? as i16).to_string(),
+        opcode::LDC => constant(class_file, bytes[offset + 1] as u16)?,
+        opcode::LDC_W
+        | opcode::LDC2_W
+        | opcode::GETSTATIC..=opcode::INVOKEDYNAMIC
+        | opcode::NEW
+        | opcode::ANEWARRAY
+        | opcode::CHECKCAST
+        | opcode::INSTANCEOF => constant(class_file, instruction.u16_operand(bytes)?)?,
+        opcode::MULTIANEWARRAY => format!(
+            "{} {}",
+            constant(class_file, instruction.u16_operand(bytes)?)?,
+            bytes[offset + 3]
+        ),
+        opcode::NEWARRAY => match bytes[offset + 1] {
+            4 => "boolean",
+            5 => "char",
+            6 => "float",
+            7 => "double",
+            8 => "byte",
+            9 => "short",
+            10 => "int",
+            11 => "long",
+            _ => "?",
+        }
+        .to_string(),
+        opcode::IINC => {
+            let increment = if instruction.wide {
+                read_u16(bytes, offset + 4)? as i16
+            } else {
+                bytes[offset + 2] as i8 as i16
+            };
+            format!("{} {increment}", instruction.local_index(bytes)?)
+        }
+        opcode::ILOAD..=opcode::ALOAD | opcode::ISTORE..=opcode::ASTORE | opcode::RET => {
+            instruction.local_index(bytes)?.to_string()
+        }
+        opcode::TABLESWITCH => {
+            let base = (offset + 4) & !3;
+            let low = read_i32(bytes, base + 4)?;
+            let targets = targets()?;
+            let mut cases = vec![format!("default: {}", label(targets[0]))];
+            for (i, target) in targets[1..].iter().enumerate() {
+                cases.push(format!("{}: {}", low as i64 + i as i64, label(*target)));
+            }
+            cases.join(", ")
+        }
+        opcode::LOOKUPSWITCH => {
+            let base = (offset + 4) & !3;
+            let targets = targets()?;
+            let mut cases = vec![format!("default: {}", label(targets[0]))];
+            for (i, target) in targets[1..].iter().enumerate() {
+                let key = read_i32(bytes, base + 8 + i * 8)?;
+                cases.push(format!("{key}: {}", label(*target)));
+            }
+            cases.join(", ")
+        }
+        _ => targets()?
+            .into_iter()
+            .map(label)
+            .collect::<Vec<_>>()
+            .join(" "),
+    };
+
+    if operands.is_empty() {
+        Ok(name.to_string())
+    } else {
+        Ok(format!("{name} {operands}"))
+    }
+}
+
+// symbolic form of a constant pool entry
+pub fn constant(class_file: &ClassFile, index: u16) -> Result<String, crate::error::Error> {
+    let constant_pool = &class_file.constant_pool;
+    let rendered = match constant_pool.get(index)? {
+        Constant::Utf8(_) => constant_pool.utf8(index)?,
+        Constant::Integer(value) => value.to_string(),
+        Constant::Float(bits) => format!("{:?}f", f32::from_bits(*bits)),
+        Constant::Long(value) => format!("{value}L"),
+        Constant::Double(bits) => format!("{:?}d", f64::from_bits(*bits)),
+        Constant::Class(_) => constant_pool.class_name(index)?,
+        Constant::String(value) => match constant_pool.get(*value)? {
+            Constant::Utf8(bytes) => format!("{:?}", decode_modified_utf8_escaped(bytes)),
+            _ => constant_pool.utf8(*value)?,
+        },
+        Constant::FieldRef(..) => {
+            let (owner, name, descriptor) = constant_pool.member_ref(index)?;
+            format!("{owner}.{name}:{descriptor}")
+        }
+        Constant::MethodRef(..) | Constant::InterfaceMethodRef(..) => {
+            let (owner, name, descriptor) = constant_pool.member_ref(index)?;
+            format!("{owner}.{name}{descriptor}")
+        }
+        Constant::NameAndType(..) => {
+            let (name, descriptor) = constant_pool.name_and_type(index)?;
+            format!("{name}:{descriptor}")
+        }
+        Constant::MethodHandle(kind, reference) => {
+            format!("handle {kind} {}", constant(class_file, *reference)?)
+        }
+        Constant::MethodType(descriptor) => constant_pool.utf8(*descriptor)?,
+        Constant::Dynamic(bootstrap, name_and_type)
+        | Constant::InvokeDynamic(bootstrap, name_and_type) => {
+            let (name, descriptor) = constant_pool.name_and_type(*name_and_type)?;
+            format!(
+                "{name}{descriptor} [{}]",
+                bootstrap_method(class_file, *bootstrap)?
+            )
+        }
+        Constant::Module(name) | Constant::Package(name) => constant_pool.utf8(*name)?,
+    };
+
+    Ok(rendered)
+}
+
+// bootstrap method handle and static arguments of an invokedynamic or dynamic constant
+fn bootstrap_method(class_file: &ClassFile, index: u16) -> Result<String, crate::error::Error> {
+    let Some(attribute) = class_file.find_attribute(&class_file.attributes, "BootstrapMethods")
+    else {
+        return Ok(format!("bootstrap {index}"));
+    };
+
+    let mut reader = ByteReader::new(&attribute.info);
+    let count = reader.u16()?;
+    for current in 0..count {
+        let method = reader.u16()?;
+        let arguments = (0..reader.u16()?)
+            .map(|_| reader.u16())
+            .collect::<Result<Vec<_>, _>>()?;
+        if current == index {
+            let mut rendered = vec![constant(class_file, method)?];
+            for argument in arguments {
+                rendered.push(constant(class_file, argument)?);
+            }
+            return Ok(rendered.join(", "));
+        }
+    }
+
+    Err(crate::error::Error::ClassFormat(format!(
+        "bootstrap method {index} does not exist"
+    )))
+}
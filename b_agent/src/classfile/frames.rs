@@ -0,0 +1,1099 @@
+use std::collections::HashMap;
+
+use super::code::{CodeAttribute, ExceptionHandler, Instruction, instructions};
+use super::constant_pool::{Constant, ConstantPool};
+use super::reader::put_u16;
+use super::{ACC_STATIC, descriptor, opcode};
+
+const OBJECT: &str = "java/lang/Object";
+const THROWABLE: &str = "java/lang/Throwable";
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum VerificationType {
+    Top,
+    Integer,
+    Float,
+    Long,
+    Double,
+    Null,
+    UninitializedThis,
+    // offset of the `new` instruction that created the object
+    Uninitialized(u16),
+    // internal name for classes, descriptor for arrays
+    Object(String),
+}
+
+impl VerificationType {
+    pub fn from_descriptor(descriptor: &str) -> Result<Self, crate::error::Error> {
+        let kind = match descriptor.as_bytes().first() {
+            Some(b'B' | b'C' | b'I' | b'S' | b'Z') => VerificationType::Integer,
+            Some(b'F') => VerificationType::Float,
+            Some(b'J') => VerificationType::Long,
+            Some(b'D') => VerificationType::Double,
+            Some(b'L') => match descriptor::referenced_class(descriptor) {
+                Some(name) => VerificationType::Object(name.to_string()),
+                None => return Err(invalid_descriptor(descriptor)),
+            },
+            Some(b'[') => VerificationType::Object(descriptor.to_string()),
+            _ => return Err(invalid_descriptor(descriptor)),
+        };
+
+        Ok(kind)
+    }
+
+    // long and double take two local variable slots and two stack words
+    pub fn is_wide(&self) -> bool {
+        matches!(self, VerificationType::Long | VerificationType::Double)
+    }
+}
+
+fn invalid_descriptor(descriptor: &str) -> crate::error::Error {
+    crate::error::Error::ClassFormat(format!("invalid field descriptor {descriptor}"))
+}
+
+// answers the type hierarchy questions needed to merge reference types
+pub trait Hierarchy {
+    // internal name of the direct superclass, None for java/lang/Object
+    fn super_class(&mut self, class_name: &str) -> Result<Option<String>, crate::error::Error>;
+
+    fn is_interface(&mut self, class_name: &str) -> Result<bool, crate::error::Error>;
+}
+
+// frame in word form, the second word of a long or double is Top
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Frame {
+    pub locals: Vec<VerificationType>,
+    pub stack: Vec<VerificationType>,
+}
+
+impl Frame {
+    // locals as written to a StackMapTable, one entry per value without trailing Tops
+    pub fn compact_locals(&self) -> Vec<VerificationType> {
+        let mut locals = compact(&self.locals);
+        while locals.last() == Some(&VerificationType::Top) {
+            locals.pop();
+        }
+        locals
+    }
+
+    pub fn compact_stack(&self) -> Vec<VerificationType> {
+        compact(&self.stack)
+    }
+
+    fn push(&mut self, value: VerificationType) {
+        let wide = value.is_wide();
+        self.stack.push(value);
+        if wide {
+            self.stack.push(VerificationType::Top);
+        }
+    }
+
+    fn pop_words(&mut self, count: usize) -> Result<Vec<VerificationType>, crate::error::Error> {
+        if self.stack.len() < count {
+            return Err(crate::error::Error::ClassFormat(
+                "operand stack underflow".to_string(),
+            ));
+        }
+        Ok(self.stack.split_off(self.stack.len() - count))
+    }
+
+    fn pop(&mut self, count: usize) -> Result<(), crate::error::Error> {
+        self.pop_words(count).map(|_| ())
+    }
+
+    fn pop_reference(&mut self) -> Result<VerificationType, crate::error::Error> {
+        Ok(self.pop_words(1)?.remove(0))
+    }
+
+    fn set_local(&mut self, index: usize, value: VerificationType) {
+        let wide = value.is_wide();
+        let needed = index + if wide { 2 } else { 1 };
+        if self.locals.len() < needed {
+            self.locals.resize(needed, VerificationType::Top);
+        }
+
+        if index > 0 && self.locals[index - 1].is_wide() {
+            self.locals[index - 1] = VerificationType::Top;
+        }
+        self.locals[index] = value;
+        if wide {
+            self.locals[index + 1] = VerificationType::Top;
+        }
+    }
+
+    fn get_local(&self, index: usize) -> Result<VerificationType, crate::error::Error> {
+        self.locals.get(index).cloned().ok_or_else(|| {
+            crate::error::Error::ClassFormat(format!("load from unset local variable {index}"))
+        })
+    }
+
+    fn replace(&mut self, from: &VerificationType, to: &VerificationType) {
+        for value in self.locals.iter_mut().chain(self.stack.iter_mut()) {
+            if value == from {
+                *value = to.clone();
+            }
+        }
+    }
+}
+
+fn compact(words: &[VerificationType]) -> Vec<VerificationType> {
+    let mut values = Vec::with_capacity(words.len());
+    let mut i = 0;
+    while i < words.len() {
+        values.push(words[i].clone());
+        i += if words[i].is_wide() { 2 } else { 1 };
+    }
+    values
+}
+
+// implicit frame at the start of a method, derived from its descriptor
+pub fn initial_frame(
+    this_class: &str,
+    access_flags: u16,
+    name: &str,
+    method_descriptor: &str,
+) -> Result<Frame, crate::error::Error> {
+    let mut frame = Frame::default();
+    if access_flags & ACC_STATIC == 0 {
+        if name == "<init>" && this_class != OBJECT {
+            frame.locals.push(VerificationType::UninitializedThis);
+        } else {
+            frame
+                .locals
+                .push(VerificationType::Object(this_class.to_string()));
+        }
+    }
+
+    let (parameters, _) = descriptor::parse_method(method_descriptor)?;
+    for parameter in parameters {
+        let value = VerificationType::from_descriptor(&parameter)?;
+        let wide = value.is_wide();
+        frame.locals.push(value);
+        if wide {
+            frame.locals.push(VerificationType::Top);
+        }
+    }
+
+    Ok(frame)
+}
+
+// result of analysing one method body
+pub struct MethodFrames {
+    pub initial: Frame,
+    // explicit frames keyed by bytecode offset, sorted
+    pub frames: Vec<(usize, Frame)>,
+}
+
+// infer the frame at every branch target and handler of a method, like the verifier would.
+// unreachable code is replaced by nop ... athrow and removed from exception handler ranges,
+// max_stack and max_locals are updated to the computed values.
+pub fn compute(
+    constant_pool: &ConstantPool,
+    this_class: &str,
+    access_flags: u16,
+    name: &str,
+    method_descriptor: &str,
+    code: &mut CodeAttribute,
+    hierarchy: &mut dyn Hierarchy,
+) -> Result<MethodFrames, crate::error::Error> {
+    let insns = instructions(&code.code)?;
+    let mut index_at = vec![usize::MAX; code.code.len() + 1];
+    for (i, insn) in insns.iter().enumerate() {
+        index_at[insn.offset] = i;
+    }
+    index_at[code.code.len()] = insns.len();
+    let index_of = |offset: usize| -> Result<usize, crate::error::Error> {
+        match index_at.get(offset) {
+            Some(&index) if index != usize::MAX => Ok(index),
+            _ => Err(crate::error::Error::ClassFormat(format!(
+                "offset {offset} is not an instruction boundary"
+            ))),
+        }
+    };
+
+    // handler index ranges and catch types
+    let mut handlers = Vec::with_capacity(code.exception_table.len());
+    for handler in &code.exception_table {
+        let start = index_of(handler.start_pc as usize)?;
+        let end = index_of(handler.end_pc as usize)?;
+        let target = index_of(handler.handler_pc as usize)?;
+        if start >= end || target >= insns.len() {
+            return Err(crate::error::Error::ClassFormat(format!(
+                "invalid exception handler range {}..{}",
+                handler.start_pc, handler.end_pc
+            )));
+        }
+        let catch_type = match handler.catch_type {
+            0 => THROWABLE.to_string(),
+            index => constant_pool.class_name(index)?,
+        };
+        handlers.push((start, end, target, VerificationType::Object(catch_type)));
+    }
+
+    let initial = initial_frame(this_class, access_flags, name, method_descriptor)?;
+    let mut analyzer = Analyzer {
+        constant_pool,
+        code: &code.code,
+        this_class,
+        hierarchy,
+        common_supers: HashMap::new(),
+        max_stack: 0,
+        max_locals: initial.locals.len().max(code.max_locals as usize),
+    };
+
+    let mut frames_in: Vec<Option<Frame>> = vec![None; insns.len()];
+    let mut queued = vec![false; insns.len()];
+    let mut worklist = vec![0];
+    frames_in[0] = Some(initial.clone());
+    queued[0] = true;
+
+    while let Some(i) = worklist.pop() {
+        queued[i] = false;
+        let insn = insns[i];
+        let Some(before) = frames_in[i].clone() else {
+            continue;
+        };
+        let mut after = before.clone();
+        analyzer
+            .execute(&insn, &mut after)
+            .map_err(|e| at_offset(e, insn.offset))?;
+
+        let mut incoming = Vec::new();
+        for (start, end, target, catch_type) in &handlers {
+            if (*start..*end).contains(&i) {
+                incoming.push((*target, before.locals.clone(), catch_type.clone()));
+                if is_store(insn.opcode) {
+                    incoming.push((*target, after.locals.clone(), catch_type.clone()));
+                }
+            }
+        }
+        for (target, locals, catch_type) in incoming {
+            let frame = Frame {
+                locals,
+                stack: vec![catch_type],
+            };
+            analyzer.max_stack = analyzer.max_stack.max(1);
+            analyzer.merge_into(&mut frames_in, &mut worklist, &mut queued, target, frame)?;
+        }
+
+        let mut successors = Vec::new();
+        if !opcode::is_unconditional(insn.opcode) {
+            if i + 1 >= insns.len() {
+                return Err(crate::error::Error::ClassFormat(
+                    "execution falls off the end of the code".to_string(),
+                ));
+            }
+            successors.push(i + 1);
+        }
+        for target in insn.branch_targets(&code.code)? {
+            successors.push(index_of(target)?);
+        }
+        for successor in successors {
+            analyzer.merge_into(
+                &mut frames_in,
+                &mut worklist,
+                &mut queued,
+                successor,
+                after.clone(),
+            )?;
+        }
+    }
+
+    // frames are required at every reachable jump target and handler
+    let mut frame_at = vec![false; insns.len()];
+    for (i, insn) in insns.iter().enumerate() {
+        if frames_in[i].is_none() {
+            continue;
+        }
+        for target in insn.branch_targets(&code.code)? {
+            frame_at[index_of(target)?] = true;
+        }
+    }
+    for (_, _, target, _) in &handlers {
+        if frames_in[*target].is_some() {
+            frame_at[*target] = true;
+        }
+    }
+
+    let max_stack = analyzer.max_stack;
+    let max_locals = analyzer.max_locals;
+
+    // replace unreachable code with nop ... athrow so it still verifies
+    let mut dead_ranges = Vec::new();
+    let mut frames = Vec::new();
+    let mut i = 0;
+    while i < insns.len() {
+        if let Some(frame) = &frames_in[i] {
+            if frame_at[i] {
+                frames.push((insns[i].offset, frame.clone()));
+            }
+            i += 1;
+            continue;
+        }
+
+        let start = insns[i].offset;
+        while i < insns.len() && frames_in[i].is_none() {
+            i += 1;
+        }
+        let end = insns.get(i).map_or(code.code.len(), |insn| insn.offset);
+        code.code[start..end - 1].fill(opcode::NOP);
+        code.code[end - 1] = opcode::ATHROW;
+        dead_ranges.push((start, end));
+        frames.push((
+            start,
+            Frame {
+                locals: vec![],
+                stack: vec![VerificationType::Object(THROWABLE.to_string())],
+            },
+        ));
+    }
+
+    if !dead_ranges.is_empty() {
+        code.exception_table = split_handlers(&code.exception_table, &dead_ranges);
+    }
+
+    code.max_stack = max_stack.max(if dead_ranges.is_empty() { 0 } else { 1 }) as u16;
+    code.max_locals = max_locals as u16;
+
+    Ok(MethodFrames { initial, frames })
+}
+
+fn at_offset(error: crate::error::Error, offset: usize) -> crate::error::Error {
+    match error {
+        crate::error::Error::ClassFormat(message) => {
+            crate::error::Error::ClassFormat(format!("{message} at offset {offset}"))
+        }
+        other => other,
+    }
+}
+
+fn is_store(opcode: u8) -> bool {
+    (opcode::ISTORE..=78).contains(&opcode)
+}
+
+// remove dead code ranges from the exception table, splitting handlers where needed
+fn split_handlers(
+    exception_table: &[ExceptionHandler],
+    dead_ranges: &[(usize, usize)],
+) -> Vec<ExceptionHandler> {
+    let mut result = Vec::new();
+    for handler in exception_table {
+        let mut pieces = vec![(handler.start_pc as usize, handler.end_pc as usize)];
+        for &(dead_start, dead_end) in dead_ranges {
+            pieces = pieces
+                .into_iter()
+                .flat_map(|(start, end)| [(start, end.min(dead_start)), (start.max(dead_end), end)])
+                .filter(|(start, end)| start < end)
+                .collect();
+        }
+        for (start, end) in pieces {
+            result.push(ExceptionHandler {
+                start_pc: start as u16,
+                end_pc: end as u16,
+                ..handler.clone()
+            });
+        }
+    }
+    result
+}
+
+struct Analyzer<'a> {
+    constant_pool: &'a ConstantPool,
+    code: &'a [u8],
+    this_class: &'a str,
+    hierarchy: &'a mut dyn Hierarchy,
+    common_supers: HashMap<(String, String), String>,
+    max_stack: usize,
+    max_locals: usize,
+}
+
+impl Analyzer<'_> {
+    fn merge_into(
+        &mut self,
+        frames_in: &mut [Option<Frame>],
+        worklist: &mut Vec<usize>,
+        queued: &mut [bool],
+        target: usize,
+        frame: Frame,
+    ) -> Result<(), crate::error::Error> {
+        let merged = match &frames_in[target] {
+            None => frame,
+            Some(existing) => {
+                let merged = self.merge(existing, &frame)?;
+                if merged == *existing {
+                    return Ok(());
+                }
+                merged
+            }
+        };
+
+        frames_in[target] = Some(merged);
+        if !queued[target] {
+            queued[target] = true;
+            worklist.push(target);
+        }
+        Ok(())
+    }
+
+    fn merge(&mut self, current: &Frame, incoming: &Frame) -> Result<Frame, crate::error::Error> {
+        if current.stack.len() != incoming.stack.len() {
+            return Err(crate::error::Error::ClassFormat(format!(
+                "inconsistent stack height {} vs {}",
+                current.stack.len(),
+                incoming.stack.len()
+            )));
+        }
+
+        let len = current.locals.len().max(incoming.locals.len());
+        let mut locals = Vec::with_capacity(len);
+        for i in 0..len {
+            let a = current.locals.get(i).unwrap_or(&VerificationType::Top);
+            let b = incoming.locals.get(i).unwrap_or(&VerificationType::Top);
+            locals.push(self.merge_types(a, b)?);
+        }
+        // a wide value whose second half got merged away is no longer usable
+        for i in 0..len {
+            if locals[i].is_wide() && locals.get(i + 1) != Some(&VerificationType::Top) {
+                locals[i] = VerificationType::Top;
+            }
+        }
+
+        let mut stack = Vec::with_capacity(current.stack.len());
+        for (a, b) in current.stack.iter().zip(&incoming.stack) {
+            let merged = self.merge_types(a, b)?;
+            if merged == VerificationType::Top && *a != VerificationType::Top {
+                return Err(crate::error::Error::ClassFormat(format!(
+                    "incompatible stack values {a:?} and {b:?}"
+                )));
+            }
+            stack.push(merged);
+        }
+
+        Ok(Frame { locals, stack })
+    }
+
+    fn merge_types(
+        &mut self,
+        a: &VerificationType,
+        b: &VerificationType,
+    ) -> Result<VerificationType, crate::error::Error> {
+        use VerificationType::*;
+
+        let merged = match (a, b) {
+            _ if a == b => a.clone(),
+            (Null, Object(_)) => b.clone(),
+            (Object(_), Null) => a.clone(),
+            (Object(x), Object(y)) => Object(self.common_super(x, y)?),
+            _ => Top,
+        };
+
+        Ok(merged)
+    }
+
+    fn common_super(&mut self, a: &str, b: &str) -> Result<String, crate::error::Error> {
+        if a == b {
+            return Ok(a.to_string());
+        }
+        let key = (a.to_string(), b.to_string());
+        if let Some(found) = self.common_supers.get(&key) {
+            return Ok(found.clone());
+        }
+
+        let found = if a.starts_with('[') || b.starts_with('[') {
+            self.common_array_super(a, b)?
+        } else if self.hierarchy.is_interface(a)? || self.hierarchy.is_interface(b)? {
+            OBJECT.to_string()
+        } else {
+            let mut supers_of_a = vec![a.to_string()];
+            while let Some(next) = self.hierarchy.super_class(supers_of_a.last().unwrap())? {
+                supers_of_a.push(next);
+            }
+
+            let mut current = Some(b.to_string());
+            loop {
+                match current {
+                    Some(name) if supers_of_a.contains(&name) => break name,
+                    Some(name) => current = self.hierarchy.super_class(&name)?,
+                    None => break OBJECT.to_string(),
+                }
+            }
+        };
+
+        self.common_supers.insert(key, found.clone());
+        Ok(found)
+    }
+
+    fn common_array_super(&mut self, a: &str, b: &str) -> Result<String, crate::error::Error> {
+        let (Some(element_a), Some(element_b)) = (a.strip_prefix('['), b.strip_prefix('[')) else {
+            return Ok(OBJECT.to_string());
+        };
+
+        let as_class = |element: &str| -> Option<String> {
+            if element.starts_with('[') {
+                Some(element.to_string())
+            } else {
+                descriptor::referenced_class(element)
+                    .filter(|_| element.starts_with('L'))
+                    .map(str::to_string)
+            }
+        };
+
+        match (as_class(element_a), as_class(element_b)) {
+            (Some(x), Some(y)) => {
+                let common = self.common_super(&x, &y)?;
+                if common.starts_with('[') {
+                    Ok(format!("[{common}"))
+                } else {
+                    Ok(format!("[L{common};"))
+                }
+            }
+            _ => Ok(OBJECT.to_string()),
+        }
+    }
+
+    fn execute(
+        &mut self,
+        insn: &Instruction,
+        frame: &mut Frame,
+    ) -> Result<(), crate::error::Error> {
+        use VerificationType::*;
+
+        let code = self.code;
+        let op = insn.opcode;
+        match op {
+            opcode::NOP => {}
+            opcode::ACONST_NULL => frame.push(Null),
+            2..=8 | opcode::BIPUSH | opcode::SIPUSH => frame.push(Integer),
+            9 | 10 => frame.push(Long),
+            11..=13 => frame.push(Float),
+            14 | 15 => frame.push(Double),
+            opcode::LDC => self.ldc(code[insn.offset + 1] as u16, frame)?,
+            opcode::LDC_W | opcode::LDC2_W => self.ldc(insn.u16_operand(code)?, frame)?,
+            opcode::ILOAD..=opcode::ALOAD => {
+                let index = insn.local_index(code)? as usize;
+                self.load(frame, index, op - opcode::ILOAD)?;
+            }
+            26..=45 => self.load(frame, ((op - 26) % 4) as usize, (op - 26) / 4)?,
+            46 | 51..=53 => {
+                frame.pop(2)?;
+                frame.push(Integer);
+            }
+            47 => {
+                frame.pop(2)?;
+                frame.push(Long);
+            }
+            48 => {
+                frame.pop(2)?;
+                frame.push(Float);
+            }
+            49 => {
+                frame.pop(2)?;
+                frame.push(Double);
+            }
+            50 => {
+                frame.pop(1)?;
+                let array = frame.pop_reference()?;
+                let element = match array {
+                    Object(descriptor) if descriptor.starts_with('[') => {
+                        VerificationType::from_descriptor(&descriptor[1..])?
+                    }
+                    Null => Null,
+                    other => {
+                        return Err(crate::error::Error::ClassFormat(format!(
+                            "aaload from non array {other:?}"
+                        )));
+                    }
+                };
+                frame.push(element);
+            }
+            opcode::ISTORE..=opcode::ASTORE => {
+                let index = insn.local_index(code)? as usize;
+                self.store(frame, index, op - opcode::ISTORE)?;
+            }
+            59..=78 => self.store(frame, ((op - 59) % 4) as usize, (op - 59) / 4)?,
+            79 | 81 | 83..=86 => frame.pop(3)?,
+            80 | 82 => frame.pop(4)?,
+            87 => frame.pop(1)?,
+            88 => frame.pop(2)?,
+            89 => {
+                let words = frame.pop_words(1)?;
+                self.push_words(frame, &[&words, &words]);
+            }
+            90 => {
+                let words = frame.pop_words(2)?;
+                self.push_words(frame, &[&words[1..], &words]);
+            }
+            91 => {
+                let words = frame.pop_words(3)?;
+                self.push_words(frame, &[&words[2..], &words]);
+            }
+            92 => {
+                let words = frame.pop_words(2)?;
+                self.push_words(frame, &[&words, &words]);
+            }
+            93 => {
+                let words = frame.pop_words(3)?;
+                self.push_words(frame, &[&words[1..], &words]);
+            }
+            94 => {
+                let words = frame.pop_words(4)?;
+                self.push_words(frame, &[&words[2..], &words]);
+            }
+            95 => {
+                let words = frame.pop_words(2)?;
+                self.push_words(frame, &[&words[1..], &words[..1]]);
+            }
+            96..=115 => match (op - 96) % 4 {
+                0 => binary(frame, 2, Integer)?,
+                1 => binary(frame, 4, Long)?,
+                2 => binary(frame, 2, Float)?,
+                _ => binary(frame, 4, Double)?,
+            },
+            116..=119 => match op - 116 {
+                0 => binary(frame, 1, Integer)?,
+                1 => binary(frame, 2, Long)?,
+                2 => binary(frame, 1, Float)?,
+                _ => binary(frame, 2, Double)?,
+            },
+            120..=131 => {
+                let long = op % 2 == 1;
+                let shift = op <= 125;
+                match (long, shift) {
+                    (false, _) => binary(frame, 2, Integer)?,
+                    (true, true) => binary(frame, 3, Long)?,
+                    (true, false) => binary(frame, 4, Long)?,
+                }
+            }
+            opcode::IINC => {}
+            133 => binary(frame, 1, Long)?,
+            134 => binary(frame, 1, Float)?,
+            135 => binary(frame, 1, Double)?,
+            136 => binary(frame, 2, Integer)?,
+            137 => binary(frame, 2, Float)?,
+            138 => binary(frame, 2, Double)?,
+            139 => binary(frame, 1, Integer)?,
+            140 => binary(frame, 1, Long)?,
+            141 => binary(frame, 1, Double)?,
+            142 => binary(frame, 2, Integer)?,
+            143 => binary(frame, 2, Long)?,
+            144 => binary(frame, 2, Float)?,
+            145..=147 => binary(frame, 1, Integer)?,
+            148 | 151 | 152 => binary(frame, 4, Integer)?,
+            149 | 150 => binary(frame, 2, Integer)?,
+            153..=158 | opcode::IFNULL | opcode::IFNONNULL => frame.pop(1)?,
+            159..=opcode::IF_ACMPNE => frame.pop(2)?,
+            opcode::GOTO | opcode::GOTO_W => {}
+            opcode::JSR | opcode::JSR_W | opcode::RET => {
+                return Err(crate::error::Error::ClassFormat(
+                    "subroutines are not supported".to_string(),
+                ));
+            }
+            opcode::TABLESWITCH | opcode::LOOKUPSWITCH => frame.pop(1)?,
+            172 | 174 | 176 => frame.pop(1)?,
+            173 | 175 => frame.pop(2)?,
+            opcode::RETURN => {}
+            opcode::GETSTATIC..=opcode::PUTFIELD => {
+                let (_, _, field_descriptor) =
+                    self.constant_pool.member_ref(insn.u16_operand(code)?)?;
+                let size = descriptor::size(&field_descriptor);
+                match op {
+                    opcode::GETSTATIC => {
+                        frame.push(VerificationType::from_descriptor(&field_descriptor)?)
+                    }
+                    opcode::PUTSTATIC => frame.pop(size)?,
+                    opcode::GETFIELD => {
+                        frame.pop(1)?;
+                        frame.push(VerificationType::from_descriptor(&field_descriptor)?);
+                    }
+                    _ => frame.pop(size + 1)?,
+                }
+            }
+            opcode::INVOKEVIRTUAL..=opcode::INVOKEDYNAMIC => self.invoke(insn, frame)?,
+            opcode::NEW => frame.push(Uninitialized(insn.offset as u16)),
+            opcode::NEWARRAY => {
+                frame.pop(1)?;
+                let element = match code[insn.offset + 1] {
+                    4 => 'Z',
+                    5 => 'C',
+                    6 => 'F',
+                    7 => 'D',
+                    8 => 'B',
+                    9 => 'S',
+                    10 => 'I',
+                    11 => 'J',
+                    other => {
+                        return Err(crate::error::Error::ClassFormat(format!(
+                            "invalid newarray type {other}"
+                        )));
+                    }
+                };
+                frame.push(Object(format!("[{element}")));
+            }
+            opcode::ANEWARRAY => {
+                frame.pop(1)?;
+                let class = self.constant_pool.class_name(insn.u16_operand(code)?)?;
+                if class.starts_with('[') {
+                    frame.push(Object(format!("[{class}")));
+                } else {
+                    frame.push(Object(format!("[L{class};")));
+                }
+            }
+            190 => binary(frame, 1, Integer)?,
+            opcode::ATHROW => frame.pop(1)?,
+            opcode::CHECKCAST => {
+                frame.pop(1)?;
+                let class = self.constant_pool.class_name(insn.u16_operand(code)?)?;
+                frame.push(Object(class));
+            }
+            opcode::INSTANCEOF => binary(frame, 1, Integer)?,
+            194 | 195 => frame.pop(1)?,
+            opcode::MULTIANEWARRAY => {
+                frame.pop(code[insn.offset + 3] as usize)?;
+                let class = self.constant_pool.class_name(insn.u16_operand(code)?)?;
+                frame.push(Object(class));
+            }
+            other => {
+                return Err(crate::error::Error::ClassFormat(format!(
+                    "unexpected opcode {other}"
+                )));
+            }
+        }
+
+        self.max_stack = self.max_stack.max(frame.stack.len());
+        self.max_locals = self.max_locals.max(frame.locals.len());
+        Ok(())
+    }
+
+    fn push_words(&self, frame: &mut Frame, parts: &[&[VerificationType]]) {
+        for part in parts {
+            frame.stack.extend_from_slice(part);
+        }
+    }
+
+    fn ldc(&mut self, index: u16, frame: &mut Frame) -> Result<(), crate::error::Error> {
+        use VerificationType::*;
+
+        let value = match self.constant_pool.get(index)? {
+            Constant::Integer(_) => Integer,
+            Constant::Float(_) => Float,
+            Constant::Long(_) => Long,
+            Constant::Double(_) => Double,
+            Constant::String(_) => Object("java/lang/String".to_string()),
+            Constant::Class(_) => Object("java/lang/Class".to_string()),
+            Constant::MethodType(_) => Object("java/lang/invoke/MethodType".to_string()),
+            Constant::MethodHandle(..) => Object("java/lang/invoke/MethodHandle".to_string()),
+            Constant::Dynamic(_, nat_index) => {
+                let (_, field_descriptor) = self.constant_pool.name_and_type(*nat_index)?;
+                VerificationType::from_descriptor(&field_descriptor)?
+            }
+            other => {
+                return Err(crate::error::Error::ClassFormat(format!(
+                    "ldc of unloadable constant with tag {}",
+                    other.tag()
+                )));
+            }
+        };
+
+        frame.push(value);
+        Ok(())
+    }
+
+    // kind is 0..=4 for int, long, float, double and reference
+    fn load(&self, frame: &mut Frame, index: usize, kind: u8) -> Result<(), crate::error::Error> {
+        use VerificationType::*;
+
+        let value = match kind {
+            0 => Integer,
+            1 => Long,
+            2 => Float,
+            3 => Double,
+            _ => frame.get_local(index)?,
+        };
+        if kind != 4 {
+            frame.get_local(index)?;
+        }
+        frame.push(value);
+        Ok(())
+    }
+
+    fn store(&self, frame: &mut Frame, index: usize, kind: u8) -> Result<(), crate::error::Error> {
+        use VerificationType::*;
+
+        let value = match kind {
+            0 => Integer,
+            1 => Long,
+            2 => Float,
+            3 => Double,
+            _ => frame.pop_reference()?,
+        };
+        if kind != 4 {
+            frame.pop(if value.is_wide() { 2 } else { 1 })?;
+        }
+        frame.set_local(index, value);
+        Ok(())
+    }
+
+    fn invoke(&mut self, insn: &Instruction, frame: &mut Frame) -> Result<(), crate::error::Error> {
+        let index = insn.u16_operand(self.code)?;
+        let (name, method_descriptor) = match self.constant_pool.get(index)? {
+            Constant::InvokeDynamic(_, nat_index) => {
+                self.constant_pool.name_and_type(*nat_index)?
+            }
+            _ => {
+                let (_, name, method_descriptor) = self.constant_pool.member_ref(index)?;
+                (name, method_descriptor)
+            }
+        };
+
+        let (parameters, return_descriptor) = descriptor::parse_method(&method_descriptor)?;
+        let words = parameters.iter().map(|p| descriptor::size(p)).sum();
+        frame.pop(words)?;
+
+        if insn.opcode != opcode::INVOKESTATIC && insn.opcode != opcode::INVOKEDYNAMIC {
+            let receiver = frame.pop_reference()?;
+            if insn.opcode == opcode::INVOKESPECIAL && name == "<init>" {
+                let initialized = match &receiver {
+                    VerificationType::UninitializedThis => {
+                        Some(VerificationType::Object(self.this_class.to_string()))
+                    }
+                    VerificationType::Uninitialized(offset) => {
+                        let new_insn = *offset as usize;
+                        if self.code.get(new_insn) != Some(&opcode::NEW) {
+                            return Err(crate::error::Error::ClassFormat(format!(
+                                "uninitialized value does not come from new at {new_insn}"
+                            )));
+                        }
+                        let class_index = super::reader::read_u16(self.code, new_insn + 1)?;
+                        Some(VerificationType::Object(
+                            self.constant_pool.class_name(class_index)?,
+                        ))
+                    }
+                    _ => None,
+                };
+                if let Some(initialized) = initialized {
+                    frame.replace(&receiver, &initialized);
+                }
+            }
+        }
+
+        if return_descriptor != "V" {
+            frame.push(VerificationType::from_descriptor(&return_descriptor)?);
+        }
+        Ok(())
+    }
+}
+
+// pop the operands of an instruction and push its single result
+fn binary(
+    frame: &mut Frame,
+    words: usize,
+    result: VerificationType,
+) -> Result<(), crate::error::Error> {
+    frame.pop(words)?;
+    frame.push(result);
+    Ok(())
+}
+
+// encode explicit frames as the body of a StackMapTable attribute
+pub fn encode_stack_map_table(
+    initial: &Frame,
+    frames: &[(usize, Frame)],
+    constant_pool: &mut ConstantPool,
+) -> Result<Vec<u8>, crate::error::Error> {
+    let mut out = Vec::new();
+    put_u16(&mut out, frames.len() as u16);
+
+    let mut previous_locals = initial.compact_locals();
+    let mut previous_offset = None;
+    for (offset, frame) in frames {
+        let delta = match previous_offset {
+            None => *offset,
+            Some(previous) => offset - previous - 1,
+        } as u16;
+        previous_offset = Some(*offset);
+
+        let locals = frame.compact_locals();
+        let stack = frame.compact_stack();
+        let appended = locals.len() as isize - previous_locals.len() as isize;
+
+        if stack.is_empty() && locals == previous_locals {
+            if delta < 64 {
+                out.push(delta as u8);
+            } else {
+                out.push(251);
+                put_u16(&mut out, delta);
+            }
+        } else if stack.len() == 1 && locals == previous_locals {
+            if delta < 64 {
+                out.push(64 + delta as u8);
+            } else {
+                out.push(247);
+                put_u16(&mut out, delta);
+            }
+            write_type(&mut out, &stack[0], constant_pool)?;
+        } else if stack.is_empty()
+            && (1..=3).contains(&appended)
+            && locals[..previous_locals.len()] == previous_locals[..]
+        {
+            out.push((251 + appended) as u8);
+            put_u16(&mut out, delta);
+            for value in &locals[previous_locals.len()..] {
+                write_type(&mut out, value, constant_pool)?;
+            }
+        } else if stack.is_empty()
+            && (-3..=-1).contains(&appended)
+            && previous_locals[..locals.len()] == locals[..]
+        {
+            out.push((251 + appended) as u8);
+            put_u16(&mut out, delta);
+        } else {
+            out.push(255);
+            put_u16(&mut out, delta);
+            put_u16(&mut out, locals.len() as u16);
+            for value in &locals {
+                write_type(&mut out, value, constant_pool)?;
+            }
+            put_u16(&mut out, stack.len() as u16);
+            for value in &stack {
+                write_type(&mut out, value, constant_pool)?;
+            }
+        }
+
+        previous_locals = locals;
+    }
+
+    Ok(out)
+}
+
+fn write_type(
+    out: &mut Vec<u8>,
+    value: &VerificationType,
+    constant_pool: &mut ConstantPool,
+) -> Result<(), crate::error::Error> {
+    match value {
+        VerificationType::Top => out.push(0),
+        VerificationType::Integer => out.push(1),
+        VerificationType::Float => out.push(2),
+        VerificationType::Double => out.push(3),
+        VerificationType::Long => out.push(4),
+        VerificationType::Null => out.push(5),
+        VerificationType::UninitializedThis => out.push(6),
+        VerificationType::Object(name) => {
+            out.push(7);
+            put_u16(out, constant_pool.add_class(name)?);
+        }
+        VerificationType::Uninitialized(offset) => {
+            out.push(8);
+            put_u16(out, *offset);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct NoHierarchy;
+
+    impl Hierarchy for NoHierarchy {
+        fn super_class(&mut self, _: &str) -> Result<Option<String>, crate::error::Error> {
+            Ok(Some(OBJECT.to_string()))
+        }
+
+        fn is_interface(&mut self, _: &str) -> Result<bool, crate::error::Error> {
+            Ok(false)
+        }
+    }
+
+    fn code(bytes: &[u8]) -> CodeAttribute {
+        CodeAttribute {
+            max_stack: 0,
+            max_locals: 0,
+            code: bytes.to_vec(),
+            exception_table: vec![],
+            attributes: vec![],
+        }
+    }
+
+    #[test]
+    fn test_branch_target_frame() {
+        // static int f(int x) { return x == 0 ? 1 : 0; } with an extra long local
+        let mut code = code(&[
+            0x09, // lconst_0
+            0x40, // lstore_1
+            0x1a, // iload_0
+            0x99, 0x00, 0x05, // ifeq +5
+            0x04, // iconst_1
+            0xac, // ireturn
+            0x03, // iconst_0
+            0xac, // ireturn
+        ]);
+        let computed = compute(
+            &ConstantPool::new(),
+            "Test",
+            ACC_STATIC,
+            "f",
+            "(I)I",
+            &mut code,
+            &mut NoHierarchy,
+        )
+        .unwrap();
+
+        assert_eq!(code.max_stack, 2);
+        assert_eq!(code.max_locals, 3);
+        assert_eq!(computed.frames.len(), 1);
+        let (offset, frame) = &computed.frames[0];
+        assert_eq!(*offset, 8);
+        assert_eq!(
+            frame.compact_locals(),
+            vec![VerificationType::Integer, VerificationType::Long]
+        );
+        assert!(frame.stack.is_empty());
+
+        // append_frame with one local, offset delta 8
+        let table = encode_stack_map_table(
+            &computed.initial,
+            &computed.frames,
+            &mut ConstantPool::new(),
+        )
+        .unwrap();
+        assert_eq!(table, vec![0, 1, 252, 0, 8, 4]);
+    }
+
+    #[test]
+    fn test_dead_code_replaced() {
+        // goto over an unreachable iconst_0 / pop pair
+        let mut code = code(&[
+            0xa7, 0x00, 0x05, // goto +5
+            0x03, // iconst_0
+            0x57, // pop
+            0xb1, // return
+        ]);
+        let computed = compute(
+            &ConstantPool::new(),
+            "Test",
+            ACC_STATIC,
+            "f",
+            "()V",
+            &mut code,
+            &mut NoHierarchy,
+        )
+        .unwrap();
+
+        assert_eq!(
+            code.code,
+            vec![0xa7, 0x00, 0x05, opcode::NOP, opcode::ATHROW, 0xb1]
+        );
+        let offsets = computed
+            .frames
+            .iter()
+            .map(|(offset, _)| *offset)
+            .collect::<Vec<_>>();
+        assert_eq!(offsets, vec![3, 5]);
+        assert_eq!(
+            computed.frames[0].1.stack,
+            vec![VerificationType::Object(THROWABLE.to_string())]
+        );
+    }
+}
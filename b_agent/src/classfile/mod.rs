@@ -0,0 +1,296 @@
+use reader::{ByteReader, put_u16, put_u32};
+
+pub mod code;
+pub mod constant_pool;
+pub mod dependencies;
+pub mod descriptor;
+pub mod disasm;
+pub mod frames;
+pub mod normalize;
+pub mod opcode;
+pub mod reader;
+pub mod validate;
+pub mod writer;
+
+pub use constant_pool::{Constant, ConstantPool};
+
+pub const MAGIC: u32 = 0xCAFEBABE;
+
+pub const ACC_PUBLIC: u16 = 0x0001;
+pub const ACC_PRIVATE: u16 = 0x0002;
+pub const ACC_PROTECTED: u16 = 0x0004;
+pub const ACC_STATIC: u16 = 0x0008;
+pub const ACC_FINAL: u16 = 0x0010;
+pub const ACC_SYNCHRONIZED: u16 = 0x0020;
+pub const ACC_BRIDGE: u16 = 0x0040;
+pub const ACC_NATIVE: u16 = 0x0100;
+pub const ACC_INTERFACE: u16 = 0x0200;
+pub const ACC_ABSTRACT: u16 = 0x0400;
+pub const ACC_SYNTHETIC: u16 = 0x1000;
+pub const ACC_ANNOTATION: u16 = 0x2000;
+pub const ACC_ENUM: u16 = 0x4000;
+pub const ACC_MODULE: u16 = 0x8000;
+
+// first class file version that carries StackMapTable attributes
+pub const STACK_MAP_VERSION: u16 = 50;
+
+// attribute kept as its raw bytes, decoded on demand by whoever needs it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Attribute {
+    pub name_index: u16,
+    pub info: Vec<u8>,
+}
+
+impl Attribute {
+    pub fn parse_all(reader: &mut ByteReader) -> Result<Vec<Self>, crate::error::Error> {
+        (0..reader.u16()?)
+            .map(|_| {
+                let name_index = reader.u16()?;
+                let len = reader.u32()? as usize;
+                Ok(Attribute {
+                    name_index,
+                    info: reader.bytes(len)?.to_vec(),
+                })
+            })
+            .collect()
+    }
+
+    pub fn write_all(attributes: &[Self], out: &mut Vec<u8>) {
+        put_u16(out, attributes.len() as u16);
+        for attribute in attributes {
+            put_u16(out, attribute.name_index);
+            put_u32(out, attribute.info.len() as u32);
+            out.extend_from_slice(&attribute.info);
+        }
+    }
+}
+
+// field or method
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Member {
+    pub access_flags: u16,
+    pub name_index: u16,
+    pub descriptor_index: u16,
+    pub attributes: Vec<Attribute>,
+}
+
+impl Member {
+    fn parse(reader: &mut ByteReader) -> Result<Self, crate::error::Error> {
+        Ok(Member {
+            access_flags: reader.u16()?,
+            name_index: reader.u16()?,
+            descriptor_index: reader.u16()?,
+            attributes: Attribute::parse_all(reader)?,
+        })
+    }
+
+    fn write(&self, out: &mut Vec<u8>) {
+        put_u16(out, self.access_flags);
+        put_u16(out, self.name_index);
+        put_u16(out, self.descriptor_index);
+        Attribute::write_all(&self.attributes, out);
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClassFile {
+    pub minor_version: u16,
+    pub major_version: u16,
+    pub constant_pool: ConstantPool,
+    pub access_flags: u16,
+    pub this_class: u16,
+    pub super_class: u16,
+    pub interfaces: Vec<u16>,
+    pub fields: Vec<Member>,
+    pub methods: Vec<Member>,
+    pub attributes: Vec<Attribute>,
+}
+
+impl ClassFile {
+    pub fn parse(data: &[u8]) -> Result<Self, crate::error::Error> {
+        let mut reader = ByteReader::new(data);
+        let magic = reader.u32()?;
+        if magic != MAGIC {
+            return Err(crate::error::Error::ClassFormat(format!(
+                "bad magic {magic:#010x}"
+            )));
+        }
+
+        let minor_version = reader.u16()?;
+        let major_version = reader.u16()?;
+        let constant_pool = ConstantPool::parse(&mut reader)?;
+        let access_flags = reader.u16()?;
+        let this_class = reader.u16()?;
+        let super_class = reader.u16()?;
+        let interfaces = (0..reader.u16()?)
+            .map(|_| reader.u16())
+            .collect::<Result<Vec<_>, _>>()?;
+        let fields = (0..reader.u16()?)
+            .map(|_| Member::parse(&mut reader))
+            .collect::<Result<Vec<_>, _>>()?;
+        let methods = (0..reader.u16()?)
+            .map(|_| Member::parse(&mut reader))
+            .collect::<Result<Vec<_>, _>>()?;
+        let attributes = Attribute::parse_all(&mut reader)?;
+
+        if reader.remaining() != 0 {
+            return Err(crate::error::Error::ClassFormat(format!(
+                "{} trailing bytes after the class file",
+                reader.remaining()
+            )));
+        }
+
+        Ok(ClassFile {
+            minor_version,
+            major_version,
+            constant_pool,
+            access_flags,
+            this_class,
+            super_class,
+            interfaces,
+            fields,
+            methods,
+            attributes,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        put_u32(&mut out, MAGIC);
+        put_u16(&mut out, self.minor_version);
+        put_u16(&mut out, self.major_version);
+        self.constant_pool.write(&mut out);
+        put_u16(&mut out, self.access_flags);
+        put_u16(&mut out, self.this_class);
+        put_u16(&mut out, self.super_class);
+        put_u16(&mut out, self.interfaces.len() as u16);
+        for interface in &self.interfaces {
+            put_u16(&mut out, *interface);
+        }
+        put_u16(&mut out, self.fields.len() as u16);
+        for field in &self.fields {
+            field.write(&mut out);
+        }
+        put_u16(&mut out, self.methods.len() as u16);
+        for method in &self.methods {
+            method.write(&mut out);
+        }
+        Attribute::write_all(&self.attributes, &mut out);
+        out
+    }
+
+    // internal name of this class, e.g. java/lang/String
+    pub fn name(&self) -> Result<String, crate::error::Error> {
+        self.constant_pool.class_name(self.this_class)
+    }
+
+    // None for java/lang/Object and module-info
+    pub fn super_name(&self) -> Result<Option<String>, crate::error::Error> {
+        if self.super_class == 0 {
+            return Ok(None);
+        }
+
+        self.constant_pool.class_name(self.super_class).map(Some)
+    }
+
+    pub fn interface_names(&self) -> Result<Vec<String>, crate::error::Error> {
+        self.interfaces
+            .iter()
+            .map(|&index| self.constant_pool.class_name(index))
+            .collect()
+    }
+
+    pub fn is_interface(&self) -> bool {
+        self.access_flags & ACC_INTERFACE != 0
+    }
+
+    pub fn member_name(&self, member: &Member) -> Result<String, crate::error::Error> {
+        self.constant_pool.utf8(member.name_index)
+    }
+
+    pub fn member_descriptor(&self, member: &Member) -> Result<String, crate::error::Error> {
+        self.constant_pool.utf8(member.descriptor_index)
+    }
+
+    pub fn attribute_name(&self, attribute: &Attribute) -> Result<String, crate::error::Error> {
+        self.constant_pool.utf8(attribute.name_index)
+    }
+
+    // first attribute with the given name out of a list belonging to this class
+    pub fn find_attribute<'a>(
+        &self,
+        attributes: &'a [Attribute],
+        name: &str,
+    ) -> Option<&'a Attribute> {
+        attributes
+            .iter()
+            .find(|attribute| self.attribute_name(attribute).is_ok_and(|n| n == name))
+    }
+
+    pub fn code(
+        &self,
+        method: &Member,
+    ) -> Result<Option<code::CodeAttribute>, crate::error::Error> {
+        self.find_attribute(&method.attributes, "Code")
+            .map(|attribute| code::CodeAttribute::parse(&attribute.info))
+            .transpose()
+    }
+}
+
+// java.lang.String -> java/lang/String
+pub fn internal_name(class_name: &str) -> String {
+    class_name.replace('.', "/")
+}
+
+// java/lang/String -> java.lang.String
+pub fn binary_name(internal_name: &str) -> String {
+    internal_name.replace('/', ".")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        // minimal class with a wide constant, a field and an attribute
+        let mut constant_pool = ConstantPool::new();
+        let this_class = constant_pool.add_class("a/B").unwrap();
+        let super_class = constant_pool.add_class("java/lang/Object").unwrap();
+        constant_pool.add(Constant::Long(42)).unwrap();
+        let field_name = constant_pool.add_utf8("value").unwrap();
+        let field_descriptor = constant_pool.add_utf8("J").unwrap();
+        let source_file = constant_pool.add_utf8("SourceFile").unwrap();
+
+        let class_file = ClassFile {
+            minor_version: 0,
+            major_version: 52,
+            constant_pool,
+            access_flags: ACC_PUBLIC,
+            this_class,
+            super_class,
+            interfaces: vec![],
+            fields: vec![Member {
+                access_flags: ACC_PRIVATE,
+                name_index: field_name,
+                descriptor_index: field_descriptor,
+                attributes: vec![],
+            }],
+            methods: vec![],
+            attributes: vec![Attribute {
+                name_index: source_file,
+                info: vec![0, field_name as u8],
+            }],
+        };
+
+        let bytes = class_file.to_bytes();
+        let parsed = ClassFile::parse(&bytes).unwrap();
+        assert_eq!(parsed, class_file);
+        assert_eq!(parsed.name().unwrap(), "a/B");
+        assert_eq!(
+            parsed.super_name().unwrap().as_deref(),
+            Some("java/lang/Object")
+        );
+        assert!(ClassFile::parse(&bytes[..bytes.len() - 1]).is_err());
+    }
+}
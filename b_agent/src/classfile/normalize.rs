@@ -0,0 +1,679 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use super::code::{CodeAttribute, instructions};
+use super::constant_pool::{Constant, ConstantPool};
+use super::reader::ByteReader;
+use super::{Attribute, ClassFile, opcode};
+
+// attributes that only carry debugging information
+const DEBUG_ATTRIBUTES: [&str; 5] = [
+    "SourceFile",
+    "SourceDebugExtension",
+    "LineNumberTable",
+    "LocalVariableTable",
+    "LocalVariableTypeTable",
+];
+
+// rewrite a class into a canonical form: the constant pool only holds referenced entries, sorted
+// by content with duplicates merged, and every attribute table is sorted by name. two classes that
+// only differ in constant pool layout or attribute order come out byte for byte identical.
+// fails on attributes whose constant pool references are unknown, since they can't be remapped.
+pub fn normalize(
+    class_file: &ClassFile,
+    strip_debug: bool,
+) -> Result<ClassFile, crate::error::Error> {
+    let mut class_file = class_file.clone();
+    if strip_debug {
+        strip_debug_attributes(&mut class_file)?;
+    }
+
+    let slots = collect_slots(&class_file)?;
+    let mapping = canonical_order(&class_file, &slots)?;
+
+    let old_pool = std::mem::take(&mut class_file.constant_pool);
+    let mut constant_pool = ConstantPool::new();
+    let mut ordered = mapping.iter().collect::<Vec<_>>();
+    ordered.sort_by_key(|(_, new)| **new);
+    for (old, &new) in ordered {
+        // entries merged into an earlier one
+        if (new as usize) < constant_pool.count() {
+            continue;
+        }
+        let constant = old_pool.get(*old)?.map_references(|index| mapping[&index]);
+        constant_pool.push(constant)?;
+    }
+    class_file.constant_pool = constant_pool;
+
+    let map = |index: u16| if index == 0 { 0 } else { mapping[&index] };
+    class_file.this_class = map(class_file.this_class);
+    class_file.super_class = map(class_file.super_class);
+    class_file.interfaces.iter_mut().for_each(|i| *i = map(*i));
+    for member in class_file
+        .fields
+        .iter_mut()
+        .chain(class_file.methods.iter_mut())
+    {
+        member.name_index = map(member.name_index);
+        member.descriptor_index = map(member.descriptor_index);
+    }
+
+    let mut slots = slots.into_iter();
+    for_each_attribute_list(&mut class_file, |attributes| {
+        for attribute in attributes.iter_mut() {
+            attribute.name_index = map(attribute.name_index);
+            for slot in slots.next().unwrap() {
+                slot.remap(&mut attribute.info, map)?;
+            }
+        }
+        Ok(())
+    })?;
+
+    sort_attributes(&mut class_file)?;
+    Ok(class_file)
+}
+
+// location of a constant pool index inside an attribute body
+#[derive(Clone, Copy, Debug)]
+enum Slot {
+    Wide(usize),
+    // the single byte operand of ldc
+    Narrow(usize),
+}
+
+impl Slot {
+    fn read(&self, info: &[u8]) -> u16 {
+        match *self {
+            Slot::Wide(position) => u16::from_be_bytes([info[position], info[position + 1]]),
+            Slot::Narrow(position) => info[position] as u16,
+        }
+    }
+
+    fn remap(&self, info: &mut [u8], map: impl Fn(u16) -> u16) -> Result<(), crate::error::Error> {
+        let index = map(self.read(info));
+        match *self {
+            Slot::Wide(position) => {
+                info[position..position + 2].copy_from_slice(&index.to_be_bytes())
+            }
+            Slot::Narrow(position) => {
+                info[position] = u8::try_from(index).map_err(|_| {
+                    crate::error::Error::ClassFormat(format!(
+                        "ldc operand {index} does not fit in a byte"
+                    ))
+                })?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// visit the class, field, method attribute tables in a fixed order
+fn for_each_attribute_list(
+    class_file: &mut ClassFile,
+    mut visit: impl FnMut(&mut Vec<Attribute>) -> Result<(), crate::error::Error>,
+) -> Result<(), crate::error::Error> {
+    visit(&mut class_file.attributes)?;
+    for member in class_file
+        .fields
+        .iter_mut()
+        .chain(class_file.methods.iter_mut())
+    {
+        visit(&mut member.attributes)?;
+    }
+    Ok(())
+}
+
+// constant pool slots of every attribute, in for_each_attribute_list order
+fn collect_slots(class_file: &ClassFile) -> Result<Vec<Vec<Slot>>, crate::error::Error> {
+    let constant_pool = &class_file.constant_pool;
+    let lists = std::iter::once(&class_file.attributes).chain(
+        class_file
+            .fields
+            .iter()
+            .chain(class_file.methods.iter())
+            .map(|member| &member.attributes),
+    );
+
+    let mut slots = Vec::new();
+    for attributes in lists {
+        for attribute in attributes {
+            let name = constant_pool.utf8(attribute.name_index)?;
+            let mut walker = Walker {
+                constant_pool,
+                reader: ByteReader::new(&attribute.info),
+                slots: Vec::new(),
+            };
+            walker.attribute(&name, attribute.info.len())?;
+            slots.push(walker.slots);
+        }
+    }
+
+    Ok(slots)
+}
+
+// new index for every referenced constant pool entry, identical entries share one index.
+// constants loaded by ldc come first so that they still fit in its single byte operand.
+fn canonical_order(
+    class_file: &ClassFile,
+    slots: &[Vec<Slot>],
+) -> Result<HashMap<u16, u16>, crate::error::Error> {
+    let constant_pool = &class_file.constant_pool;
+    let mut roots = vec![class_file.this_class, class_file.super_class];
+    roots.extend(&class_file.interfaces);
+    for member in class_file.fields.iter().chain(class_file.methods.iter()) {
+        roots.extend([member.name_index, member.descriptor_index]);
+    }
+
+    let mut narrow = HashSet::new();
+    let mut slots = slots.iter();
+    let lists = std::iter::once(&class_file.attributes).chain(
+        class_file
+            .fields
+            .iter()
+            .chain(class_file.methods.iter())
+            .map(|member| &member.attributes),
+    );
+    for attributes in lists {
+        for attribute in attributes {
+            roots.push(attribute.name_index);
+            for slot in slots.next().unwrap() {
+                let index = slot.read(&attribute.info);
+                if matches!(slot, Slot::Narrow(_)) {
+                    narrow.insert(index);
+                }
+                roots.push(index);
+            }
+        }
+    }
+
+    // everything reachable from the class structure
+    let mut referenced = HashSet::new();
+    while let Some(index) = roots.pop() {
+        if index == 0 || !referenced.insert(index) {
+            continue;
+        }
+        roots.extend(constant_pool.get(index)?.references());
+    }
+
+    let mut keys = HashMap::new();
+    let mut groups: BTreeMap<(bool, Vec<u8>), Vec<u16>> = BTreeMap::new();
+    for &index in &referenced {
+        let key = sort_key(constant_pool, index, &mut keys, 0)?;
+        groups
+            .entry((!narrow.contains(&index), key))
+            .or_default()
+            .push(index);
+    }
+
+    let mut mapping = HashMap::new();
+    let mut by_key: HashMap<&[u8], u16> = HashMap::new();
+    let mut next = 1u32;
+    for ((_, key), indices) in &groups {
+        // an ldc constant and a plain one with the same content share the ldc slot
+        let new = match by_key.get(key.as_slice()) {
+            Some(&new) => new,
+            None => {
+                let new = u16::try_from(next).map_err(|_| {
+                    crate::error::Error::ClassFormat("constant pool is full".to_string())
+                })?;
+                next += if constant_pool.get(indices[0])?.is_wide() {
+                    2
+                } else {
+                    1
+                };
+                by_key.insert(key, new);
+                new
+            }
+        };
+        for &index in indices {
+            mapping.insert(index, new);
+        }
+    }
+
+    Ok(mapping)
+}
+
+// content of a constant with the entries it refers to inlined, so equal keys mean equal constants
+fn sort_key(
+    constant_pool: &ConstantPool,
+    index: u16,
+    keys: &mut HashMap<u16, Vec<u8>>,
+    depth: usize,
+) -> Result<Vec<u8>, crate::error::Error> {
+    if let Some(key) = keys.get(&index) {
+        return Ok(key.clone());
+    }
+    if depth > 8 {
+        return Err(crate::error::Error::ClassFormat(format!(
+            "constant pool entry {index} refers to itself"
+        )));
+    }
+
+    let constant = constant_pool.get(index)?;
+    let mut key = vec![constant.tag()];
+    match constant {
+        Constant::Utf8(bytes) => key.extend(bytes),
+        Constant::Integer(value) => key.extend(value.to_be_bytes()),
+        Constant::Float(bits) => key.extend(bits.to_be_bytes()),
+        Constant::Long(value) => key.extend(value.to_be_bytes()),
+        Constant::Double(bits) => key.extend(bits.to_be_bytes()),
+        Constant::MethodHandle(kind, _) => key.push(*kind),
+        Constant::Dynamic(bootstrap, _) | Constant::InvokeDynamic(bootstrap, _) => {
+            key.extend(bootstrap.to_be_bytes())
+        }
+        _ => {}
+    }
+    for reference in constant.references() {
+        let nested = sort_key(constant_pool, reference, keys, depth + 1)?;
+        key.extend((nested.len() as u32).to_be_bytes());
+        key.extend(nested);
+    }
+
+    keys.insert(index, key.clone());
+    Ok(key)
+}
+
+fn strip_debug_attributes(class_file: &mut ClassFile) -> Result<(), crate::error::Error> {
+    let constant_pool = class_file.constant_pool.clone();
+    let is_debug = |attribute: &Attribute| {
+        constant_pool
+            .utf8(attribute.name_index)
+            .is_ok_and(|name| DEBUG_ATTRIBUTES.contains(&name.as_str()))
+    };
+
+    for_each_attribute_list(class_file, |attributes| {
+        attributes.retain(|attribute| !is_debug(attribute));
+        for attribute in attributes.iter_mut() {
+            if constant_pool.utf8(attribute.name_index)? == "Code" {
+                let mut code = CodeAttribute::parse(&attribute.info)?;
+                code.attributes.retain(|attribute| !is_debug(attribute));
+                attribute.info = code.to_bytes();
+            }
+        }
+        Ok(())
+    })
+}
+
+// attribute order carries no meaning, sort every table by name then content
+fn sort_attributes(class_file: &mut ClassFile) -> Result<(), crate::error::Error> {
+    let constant_pool = class_file.constant_pool.clone();
+    let sort = |attributes: &mut Vec<Attribute>| -> Result<(), crate::error::Error> {
+        let mut keyed = attributes
+            .drain(..)
+            .map(|attribute| Ok((constant_pool.utf8(attribute.name_index)?, attribute)))
+            .collect::<Result<Vec<_>, crate::error::Error>>()?;
+        keyed.sort_by(|(a_name, a), (b_name, b)| {
+            a_name.cmp(b_name).then_with(|| a.info.cmp(&b.info))
+        });
+        attributes.extend(keyed.into_iter().map(|(_, attribute)| attribute));
+        Ok(())
+    };
+
+    for_each_attribute_list(class_file, |attributes| {
+        sort(attributes)?;
+        for attribute in attributes.iter_mut() {
+            if constant_pool.utf8(attribute.name_index)? == "Code" {
+                let mut code = CodeAttribute::parse(&attribute.info)?;
+                sort(&mut code.attributes)?;
+                attribute.info = code.to_bytes();
+            }
+        }
+        Ok(())
+    })
+}
+
+// finds the constant pool indices inside an attribute body
+struct Walker<'a> {
+    constant_pool: &'a ConstantPool,
+    reader: ByteReader<'a>,
+    slots: Vec<Slot>,
+}
+
+impl Walker<'_> {
+    fn index(&mut self) -> Result<(), crate::error::Error> {
+        self.slots.push(Slot::Wide(self.reader.position()));
+        self.reader.u16()?;
+        Ok(())
+    }
+
+    fn indices(&mut self) -> Result<(), crate::error::Error> {
+        for _ in 0..self.reader.u16()? {
+            self.index()?;
+        }
+        Ok(())
+    }
+
+    fn skip(&mut self, len: usize) -> Result<(), crate::error::Error> {
+        self.reader.bytes(len)?;
+        Ok(())
+    }
+
+    // nested attribute table, as found in Code and Record
+    fn attributes(&mut self) -> Result<(), crate::error::Error> {
+        for _ in 0..self.reader.u16()? {
+            let name_index = self.reader.u16()?;
+            self.slots.push(Slot::Wide(self.reader.position() - 2));
+            let name = self.constant_pool.utf8(name_index)?;
+            let len = self.reader.u32()? as usize;
+            let end = self.reader.position() + len;
+            self.attribute(&name, end)?;
+        }
+        Ok(())
+    }
+
+    fn attribute(&mut self, name: &str, end: usize) -> Result<(), crate::error::Error> {
+        match name {
+            "ConstantValue" | "SourceFile" | "Signature" | "NestHost" | "ModuleMainClass" => {
+                self.index()?
+            }
+            "EnclosingMethod" => {
+                self.index()?;
+                self.index()?;
+            }
+            "Exceptions" | "NestMembers" | "PermittedSubclasses" | "ModulePackages" => {
+                self.indices()?
+            }
+            "InnerClasses" => {
+                for _ in 0..self.reader.u16()? {
+                    self.index()?;
+                    self.index()?;
+                    self.index()?;
+                    self.skip(2)?;
+                }
+            }
+            "LocalVariableTable" | "LocalVariableTypeTable" => {
+                for _ in 0..self.reader.u16()? {
+                    self.skip(4)?;
+                    self.index()?;
+                    self.index()?;
+                    self.skip(2)?;
+                }
+            }
+            "MethodParameters" => {
+                for _ in 0..self.reader.u8()? {
+                    self.index()?;
+                    self.skip(2)?;
+                }
+            }
+            "BootstrapMethods" => {
+                for _ in 0..self.reader.u16()? {
+                    self.index()?;
+                    self.indices()?;
+                }
+            }
+            "RuntimeVisibleAnnotations" | "RuntimeInvisibleAnnotations" => {
+                for _ in 0..self.reader.u16()? {
+                    self.annotation()?;
+                }
+            }
+            "RuntimeVisibleParameterAnnotations" | "RuntimeInvisibleParameterAnnotations" => {
+                for _ in 0..self.reader.u8()? {
+                    for _ in 0..self.reader.u16()? {
+                        self.annotation()?;
+                    }
+                }
+            }
+            "RuntimeVisibleTypeAnnotations" | "RuntimeInvisibleTypeAnnotations" => {
+                for _ in 0..self.reader.u16()? {
+                    self.type_annotation()?;
+                }
+            }
+            "AnnotationDefault" => self.element_value()?,
+            "Record" => {
+                for _ in 0..self.reader.u16()? {
+                    self.index()?;
+                    self.index()?;
+                    self.attributes()?;
+                }
+            }
+            "Module" => self.module()?,
+            // jdk specific module attributes
+            "ModuleTarget" => self.index()?,
+            "ModuleResolution" => self.skip(2)?,
+            "ModuleHashes" => {
+                self.index()?;
+                for _ in 0..self.reader.u16()? {
+                    self.index()?;
+                    let len = self.reader.u16()? as usize;
+                    self.skip(len)?;
+                }
+            }
+            "Code" => self.code()?,
+            "StackMapTable" => self.stack_map_table()?,
+            "LineNumberTable" | "SourceDebugExtension" | "Deprecated" | "Synthetic" => {
+                self.skip(end - self.reader.position())?
+            }
+            _ => {
+                return Err(crate::error::Error::ClassFormat(format!(
+                    "unknown attribute {name}"
+                )));
+            }
+        }
+
+        if self.reader.position() != end {
+            return Err(crate::error::Error::ClassFormat(format!(
+                "{name} attribute has inconsistent length"
+            )));
+        }
+        Ok(())
+    }
+
+    fn code(&mut self) -> Result<(), crate::error::Error> {
+        self.skip(4)?;
+        let code_length = self.reader.u32()? as usize;
+        let start = self.reader.position();
+        let code = self.reader.bytes(code_length)?;
+        for instruction in instructions(code)? {
+            let operand = start + instruction.offset + 1;
+            match instruction.opcode {
+                opcode::LDC => self.slots.push(Slot::Narrow(operand)),
+                opcode::LDC_W
+                | opcode::LDC2_W
+                | opcode::GETSTATIC..=opcode::INVOKEDYNAMIC
+                | opcode::NEW
+                | opcode::ANEWARRAY
+                | opcode::CHECKCAST
+                | opcode::INSTANCEOF
+                | opcode::MULTIANEWARRAY => self.slots.push(Slot::Wide(operand)),
+                _ => {}
+            }
+        }
+
+        for _ in 0..self.reader.u16()? {
+            self.skip(6)?;
+            self.index()?;
+        }
+        self.attributes()
+    }
+
+    fn stack_map_table(&mut self) -> Result<(), crate::error::Error> {
+        for _ in 0..self.reader.u16()? {
+            let frame_type = self.reader.u8()?;
+            let (locals, stack) = match frame_type {
+                0..=63 => (0, 0),
+                64..=127 => (0, 1),
+                247 => {
+                    self.skip(2)?;
+                    (0, 1)
+                }
+                248..=251 => {
+                    self.skip(2)?;
+                    (0, 0)
+                }
+                252..=254 => {
+                    self.skip(2)?;
+                    (frame_type as u16 - 251, 0)
+                }
+                255 => {
+                    self.skip(2)?;
+                    let locals = self.reader.u16()?;
+                    for _ in 0..locals {
+                        self.verification_type()?;
+                    }
+                    (0, self.reader.u16()?)
+                }
+                _ => {
+                    return Err(crate::error::Error::ClassFormat(format!(
+                        "reserved frame type {frame_type}"
+                    )));
+                }
+            };
+            for _ in 0..locals + stack {
+                self.verification_type()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn verification_type(&mut self) -> Result<(), crate::error::Error> {
+        match self.reader.u8()? {
+            7 => self.index(),
+            8 => self.skip(2),
+            _ => Ok(()),
+        }
+    }
+
+    fn annotation(&mut self) -> Result<(), crate::error::Error> {
+        self.index()?;
+        for _ in 0..self.reader.u16()? {
+            self.index()?;
+            self.element_value()?;
+        }
+        Ok(())
+    }
+
+    fn element_value(&mut self) -> Result<(), crate::error::Error> {
+        match self.reader.u8()? {
+            b'B' | b'C' | b'D' | b'F' | b'I' | b'J' | b'S' | b'Z' | b's' | b'c' => self.index(),
+            b'e' => {
+                self.index()?;
+                self.index()
+            }
+            b'@' => self.annotation(),
+            b'[' => {
+                for _ in 0..self.reader.u16()? {
+                    self.element_value()?;
+                }
+                Ok(())
+            }
+            tag => Err(crate::error::Error::ClassFormat(format!(
+                "invalid element value tag {tag}"
+            ))),
+        }
+    }
+
+    fn type_annotation(&mut self) -> Result<(), crate::error::Error> {
+        let target_type = self.reader.u8()?;
+        match target_type {
+            0x00 | 0x01 | 0x16 => self.skip(1)?,
+            0x10..=0x12 | 0x17 | 0x42..=0x46 => self.skip(2)?,
+            0x13..=0x15 => {}
+            0x40 | 0x41 => {
+                let len = self.reader.u16()? as usize;
+                self.skip(len * 6)?;
+            }
+            0x47..=0x4B => self.skip(3)?,
+            _ => {
+                return Err(crate::error::Error::ClassFormat(format!(
+                    "invalid type annotation target {target_type}"
+                )));
+            }
+        }
+        let path_length = self.reader.u8()? as usize;
+        self.skip(path_length * 2)?;
+        self.annotation()
+    }
+
+    fn module(&mut self) -> Result<(), crate::error::Error> {
+        self.index()?;
+        self.skip(2)?;
+        self.index()?;
+        for _ in 0..self.reader.u16()? {
+            self.index()?;
+            self.skip(2)?;
+            self.index()?;
+        }
+        // exports, then opens
+        for _ in 0..2 {
+            for _ in 0..self.reader.u16()? {
+                self.index()?;
+                self.skip(2)?;
+                self.indices()?;
+            }
+        }
+        self.indices()?;
+        for _ in 0..self.reader.u16()? {
+            self.index()?;
+            self.indices()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::classfile::ACC_PUBLIC;
+
+    // the same class built with constants added in different orders
+    fn build(reversed: bool) -> ClassFile {
+        let mut constant_pool = ConstantPool::new();
+        constant_pool.add_utf8("unused").unwrap();
+        let mut order = ["SourceFile", "Deprecated"];
+        if reversed {
+            order.reverse();
+        }
+        for name in order {
+            constant_pool.add_utf8(name).unwrap();
+        }
+        let source_file = constant_pool.find_utf8("SourceFile").unwrap();
+        let deprecated = constant_pool.find_utf8("Deprecated").unwrap();
+        let file_name = constant_pool.add_utf8("B.java").unwrap();
+        let this_class = constant_pool.add_class("a/B").unwrap();
+        let super_class = constant_pool.add_class("java/lang/Object").unwrap();
+
+        let mut attributes = vec![
+            Attribute {
+                name_index: source_file,
+                info: file_name.to_be_bytes().to_vec(),
+            },
+            Attribute {
+                name_index: deprecated,
+                info: vec![],
+            },
+        ];
+        if reversed {
+            attributes.reverse();
+        }
+
+        ClassFile {
+            minor_version: 0,
+            major_version: 52,
+            constant_pool,
+            access_flags: ACC_PUBLIC,
+            this_class,
+            super_class,
+            interfaces: vec![],
+            fields: vec![],
+            methods: vec![],
+            attributes,
+        }
+    }
+
+    #[test]
+    fn test_normalize_is_canonical() {
+        let a = build(false);
+        let b = build(true);
+        assert_ne!(a.to_bytes(), b.to_bytes());
+
+        let a = normalize(&a, false).unwrap();
+        let b = normalize(&b, false).unwrap();
+        assert_eq!(a.to_bytes(), b.to_bytes());
+        assert_eq!(a.name().unwrap(), "a/B");
+        assert!(a.constant_pool.find_utf8("unused").is_none());
+        assert_eq!(normalize(&a, false).unwrap(), a);
+
+        let stripped = normalize(&a, true).unwrap();
+        assert_eq!(stripped.attributes.len(), 1);
+        assert!(stripped.constant_pool.find_utf8("B.java").is_none());
+    }
+}
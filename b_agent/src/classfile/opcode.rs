@@ -0,0 +1,122 @@
+// jvm instruction opcodes, only the ones referred to by name elsewhere get a constant
+pub const NOP: u8 = 0;
+pub const ACONST_NULL: u8 = 1;
+pub const BIPUSH: u8 = 16;
+pub const SIPUSH: u8 = 17;
+pub const LDC: u8 = 18;
+pub const LDC_W: u8 = 19;
+pub const LDC2_W: u8 = 20;
+pub const ILOAD: u8 = 21;
+pub const LLOAD: u8 = 22;
+pub const FLOAD: u8 = 23;
+pub const DLOAD: u8 = 24;
+pub const ALOAD: u8 = 25;
+pub const ISTORE: u8 = 54;
+pub const LSTORE: u8 = 55;
+pub const FSTORE: u8 = 56;
+pub const DSTORE: u8 = 57;
+pub const ASTORE: u8 = 58;
+pub const IINC: u8 = 132;
+pub const IFEQ: u8 = 153;
+pub const IF_ACMPNE: u8 = 166;
+pub const GOTO: u8 = 167;
+pub const JSR: u8 = 168;
+pub const RET: u8 = 169;
+pub const TABLESWITCH: u8 = 170;
+pub const LOOKUPSWITCH: u8 = 171;
+pub const IRETURN: u8 = 172;
+pub const RETURN: u8 = 177;
+pub const GETSTATIC: u8 = 178;
+pub const PUTSTATIC: u8 = 179;
+pub const GETFIELD: u8 = 180;
+pub const PUTFIELD: u8 = 181;
+pub const INVOKEVIRTUAL: u8 = 182;
+pub const INVOKESPECIAL: u8 = 183;
+pub const INVOKESTATIC: u8 = 184;
+pub const INVOKEINTERFACE: u8 = 185;
+pub const INVOKEDYNAMIC: u8 = 186;
+pub const NEW: u8 = 187;
+pub const NEWARRAY: u8 = 188;
+pub const ANEWARRAY: u8 = 189;
+pub const ATHROW: u8 = 191;
+pub const CHECKCAST: u8 = 192;
+pub const INSTANCEOF: u8 = 193;
+pub const WIDE: u8 = 196;
+pub const MULTIANEWARRAY: u8 = 197;
+pub const IFNULL: u8 = 198;
+pub const IFNONNULL: u8 = 199;
+pub const GOTO_W: u8 = 200;
+pub const JSR_W: u8 = 201;
+
+#[rustfmt::skip]
+const NAMES: [&str; 202] = [
+    "nop", "aconst_null", "iconst_m1", "iconst_0", "iconst_1", "iconst_2", "iconst_3", "iconst_4",
+    "iconst_5", "lconst_0", "lconst_1", "fconst_0", "fconst_1", "fconst_2", "dconst_0", "dconst_1",
+    "bipush", "sipush", "ldc", "ldc_w", "ldc2_w", "iload", "lload", "fload", "dload", "aload",
+    "iload_0", "iload_1", "iload_2", "iload_3", "lload_0", "lload_1", "lload_2", "lload_3",
+    "fload_0", "fload_1", "fload_2", "fload_3", "dload_0", "dload_1", "dload_2", "dload_3",
+    "aload_0", "aload_1", "aload_2", "aload_3", "iaload", "laload", "faload", "daload", "aaload",
+    "baload", "caload", "saload", "istore", "lstore", "fstore", "dstore", "astore", "istore_0",
+    "istore_1", "istore_2", "istore_3", "lstore_0", "lstore_1", "lstore_2", "lstore_3", "fstore_0",
+    "fstore_1", "fstore_2", "fstore_3", "dstore_0", "dstore_1", "dstore_2", "dstore_3", "astore_0",
+    "astore_1", "astore_2", "astore_3", "iastore", "lastore", "fastore", "dastore", "aastore",
+    "bastore", "castore", "sastore", "pop", "pop2", "dup", "dup_x1", "dup_x2", "dup2", "dup2_x1",
+    "dup2_x2", "swap", "iadd", "ladd", "fadd", "dadd", "isub", "lsub", "fsub", "dsub", "imul",
+    "lmul", "fmul", "dmul", "idiv", "ldiv", "fdiv", "ddiv", "irem", "lrem", "frem", "drem", "ineg",
+    "lneg", "fneg", "dneg", "ishl", "lshl", "ishr", "lshr", "iushr", "lushr", "iand", "land", "ior",
+    "lor", "ixor", "lxor", "iinc", "i2l", "i2f", "i2d", "l2i", "l2f", "l2d", "f2i", "f2l", "f2d",
+    "d2i", "d2l", "d2f", "i2b", "i2c", "i2s", "lcmp", "fcmpl", "fcmpg", "dcmpl", "dcmpg", "ifeq",
+    "ifne", "iflt", "ifge", "ifgt", "ifle", "if_icmpeq", "if_icmpne", "if_icmplt", "if_icmpge",
+    "if_icmpgt", "if_icmple", "if_acmpeq", "if_acmpne", "goto", "jsr", "ret", "tableswitch",
+    "lookupswitch", "ireturn", "lreturn", "freturn", "dreturn", "areturn", "return", "getstatic",
+    "putstatic", "getfield", "putfield", "invokevirtual", "invokespecial", "invokestatic",
+    "invokeinterface", "invokedynamic", "new", "newarray", "anewarray", "arraylength", "athrow",
+    "checkcast", "instanceof", "monitorenter", "monitorexit", "wide", "multianewarray", "ifnull",
+    "ifnonnull", "goto_w", "jsr_w",
+];
+
+pub fn name(opcode: u8) -> Option<&'static str> {
+    NAMES.get(opcode as usize).copied()
+}
+
+// instruction length excluding the variable sized switch and wide forms
+pub fn fixed_length(opcode: u8) -> Option<usize> {
+    let length = match opcode {
+        0..=15 => 1,
+        BIPUSH => 2,
+        SIPUSH => 3,
+        LDC => 2,
+        LDC_W | LDC2_W => 3,
+        ILOAD..=ALOAD => 2,
+        26..=53 => 1,
+        ISTORE..=ASTORE => 2,
+        59..=131 => 1,
+        IINC => 3,
+        133..=152 => 1,
+        IFEQ..=JSR => 3,
+        RET => 2,
+        IRETURN..=RETURN => 1,
+        GETSTATIC..=INVOKESTATIC => 3,
+        INVOKEINTERFACE | INVOKEDYNAMIC => 5,
+        NEW => 3,
+        NEWARRAY => 2,
+        ANEWARRAY => 3,
+        190 | ATHROW => 1,
+        CHECKCAST | INSTANCEOF => 3,
+        194 | 195 => 1,
+        MULTIANEWARRAY => 4,
+        IFNULL | IFNONNULL => 3,
+        GOTO_W | JSR_W => 5,
+        _ => return None,
+    };
+
+    Some(length)
+}
+
+// instructions after which execution never falls through to the next instruction
+pub fn is_unconditional(opcode: u8) -> bool {
+    matches!(
+        opcode,
+        GOTO | GOTO_W | RET | TABLESWITCH | LOOKUPSWITCH | ATHROW
+    ) || (IRETURN..=RETURN).contains(&opcode)
+}
@@ -0,0 +1,76 @@
+// bounds checked big endian reader over class file bytes
+pub struct ByteReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        ByteReader { data, position: 0 }
+    }
+
+    pub fn at(data: &'a [u8], position: usize) -> Self {
+        ByteReader {
+            data,
+            position: position.min(data.len()),
+        }
+    }
+
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.data.len() - self.position
+    }
+
+    pub fn bytes(&mut self, len: usize) -> Result<&'a [u8], crate::error::Error> {
+        if self.remaining() < len {
+            return Err(crate::error::Error::ClassFormat(format!(
+                "unexpected end of data at offset {} (wanted {} bytes, {} left)",
+                self.position,
+                len,
+                self.remaining()
+            )));
+        }
+
+        let slice = &self.data[self.position..self.position + len];
+        self.position += len;
+        Ok(slice)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, crate::error::Error> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16, crate::error::Error> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, crate::error::Error> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, crate::error::Error> {
+        Ok(((self.u32()? as u64) << 32) | self.u32()? as u64)
+    }
+}
+
+pub fn put_u16(out: &mut Vec<u8>, value: u16) {
+    out.extend_from_slice(&value.to_be_bytes());
+}
+
+pub fn put_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_be_bytes());
+}
+
+// read a big endian value directly out of a code array
+pub fn read_u16(data: &[u8], offset: usize) -> Result<u16, crate::error::Error> {
+    ByteReader::at(data, offset).u16()
+}
+
+pub fn read_i32(data: &[u8], offset: usize) -> Result<i32, crate::error::Error> {
+    Ok(ByteReader::at(data, offset).u32()? as i32)
+}
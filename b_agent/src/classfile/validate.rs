@@ -0,0 +1,585 @@
+use std::collections::HashSet;
+
+use super::code::{CodeAttribute, instructions};
+use super::constant_pool::Constant;
+use super::reader::ByteReader;
+use super::{ACC_ABSTRACT, ACC_NATIVE, Attribute, ClassFile, Member, descriptor, opcode};
+
+// structural checks on a class file, roughly what the jvm's format checker does before linking.
+// returns a description of every problem found, empty when the class looks sound.
+pub fn validate(data: &[u8]) -> Vec<String> {
+    let class_file = match ClassFile::parse(data) {
+        Ok(class_file) => class_file,
+        Err(e) => return vec![e.to_string()],
+    };
+
+    let mut validator = Validator {
+        class_file: &class_file,
+        problems: Vec::new(),
+    };
+    validator.check_version();
+    validator.check_constant_pool();
+    validator.check_class();
+    validator.check_attributes(&class_file.attributes, "class");
+    for field in &class_file.fields {
+        validator.check_member(field, false);
+    }
+    for method in &class_file.methods {
+        validator.check_member(method, true);
+    }
+
+    validator.problems
+}
+
+struct Validator<'a> {
+    class_file: &'a ClassFile,
+    problems: Vec<String>,
+}
+
+impl Validator<'_> {
+    fn report(&mut self, problem: String) {
+        self.problems.push(problem);
+    }
+
+    fn check_version(&mut self) {
+        let major = self.class_file.major_version;
+        let minor = self.class_file.minor_version;
+        // newer versions are left to the target jvm, which is the one deciding what it supports
+        if major < 45 {
+            self.report(format!("unsupported class file version {major}.{minor}"));
+        } else if major >= 56 && minor != 0 && minor != 0xFFFF {
+            self.report(format!(
+                "invalid minor version {minor} for major version {major}"
+            ));
+        }
+    }
+
+    // every reference between constant pool entries has to point at an entry of the right kind
+    fn check_constant_pool(&mut self) {
+        let constant_pool = &self.class_file.constant_pool;
+        for (index, constant) in constant_pool.iter() {
+            let problem = match constant {
+                Constant::Utf8(bytes) => (!super::constant_pool::is_modified_utf8(bytes))
+                    .then(|| "invalid modified utf-8".to_string()),
+                Constant::Class(name)
+                | Constant::String(name)
+                | Constant::MethodType(name)
+                | Constant::Module(name)
+                | Constant::Package(name) => {
+                    self.expect(*name, &[super::constant_pool::CONSTANT_UTF8])
+                }
+                Constant::FieldRef(class, nat)
+                | Constant::MethodRef(class, nat)
+                | Constant::InterfaceMethodRef(class, nat) => self
+                    .expect(*class, &[super::constant_pool::CONSTANT_CLASS])
+                    .or_else(|| self.expect(*nat, &[super::constant_pool::CONSTANT_NAME_AND_TYPE])),
+                Constant::NameAndType(name, descriptor) => self
+                    .expect(*name, &[super::constant_pool::CONSTANT_UTF8])
+                    .or_else(|| self.expect(*descriptor, &[super::constant_pool::CONSTANT_UTF8])),
+                Constant::Dynamic(_, nat) | Constant::InvokeDynamic(_, nat) => {
+                    self.expect(*nat, &[super::constant_pool::CONSTANT_NAME_AND_TYPE])
+                }
+                Constant::MethodHandle(kind, reference) => {
+                    use super::constant_pool::{
+                        CONSTANT_FIELDREF, CONSTANT_INTERFACE_METHODREF, CONSTANT_METHODREF,
+                    };
+                    match kind {
+                        1..=4 => self.expect(*reference, &[CONSTANT_FIELDREF]),
+                        5 | 8 => self.expect(*reference, &[CONSTANT_METHODREF]),
+                        6 | 7 => self.expect(
+                            *reference,
+                            &[CONSTANT_METHODREF, CONSTANT_INTERFACE_METHODREF],
+                        ),
+                        9 => self.expect(*reference, &[CONSTANT_INTERFACE_METHODREF]),
+                        _ => Some(format!("invalid reference kind {kind}")),
+                    }
+                }
+                Constant::Integer(_)
+                | Constant::Float(_)
+                | Constant::Long(_)
+                | Constant::Double(_) => None,
+            };
+
+            if let Some(problem) = problem {
+                self.report(format!("constant pool entry {index}: {problem}"));
+            }
+        }
+    }
+
+    // None when the index refers to an entry with one of the expected tags
+    fn expect(&self, index: u16, tags: &[u8]) -> Option<String> {
+        match self.class_file.constant_pool.get(index) {
+            Ok(constant) if tags.contains(&constant.tag()) => None,
+            Ok(constant) => Some(format!(
+                "index {index} has tag {}, expected one of {tags:?}",
+                constant.tag()
+            )),
+            Err(e) => Some(e.to_string()),
+        }
+    }
+
+    fn check_class(&mut self) {
+        let class = super::constant_pool::CONSTANT_CLASS;
+        if let Some(problem) = self.expect(self.class_file.this_class, &[class]) {
+            self.report(format!("this_class: {problem}"));
+        }
+        if self.class_file.super_class != 0
+            && let Some(problem) = self.expect(self.class_file.super_class, &[class])
+        {
+            self.report(format!("super_class: {problem}"));
+        }
+        for &interface in &self.class_file.interfaces {
+            if let Some(problem) = self.expect(interface, &[class]) {
+                self.report(format!("interface: {problem}"));
+            }
+        }
+    }
+
+    fn check_member(&mut self, member: &Member, is_method: bool) {
+        let (name, descriptor) = match (
+            self.class_file.member_name(member),
+            self.class_file.member_descriptor(member),
+        ) {
+            (Ok(name), Ok(descriptor)) => (name, descriptor),
+            (Err(e), _) | (_, Err(e)) => {
+                self.report(format!("member: {e}"));
+                return;
+            }
+        };
+
+        let context = format!("{name}{descriptor}");
+        let descriptor_ok = if is_method {
+            descriptor::parse_method(&descriptor).is_ok()
+        } else {
+            descriptor::is_valid_field(&descriptor)
+        };
+        if !descriptor_ok {
+            self.report(format!("{context}: invalid descriptor"));
+        }
+
+        self.check_attributes(&member.attributes, &context);
+        if !is_method {
+            return;
+        }
+
+        let code_attributes = member
+            .attributes
+            .iter()
+            .filter(|attribute| self.is_named(attribute, "Code"))
+            .collect::<Vec<_>>();
+        let needs_code = member.access_flags & (ACC_ABSTRACT | ACC_NATIVE) == 0;
+        match (needs_code, code_attributes.as_slice()) {
+            (true, [code]) => self.check_code(code, &context),
+            (false, []) => {}
+            (true, []) => self.report(format!("{context}: missing Code attribute")),
+            (false, [_, ..]) => self.report(format!(
+                "{context}: abstract or native method has a Code attribute"
+            )),
+            (true, _) => self.report(format!("{context}: more than one Code attribute")),
+        }
+    }
+
+    fn is_named(&self, attribute: &Attribute, name: &str) -> bool {
+        self.class_file
+            .attribute_name(attribute)
+            .is_ok_and(|n| n == name)
+    }
+
+    // attributes with a fixed layout must have a length matching their contents
+    fn check_attributes(&mut self, attributes: &[Attribute], context: &str) {
+        for attribute in attributes {
+            let name = match self.class_file.attribute_name(attribute) {
+                Ok(name) => name,
+                Err(e) => {
+                    self.report(format!("{context}: attribute name: {e}"));
+                    continue;
+                }
+            };
+
+            let info = &attribute.info;
+            let counted = |header: usize, entry: usize| {
+                info.len() >= header
+                    && info.len()
+                        == header + entry * u16::from_be_bytes([info[0], info[1]]) as usize
+            };
+            let valid = match name.as_str() {
+                "ConstantValue" | "SourceFile" | "Signature" | "NestHost" | "ModuleMainClass" => {
+                    info.len() == 2
+                }
+                "EnclosingMethod" => info.len() == 4,
+                "Deprecated" | "Synthetic" => info.is_empty(),
+                "Exceptions" | "NestMembers" | "PermittedSubclasses" | "ModulePackages" => {
+                    counted(2, 2)
+                }
+                "InnerClasses" => counted(2, 8),
+                "LineNumberTable" => counted(2, 4),
+                "LocalVariableTable" | "LocalVariableTypeTable" => counted(2, 10),
+                "BootstrapMethods" => bootstrap_methods_length(info),
+                _ => true,
+            };
+            if !valid {
+                self.report(format!(
+                    "{context}: {name} attribute has inconsistent length {}",
+                    info.len()
+                ));
+            }
+        }
+    }
+
+    fn check_code(&mut self, attribute: &Attribute, context: &str) {
+        let code = match CodeAttribute::parse(&attribute.info) {
+            Ok(code) => code,
+            Err(e) => {
+                self.report(format!("{context}: {e}"));
+                return;
+            }
+        };
+        let instructions = match instructions(&code.code) {
+            Ok(instructions) => instructions,
+            Err(e) => {
+                self.report(format!("{context}: {e}"));
+                return;
+            }
+        };
+
+        let starts = instructions
+            .iter()
+            .map(|instruction| instruction.offset)
+            .collect::<HashSet<_>>();
+        let mut has_targets = !code.exception_table.is_empty();
+        for instruction in &instructions {
+            match instruction.branch_targets(&code.code) {
+                Ok(targets) => {
+                    has_targets |= !targets.is_empty();
+                    for target in targets.into_iter().filter(|t| !starts.contains(t)) {
+                        self.report(format!(
+                            "{context}: branch at {} targets {target}, which is not an instruction",
+                            instruction.offset
+                        ));
+                    }
+                }
+                Err(e) => self.report(format!("{context}: {e}")),
+            }
+
+            if let Some(problem) = self.check_operands(instruction, &code) {
+                self.report(format!(
+                    "{context}: {} at {}: {problem}",
+                    opcode::name(instruction.opcode).unwrap_or("?"),
+                    instruction.offset
+                ));
+            }
+        }
+
+        let len = code.code.len();
+        for handler in &code.exception_table {
+            let (start, end, target) = (
+                handler.start_pc as usize,
+                handler.end_pc as usize,
+                handler.handler_pc as usize,
+            );
+            if start >= end
+                || !starts.contains(&start)
+                || (end != len && !starts.contains(&end))
+                || !starts.contains(&target)
+            {
+                self.report(format!(
+                    "{context}: exception handler {start}..{end} -> {target} is out of bounds"
+                ));
+            }
+            if handler.catch_type != 0
+                && let Some(problem) =
+                    self.expect(handler.catch_type, &[super::constant_pool::CONSTANT_CLASS])
+            {
+                self.report(format!("{context}: catch type: {problem}"));
+            }
+        }
+
+        self.check_attributes(&code.attributes, context);
+        let stack_maps = code
+            .attributes
+            .iter()
+            .filter(|attribute| self.is_named(attribute, "StackMapTable"))
+            .collect::<Vec<_>>();
+        match stack_maps.as_slice() {
+            [] if has_targets && self.class_file.major_version > super::STACK_MAP_VERSION => {
+                self.report(format!(
+                    "{context}: missing StackMapTable for code with branches"
+                ));
+            }
+            [] => {}
+            [table] => {
+                if let Err(e) = self.check_stack_map_table(&table.info, &code, &starts) {
+                    self.report(format!("{context}: StackMapTable: {e}"));
+                }
+            }
+            _ => self.report(format!("{context}: more than one StackMapTable")),
+        }
+    }
+
+    // constant pool and local variable operands of a single instruction
+    fn check_operands(
+        &self,
+        instruction: &super::code::Instruction,
+        code: &CodeAttribute,
+    ) -> Option<String> {
+        use super::constant_pool::*;
+
+        let loadable = [
+            CONSTANT_INTEGER,
+            CONSTANT_FLOAT,
+            CONSTANT_STRING,
+            CONSTANT_CLASS,
+            CONSTANT_METHOD_HANDLE,
+            CONSTANT_METHOD_TYPE,
+            CONSTANT_DYNAMIC,
+        ];
+        let operand = || instruction.u16_operand(&code.code).unwrap_or(0);
+        match instruction.opcode {
+            opcode::LDC => self.expect(code.code[instruction.offset + 1] as u16, &loadable),
+            opcode::LDC_W => self.expect(operand(), &loadable),
+            opcode::LDC2_W => self.expect(
+                operand(),
+                &[CONSTANT_LONG, CONSTANT_DOUBLE, CONSTANT_DYNAMIC],
+            ),
+            opcode::GETSTATIC..=opcode::PUTFIELD => self.expect(operand(), &[CONSTANT_FIELDREF]),
+            opcode::INVOKEVIRTUAL => self.expect(operand(), &[CONSTANT_METHODREF]),
+            opcode::INVOKESPECIAL | opcode::INVOKESTATIC => self.expect(
+                operand(),
+                &[CONSTANT_METHODREF, CONSTANT_INTERFACE_METHODREF],
+            ),
+            opcode::INVOKEINTERFACE => self.expect(operand(), &[CONSTANT_INTERFACE_METHODREF]),
+            opcode::INVOKEDYNAMIC => self.expect(operand(), &[CONSTANT_INVOKE_DYNAMIC]),
+            opcode::NEW
+            | opcode::ANEWARRAY
+            | opcode::CHECKCAST
+            | opcode::INSTANCEOF
+            | opcode::MULTIANEWARRAY => self.expect(operand(), &[CONSTANT_CLASS]),
+            opcode::ILOAD..=opcode::ALOAD
+            | opcode::ISTORE..=opcode::ASTORE
+            | opcode::IINC
+            | opcode::RET => {
+                let index = instruction.local_index(&code.code).ok()?;
+                let wide = matches!(
+                    instruction.opcode,
+                    opcode::LLOAD | opcode::DLOAD | opcode::LSTORE | opcode::DSTORE
+                );
+                let last = index as usize + wide as usize;
+                (last >= code.max_locals as usize)
+                    .then(|| format!("local {index} is outside of max_locals {}", code.max_locals))
+            }
+            _ => None,
+        }
+    }
+
+    // frame offsets have to land on instructions in increasing order, and the frames
+    // must fit into max_locals and max_stack
+    fn check_stack_map_table(
+        &self,
+        info: &[u8],
+        code: &CodeAttribute,
+        starts: &HashSet<usize>,
+    ) -> Result<(), String> {
+        let mut reader = ByteReader::new(info);
+        let mut previous: Option<usize> = None;
+        let count = reader.u16().map_err(|e| e.to_string())?;
+        for _ in 0..count {
+            let (delta, locals, stack) = self
+                .read_frame(&mut reader, code)
+                .map_err(|e| e.to_string())?;
+            let offset = match previous {
+                None => delta as usize,
+                Some(previous) => previous + delta as usize + 1,
+            };
+            if !starts.contains(&offset) {
+                return Err(format!("frame at {offset} is not at an instruction"));
+            }
+            if locals > code.max_locals as usize {
+                return Err(format!(
+                    "frame at {offset} has {locals} locals, max_locals is {}",
+                    code.max_locals
+                ));
+            }
+            if stack > code.max_stack as usize {
+                return Err(format!(
+                    "frame at {offset} has {stack} stack words, max_stack is {}",
+                    code.max_stack
+                ));
+            }
+            previous = Some(offset);
+        }
+
+        if reader.remaining() != 0 {
+            return Err(format!("{} trailing bytes", reader.remaining()));
+        }
+
+        Ok(())
+    }
+
+    // offset delta of a frame, plus the number of local and stack words it declares.
+    // local counts of compressed frames are relative, so they are only tracked for full frames.
+    fn read_frame(
+        &self,
+        reader: &mut ByteReader,
+        code: &CodeAttribute,
+    ) -> Result<(u16, usize, usize), crate::error::Error> {
+        let frame_type = reader.u8()?;
+        let frame = match frame_type {
+            0..=63 => (frame_type as u16, 0, 0),
+            64..=127 => (frame_type as u16 - 64, 0, self.read_type(reader, code)?),
+            247 => {
+                let delta = reader.u16()?;
+                (delta, 0, self.read_type(reader, code)?)
+            }
+            248..=251 => (reader.u16()?, 0, 0),
+            252..=254 => {
+                let delta = reader.u16()?;
+                for _ in 252..=frame_type {
+                    self.read_type(reader, code)?;
+                }
+                (delta, 0, 0)
+            }
+            255 => {
+                let delta = reader.u16()?;
+                let mut locals = 0;
+                for _ in 0..reader.u16()? {
+                    locals += self.read_type(reader, code)?;
+                }
+                let mut stack = 0;
+                for _ in 0..reader.u16()? {
+                    stack += self.read_type(reader, code)?;
+                }
+                (delta, locals, stack)
+            }
+            _ => {
+                return Err(crate::error::Error::ClassFormat(format!(
+                    "reserved frame type {frame_type}"
+                )));
+            }
+        };
+
+        Ok(frame)
+    }
+
+    // number of words taken by a verification type
+    fn read_type(
+        &self,
+        reader: &mut ByteReader,
+        code: &CodeAttribute,
+    ) -> Result<usize, crate::error::Error> {
+        let words = match reader.u8()? {
+            0..=2 | 5 | 6 => 1,
+            3 | 4 => 2,
+            7 => {
+                let index = reader.u16()?;
+                if let Some(problem) = self.expect(index, &[super::constant_pool::CONSTANT_CLASS]) {
+                    return Err(crate::error::Error::ClassFormat(problem));
+                }
+                1
+            }
+            8 => {
+                let offset = reader.u16()? as usize;
+                if code.code.get(offset) != Some(&opcode::NEW) {
+                    return Err(crate::error::Error::ClassFormat(format!(
+                        "uninitialized type refers to {offset}, which is not a new instruction"
+                    )));
+                }
+                1
+            }
+            tag => {
+                return Err(crate::error::Error::ClassFormat(format!(
+                    "invalid verification type tag {tag}"
+                )));
+            }
+        };
+
+        Ok(words)
+    }
+}
+
+// BootstrapMethods is a list of variable length entries, walk them to check the total length
+fn bootstrap_methods_length(info: &[u8]) -> bool {
+    let mut reader = ByteReader::new(info);
+    let walk = |reader: &mut ByteReader| -> Result<(), crate::error::Error> {
+        for _ in 0..reader.u16()? {
+            reader.u16()?;
+            let arguments = reader.u16()? as usize;
+            reader.bytes(arguments * 2)?;
+        }
+        Ok(())
+    };
+
+    walk(&mut reader).is_ok() && reader.remaining() == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::classfile::ConstantPool;
+
+    fn class_with_code(code: Vec<u8>, max_locals: u16) -> ClassFile {
+        let mut constant_pool = ConstantPool::new();
+        let this_class = constant_pool.add_class("a/B").unwrap();
+        let super_class = constant_pool.add_class("java/lang/Object").unwrap();
+        let name = constant_pool.add_utf8("run").unwrap();
+        let descriptor = constant_pool.add_utf8("()V").unwrap();
+        let code_name = constant_pool.add_utf8("Code").unwrap();
+        let code = CodeAttribute {
+            max_stack: 1,
+            max_locals,
+            code,
+            exception_table: vec![],
+            attributes: vec![],
+        };
+
+        ClassFile {
+            minor_version: 0,
+            major_version: 49,
+            constant_pool,
+            access_flags: 0,
+            this_class,
+            super_class,
+            interfaces: vec![],
+            fields: vec![],
+            methods: vec![Member {
+                access_flags: 0,
+                name_index: name,
+                descriptor_index: descriptor,
+                attributes: vec![Attribute {
+                    name_index: code_name,
+                    info: code.to_bytes(),
+                }],
+            }],
+            attributes: vec![],
+        }
+    }
+
+    #[test]
+    fn test_valid_class() {
+        // iconst_0; istore_0; return
+        let mut class_file = class_with_code(vec![3, 59, opcode::RETURN], 1);
+        assert!(validate(&class_file.to_bytes()).is_empty());
+
+        // versions newer than any known jvm are not a problem of the class
+        class_file.major_version = 200;
+        assert!(validate(&class_file.to_bytes()).is_empty());
+        class_file.major_version = 44;
+        assert_eq!(validate(&class_file.to_bytes()).len(), 1);
+    }
+
+    #[test]
+    fn test_problems_found() {
+        // goto into the middle of itself, then iload 5 with max_locals 1
+        let class_file = class_with_code(
+            vec![opcode::GOTO, 0, 1, opcode::ILOAD, 5, opcode::RETURN],
+            1,
+        );
+        let problems = validate(&class_file.to_bytes());
+        assert_eq!(problems.len(), 2, "{problems:?}");
+
+        let mut bytes = class_file.to_bytes();
+        bytes.truncate(bytes.len() - 3);
+        assert_eq!(validate(&bytes).len(), 1);
+        bytes[0] = 0;
+        assert!(validate(&bytes)[0].contains("magic"));
+    }
+}
@@ -0,0 +1,113 @@
+use super::code::CodeAttribute;
+use super::frames::{self, Hierarchy};
+use super::{Attribute, ClassFile, STACK_MAP_VERSION};
+
+// serializes class files, recomputing StackMapTable frames and max_stack of every method.
+// class files older than version 50 have no frames and are written unchanged.
+pub struct ClassWriter<H: Hierarchy> {
+    hierarchy: H,
+}
+
+impl<H: Hierarchy> ClassWriter<H> {
+    pub fn new(hierarchy: H) -> Self {
+        ClassWriter { hierarchy }
+    }
+
+    pub fn write(&mut self, class_file: &ClassFile) -> Result<Vec<u8>, crate::error::Error> {
+        if class_file.major_version < STACK_MAP_VERSION {
+            return Ok(class_file.to_bytes());
+        }
+
+        let mut class_file = class_file.clone();
+        let this_class = class_file.name()?;
+        let mut hierarchy = WithClass {
+            name: this_class.clone(),
+            super_name: class_file.super_name()?,
+            is_interface: class_file.is_interface(),
+            inner: &mut self.hierarchy,
+        };
+
+        let mut constant_pool = class_file.constant_pool.clone();
+        for method in class_file.methods.iter_mut() {
+            let Some(position) = method
+                .attributes
+                .iter()
+                .position(|attribute| is_named(&constant_pool, attribute, "Code"))
+            else {
+                continue;
+            };
+
+            let name = constant_pool.utf8(method.name_index)?;
+            let descriptor = constant_pool.utf8(method.descriptor_index)?;
+            let mut code = CodeAttribute::parse(&method.attributes[position].info)?;
+            let computed = frames::compute(
+                &constant_pool,
+                &this_class,
+                method.access_flags,
+                &name,
+                &descriptor,
+                &mut code,
+                &mut hierarchy,
+            )
+            .map_err(|e| in_method(e, &name, &descriptor))?;
+
+            code.attributes
+                .retain(|attribute| !is_named(&constant_pool, attribute, "StackMapTable"));
+            if !computed.frames.is_empty() {
+                let info = frames::encode_stack_map_table(
+                    &computed.initial,
+                    &computed.frames,
+                    &mut constant_pool,
+                )?;
+                code.attributes.push(Attribute {
+                    name_index: constant_pool.add_utf8("StackMapTable")?,
+                    info,
+                });
+            }
+
+            method.attributes[position].info = code.to_bytes();
+        }
+
+        class_file.constant_pool = constant_pool;
+        Ok(class_file.to_bytes())
+    }
+}
+
+fn is_named(constant_pool: &super::ConstantPool, attribute: &Attribute, name: &str) -> bool {
+    constant_pool
+        .utf8(attribute.name_index)
+        .is_ok_and(|attribute_name| attribute_name == name)
+}
+
+fn in_method(error: crate::error::Error, name: &str, descriptor: &str) -> crate::error::Error {
+    match error {
+        crate::error::Error::ClassFormat(message) => {
+            crate::error::Error::ClassFormat(format!("{message} in {name}{descriptor}"))
+        }
+        other => other,
+    }
+}
+
+// the class being written is usually not loadable yet, so answer questions about it directly
+struct WithClass<'a, H: Hierarchy> {
+    name: String,
+    super_name: Option<String>,
+    is_interface: bool,
+    inner: &'a mut H,
+}
+
+impl<H: Hierarchy> Hierarchy for WithClass<'_, H> {
+    fn super_class(&mut self, class_name: &str) -> Result<Option<String>, crate::error::Error> {
+        if class_name == self.name {
+            return Ok(self.super_name.clone());
+        }
+        self.inner.super_class(class_name)
+    }
+
+    fn is_interface(&mut self, class_name: &str) -> Result<bool, crate::error::Error> {
+        if class_name == self.name {
+            return Ok(self.is_interface);
+        }
+        self.inner.is_interface(class_name)
+    }
+}
@@ -0,0 +1,72 @@
+use std::{
+    fs::File,
+    io::Read as _,
+    path::{Path, PathBuf},
+};
+
+use zip::ZipArchive;
+
+enum Entry {
+    Directory(PathBuf),
+    Jar(ZipArchive<File>),
+}
+
+// jars and class directories searched in order, like a java class path
+pub struct ClassPath {
+    // code source location of each entry, as the jvm would report it
+    entries: Vec<(String, Entry)>,
+}
+
+impl ClassPath {
+    // directories are searched as class roots, anything else is opened as a jar
+    pub fn open(paths: &[PathBuf]) -> Result<Self, crate::error::Error> {
+        let entries = paths
+            .iter()
+            .map(|path| {
+                if path.is_dir() {
+                    Ok((
+                        format!("file:{}/", path.display()),
+                        Entry::Directory(path.clone()),
+                    ))
+                } else {
+                    Ok((
+                        format!("file:{}", path.display()),
+                        Entry::Jar(ZipArchive::new(File::open(path)?)?),
+                    ))
+                }
+            })
+            .collect::<Result<_, crate::error::Error>>()?;
+
+        Ok(ClassPath { entries })
+    }
+
+    // bytes of the first class file found for a binary class name, and where it was found
+    pub fn find(
+        &mut self,
+        class_name: &str,
+    ) -> Result<Option<(Vec<u8>, String)>, crate::error::Error> {
+        let file_name = format!("{}.class", crate::classfile::internal_name(class_name));
+        for (location, entry) in &mut self.entries {
+            match entry {
+                Entry::Directory(dir) => {
+                    let path = dir.join(Path::new(&file_name));
+                    if path.is_file() {
+                        return Ok(Some((std::fs::read(path)?, location.clone())));
+                    }
+                }
+                Entry::Jar(archive) => {
+                    let mut file = match archive.by_name(&file_name) {
+                        Ok(file) => file,
+                        Err(zip::result::ZipError::FileNotFound) => continue,
+                        Err(e) => return Err(e.into()),
+                    };
+                    let mut class_data = Vec::new();
+                    file.read_to_end(&mut class_data)?;
+                    return Ok(Some((class_data, location.clone())));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+}
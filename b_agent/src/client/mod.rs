@@ -73,6 +73,11 @@ impl ClientTrait for Client {
     fn retransform_method_name(&self) -> &str {
         "getAllDependencies"
     }
+
+    // the name of the method reporting dependencies together with their kind
+    fn dependency_edges_method_name(&self) -> Option<&str> {
+        Some("getDependencyEdges")
+    }
 }
 
 #[cfg(test)]
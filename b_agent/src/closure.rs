@@ -0,0 +1,90 @@
+use std::collections::{BTreeSet, HashSet, VecDeque};
+
+#[derive(Debug, Default, serde::Serialize)]
+pub struct ClosureReport {
+    pub roots: Vec<String>,
+    pub saved: usize,
+    // dependencies not found on the class path, jdk classes usually end up here
+    pub unresolved: BTreeSet<String>,
+    // classes found but not parsable, their dependencies are not followed
+    pub failed: BTreeSet<String>,
+    // classes rejected by the filter, neither saved nor followed
+    pub filtered: BTreeSet<String>,
+}
+
+// offline counterpart of the agent: save the root classes and everything they depend on,
+// following the same relation as the retransformer, into the recorder's output
+pub fn extract(
+    class_path: &mut crate::classpath::ClassPath,
+    roots: &[String],
+    recorder: &mut crate::recorder::DumpRecorder,
+) -> Result<ClosureReport, crate::error::Error> {
+    let mut report = ClosureReport {
+        roots: roots.to_vec(),
+        ..Default::default()
+    };
+    let mut seen = roots.iter().cloned().collect::<HashSet<_>>();
+    // roots are the seeds, dependencies are followed up to the crawl depth
+    let mut pending = roots
+        .iter()
+        .map(|root| (root.clone(), 0))
+        .collect::<VecDeque<_>>();
+    let filter = crate::filter::ClassFilter::new(&recorder.config().filter)?;
+    let max_depth = recorder.config().crawl.max_depth;
+    while let Some((class_name, depth)) = pending.pop_front() {
+        if !filter.may_accept(&class_name) {
+            report.filtered.insert(class_name);
+            continue;
+        }
+        let Some((class_data, code_source)) = class_path.find(&class_name)? else {
+            report.unresolved.insert(class_name);
+            continue;
+        };
+        let source = crate::recorder::ClassSource {
+            code_source: Some(code_source),
+            ..Default::default()
+        };
+        // loader and module are unknown offline, rules on them don't match
+        if !filter.accepts(&crate::filter::ClassInfo::new(
+            &class_name,
+            &class_data,
+            &source,
+        )) {
+            report.filtered.insert(class_name);
+            continue;
+        }
+        recorder.save(&class_name, &class_data, &source)?;
+
+        let edges = match crate::classfile::ClassFile::parse(&class_data)
+            .and_then(|class_file| crate::classfile::dependencies::dependency_edges(&class_file))
+        {
+            Ok(edges) => edges,
+            Err(e) => {
+                println!("failed to read dependencies of {class_name}: {e}");
+                report.failed.insert(class_name);
+                continue;
+            }
+        };
+        let dependencies = recorder.record_dependencies(&class_name, edges);
+        if max_depth.is_some_and(|max_depth| depth >= max_depth) {
+            continue;
+        }
+        for dependency in dependencies {
+            if seen.insert(dependency.clone()) {
+                pending.push_back((dependency, depth + 1));
+            }
+        }
+    }
+
+    report.saved = recorder.saved_count();
+    for class_name in &report.unresolved {
+        recorder.mark_unresolved(class_name);
+    }
+    for class_name in &report.failed {
+        recorder.mark_failed(class_name);
+    }
+    recorder.write_report("closure.json", &serde_json::to_vec_pretty(&report)?)?;
+    recorder.finish()?;
+
+    Ok(report)
+}
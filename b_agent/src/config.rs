@@ -0,0 +1,273 @@
+use std::path::{Path, PathBuf};
+
+// config file location, next to the dumped directory
+fn get_config_path() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("config.json")
+}
+
+// agent settings, every field is optional in the config file
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub normalize: NormalizeConfig,
+    pub output: OutputConfig,
+    pub store: StoreConfig,
+    pub pipeline: PipelineConfig,
+    pub snapshot: SnapshotConfig,
+    pub filter: FilterConfig,
+    pub crawl: CrawlConfig,
+    pub watch: WatchConfig,
+    pub instrumentation: InstrumentationConfig,
+    pub history: HistoryConfig,
+    pub hidden_classes: HiddenClassesConfig,
+}
+
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct NormalizeConfig {
+    // rewrite saved classes into a canonical form so that equal classes have equal bytes
+    pub enabled: bool,
+    // also drop source file, line number and local variable attributes
+    pub strip_debug: bool,
+}
+
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct OutputConfig {
+    pub kind: OutputKind,
+    // defaults to the dumped directory, or dumped.jar / dumped.tar next to it
+    pub path: Option<PathBuf>,
+    // one jar per jar or directory the classes were loaded from, inside the dumped directory.
+    // kind is not used then.
+    pub group_by_code_source: bool,
+    // stream to a collector at "tcp:<host>:<port>" or "unix:<path>" instead of writing locally
+    pub socket: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputKind {
+    #[default]
+    Directory,
+    Jar,
+    Tar,
+}
+
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct StoreConfig {
+    // store class bytes once under their sha-256 instead of writing them to the output
+    pub enabled: bool,
+    // defaults to a store directory next to the dumped directory
+    pub path: Option<PathBuf>,
+    // defaults to the start time and process id
+    pub session: Option<String>,
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct PipelineConfig {
+    // threads saving and analyzing hooked classes off the loading threads
+    pub workers: usize,
+    // classes waiting for a worker before the backpressure policy applies
+    pub capacity: usize,
+    pub backpressure: Backpressure,
+    // where spilled classes wait, defaults to a directory in the temp directory
+    pub spill_path: Option<PathBuf>,
+}
+
+impl Default for PipelineConfig {
+    fn default() -> Self {
+        PipelineConfig {
+            workers: 2,
+            capacity: 1024,
+            backpressure: Backpressure::default(),
+            spill_path: None,
+        }
+    }
+}
+
+// what a loading thread does when the pipeline queue is full
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Backpressure {
+    // wait for a free slot
+    #[default]
+    Block,
+    // skip the class and list it in pipeline.json
+    Drop,
+    // write the class to the spill directory, workers read it back when the queue drains
+    Spill,
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct SnapshotConfig {
+    // retransform every class the jvm has loaded right after attach, so classes nothing
+    // references again are dumped too
+    pub enabled: bool,
+    // classes per RetransformClasses call
+    pub batch_size: usize,
+}
+
+impl Default for SnapshotConfig {
+    fn default() -> Self {
+        SnapshotConfig {
+            enabled: false,
+            batch_size: 100,
+        }
+    }
+}
+
+// which classes are saved, analyzed and followed. rules are checked in order and the
+// first one matching decides, classes no rule matches get the default action.
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct FilterConfig {
+    pub rules: Vec<FilterRule>,
+    pub default: FilterAction,
+}
+
+// a rule matches when all of its conditions do. patterns are globs, where `*` stays within
+// a name segment and `**` matches anything, or regexes when prefixed with "regex:".
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct FilterRule {
+    pub action: FilterAction,
+    // binary class name, e.g. "java.**"
+    pub class: Option<String>,
+    // class of the defining loader, "bootstrap" for the boot loader
+    pub loader: Option<String>,
+    // "unnamed" outside named modules
+    pub module: Option<String>,
+    // code source url
+    pub code_source: Option<String>,
+    // class file major version range, inclusive
+    pub min_version: Option<u16>,
+    pub max_version: Option<u16>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterAction {
+    #[default]
+    Include,
+    Exclude,
+}
+
+// which classes the dump starts from and how far their dependencies are followed
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct CrawlConfig {
+    // class name patterns to start from, like filter patterns, e.g. "com.plugin.**".
+    // empty starts from every hooked class.
+    pub seeds: Vec<String>,
+    // dependency levels followed from a seed, None follows everything
+    pub max_depth: Option<usize>,
+    pub order: CrawlOrder,
+    // with priority order, classes matching an earlier pattern are retransformed first
+    pub priority: Vec<String>,
+    // also dump classes referencing the seeds, found by scanning the loaded classes.
+    // max_depth bounds the levels of referrers as well, their dependencies are not followed.
+    pub reverse: bool,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CrawlOrder {
+    // closest to a seed first
+    #[default]
+    BreadthFirst,
+    Priority,
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct WatchConfig {
+    // keep the hook enabled once the queue drained and dump classes as they are loaded,
+    // until the stop file appears, the duration is over or ctrl+c is pressed in the console
+    pub enabled: bool,
+    // counted from when the queue first drained
+    pub duration_secs: Option<u64>,
+    // defaults to a stop file next to the config file, written by `b_cli stop`
+    pub stop_file: Option<PathBuf>,
+    // seconds between progress lines while watching
+    pub status_interval_secs: u64,
+}
+
+impl Default for WatchConfig {
+    fn default() -> Self {
+        WatchConfig {
+            enabled: false,
+            duration_secs: None,
+            stop_file: None,
+            status_interval_secs: 60,
+        }
+    }
+}
+
+impl WatchConfig {
+    pub fn stop_path(&self) -> PathBuf {
+        self.stop_file
+            .clone()
+            .unwrap_or_else(|| get_config_path().with_file_name("stop"))
+    }
+}
+
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct InstrumentationConfig {
+    // also capture classes as loaded, before agents able to retransform changed them, through
+    // a second jvmti environment without that capability. only classes loaded after attach
+    // have them, retransformed classes don't pass through such environments.
+    pub enabled: bool,
+}
+
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct HistoryConfig {
+    // keep every version of classes saved again with other bytes, e.g. after a hot reload,
+    // as .class.v<n> next to the latest one, with history.json telling when, by whom and
+    // what changed. the latest bytes of every saved class are kept in memory for comparing.
+    pub enabled: bool,
+}
+
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct HiddenClassesConfig {
+    // capture hidden classes as they are defined by rebinding ClassLoader.defineClass0, the
+    // jvm doesn't pass them through the load hook and they can't be retransformed. jdk 15+.
+    pub enabled: bool,
+}
+
+impl Config {
+    // defaults are used when there is no config file
+    pub fn load() -> Result<Self, crate::error::Error> {
+        let path = get_config_path();
+        if !path.exists() {
+            return Ok(Config::default());
+        }
+
+        let config = serde_json::from_slice(&std::fs::read(&path)?)?;
+        println!("loaded config: {}", path.display());
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_partial_config() {
+        let config: Config = serde_json::from_str(r#"{"normalize": {"enabled": true}}"#).unwrap();
+        assert!(config.normalize.enabled);
+        assert!(!config.normalize.strip_debug);
+        assert_eq!(config.output.kind, OutputKind::Directory);
+        assert_eq!(config.pipeline.capacity, 1024);
+        assert!(!config.snapshot.enabled);
+        assert_eq!(config.snapshot.batch_size, 100);
+        assert!(!config.watch.enabled);
+        assert_eq!(config.watch.status_interval_secs, 60);
+    }
+}
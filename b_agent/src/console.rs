@@ -1,3 +1,8 @@
+use std::sync::atomic::AtomicBool;
+
+// set once ctrl+c or ctrl+break is pressed in the console while interrupts are caught
+pub static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
 #[cfg(target_os = "windows")]
 pub unsafe fn free_console() {
     use winapi::um::wincon::FreeConsole;
@@ -54,3 +59,24 @@ pub unsafe fn alloc_console() -> Result<(), std::io::Error> {
 
     Ok(())
 }
+
+// keep ctrl+c and ctrl+break from terminating the jvm, they set INTERRUPTED instead
+#[cfg(target_os = "windows")]
+pub fn catch_interrupts(enabled: bool) -> Result<(), std::io::Error> {
+    use winapi::um::consoleapi::SetConsoleCtrlHandler;
+    use winapi::um::wincon::{CTRL_BREAK_EVENT, CTRL_C_EVENT};
+
+    unsafe extern "system" fn handler(ctrl_type: u32) -> i32 {
+        if ctrl_type != CTRL_C_EVENT && ctrl_type != CTRL_BREAK_EVENT {
+            // closing the console still ends the process
+            return 0;
+        }
+        INTERRUPTED.store(true, std::sync::atomic::Ordering::SeqCst);
+        1
+    }
+
+    if unsafe { SetConsoleCtrlHandler(Some(handler), enabled as i32) } == 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
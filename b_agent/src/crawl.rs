@@ -0,0 +1,326 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use regex::Regex;
+
+use crate::config::{CrawlConfig, CrawlOrder};
+
+// hooked classes outside the crawl whose dependencies are kept for finding referrers
+const MAX_OUTSIDE: usize = 100_000;
+
+// how a class became part of the crawl
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reach {
+    Seed,
+    // dependency levels away from a seed
+    Dependency(usize),
+    // reference levels towards a seed
+    Referrer(usize),
+}
+
+// which hooked classes are dumped and which of their dependencies are followed.
+// classes are keyed by binary name.
+pub struct Crawl {
+    seeds: Vec<Regex>,
+    // seeds naming a single class, they are looked up instead of waiting for them to load
+    named_seeds: Vec<String>,
+    priority: Vec<Regex>,
+    order: CrawlOrder,
+    max_depth: Option<usize>,
+    reverse: bool,
+    reached: HashMap<String, Reach>,
+    // dependencies of hooked classes outside the crawl, searched for referrers
+    outside: HashMap<String, BTreeSet<String>>,
+}
+
+impl Crawl {
+    pub fn new(config: &CrawlConfig) -> Result<Self, crate::error::Error> {
+        let patterns = |patterns: &[String]| {
+            patterns
+                .iter()
+                .map(|pattern| crate::filter::compile(pattern))
+                .collect::<Result<Vec<_>, _>>()
+        };
+        Ok(Crawl {
+            seeds: patterns(&config.seeds)?,
+            named_seeds: config
+                .seeds
+                .iter()
+                .filter(|seed| !seed.starts_with("regex:") && !seed.contains(['*', '?']))
+                .cloned()
+                .collect(),
+            priority: patterns(&config.priority)?,
+            order: config.order,
+            max_depth: config.max_depth,
+            reverse: config.reverse,
+            reached: HashMap::new(),
+            outside: HashMap::new(),
+        })
+    }
+
+    // without seeds every hooked class starts a crawl
+    pub fn is_seed(&self, class_name: &str) -> bool {
+        let name = crate::classfile::binary_name(class_name);
+        self.seeds.is_empty() || self.seeds.iter().any(|seed| seed.is_match(&name))
+    }
+
+    pub fn is_seeded(&self) -> bool {
+        !self.seeds.is_empty()
+    }
+
+    pub fn named_seeds(&self) -> &[String] {
+        &self.named_seeds
+    }
+
+    // referrers can only be found among classes the jvm has already loaded
+    pub fn scans_loaded_classes(&self) -> bool {
+        self.reverse && self.is_seeded()
+    }
+
+    // a class queued by an earlier attach, its depth is not known anymore
+    pub fn resume(&mut self, class_name: &str) {
+        self.reached
+            .entry(crate::classfile::binary_name(class_name))
+            .or_insert(Reach::Seed);
+    }
+
+    // how a hooked class belongs to the crawl, None when it is outside and not dumped
+    pub fn admit(&mut self, class_name: &str) -> Option<Reach> {
+        let name = crate::classfile::binary_name(class_name);
+        if let Some(&reach) = self.reached.get(&name) {
+            return Some(reach);
+        }
+        if self.is_seed(&name) {
+            self.outside.remove(&name);
+            self.reached.insert(name, Reach::Seed);
+            return Some(Reach::Seed);
+        }
+        None
+    }
+
+    // whether the dependencies of classes outside the crawl are wanted, see record_outside
+    pub fn keeps_outside(&self) -> bool {
+        self.reverse && self.outside.len() < MAX_OUTSIDE
+    }
+
+    // dependencies of a class admit left out, parsed by the caller without the crawl locked
+    pub fn record_outside(&mut self, class_name: &str, dependencies: BTreeSet<String>) {
+        let name = crate::classfile::binary_name(class_name);
+        if self.keeps_outside() && !self.reached.contains_key(&name) {
+            self.outside.insert(name, dependencies);
+        }
+    }
+
+    // generated classes are never named as a dependency, they are reached through their host
+    pub fn admit_dynamic(&mut self, class_name: &str, host: Option<&str>) -> Option<Reach> {
+        let from_host = host
+            .and_then(|host| self.reached.get(&crate::classfile::binary_name(host)))
+            .map(|reach| match *reach {
+                Reach::Seed => Reach::Dependency(1),
+                Reach::Dependency(depth) => Reach::Dependency(depth + 1),
+                Reach::Referrer(depth) => Reach::Referrer(depth),
+            });
+        match from_host {
+            Some(reach) if !self.reached.contains_key(class_name) => {
+                self.outside.remove(class_name);
+                self.reached.insert(class_name.to_string(), reach);
+                Some(reach)
+            }
+            _ => self.admit(class_name),
+        }
+    }
+
+    // take the dependencies of a dumped class into the crawl, returns the ones to retransform
+    pub fn follow(&mut self, class_name: &str, dependencies: Vec<String>) -> Vec<String> {
+        let depth = match self.reached.get(&crate::classfile::binary_name(class_name)) {
+            Some(Reach::Seed) => 0,
+            Some(Reach::Dependency(depth)) => *depth,
+            // referrers are dumped without what they depend on
+            Some(Reach::Referrer(_)) | None => return Vec::new(),
+        };
+        let next = depth + 1;
+        if self.max_depth.is_some_and(|max_depth| next > max_depth) {
+            return Vec::new();
+        }
+
+        let mut queued = Vec::new();
+        for dependency in dependencies {
+            let name = crate::classfile::binary_name(&dependency);
+            match self.reached.get_mut(&name) {
+                Some(Reach::Dependency(known)) if *known > next => *known = next,
+                Some(_) => {}
+                None => {
+                    self.outside.remove(&name);
+                    self.reached.insert(name, Reach::Dependency(next));
+                    queued.push(dependency);
+                }
+            }
+        }
+        queued
+    }
+
+    // classes outside the crawl that reference a seed, or a referrer one level closer to one.
+    // returns the ones not found before, they are dumped once retransformed.
+    pub fn find_referrers(&mut self) -> Vec<String> {
+        if !self.scans_loaded_classes() {
+            return Vec::new();
+        }
+
+        let mut found = Vec::new();
+        for level in 1.. {
+            if self.max_depth.is_some_and(|max_depth| level > max_depth) {
+                break;
+            }
+            let targets = self
+                .reached
+                .iter()
+                .filter(|(_, reach)| match reach {
+                    Reach::Seed => level == 1,
+                    Reach::Referrer(depth) => *depth == level - 1,
+                    Reach::Dependency(_) => false,
+                })
+                .map(|(name, _)| name.as_str())
+                .collect::<HashSet<_>>();
+            if targets.is_empty() {
+                break;
+            }
+
+            let referrers = self
+                .outside
+                .iter()
+                .filter(|(_, dependencies)| {
+                    dependencies
+                        .iter()
+                        .any(|dependency| targets.contains(dependency.as_str()))
+                })
+                .map(|(name, _)| name.clone())
+                .collect::<Vec<_>>();
+            for name in &referrers {
+                self.outside.remove(name);
+                self.reached.insert(name.clone(), Reach::Referrer(level));
+            }
+            found.extend(referrers);
+        }
+        found
+    }
+
+    // order queued classes are retransformed in, closest to a seed first within a priority
+    pub fn order(&self, mut classes: Vec<String>) -> Vec<String> {
+        classes.sort_by_cached_key(|class_name| {
+            let name = crate::classfile::binary_name(class_name);
+            let priority = match self.order {
+                CrawlOrder::BreadthFirst => 0,
+                CrawlOrder::Priority => self
+                    .priority
+                    .iter()
+                    .position(|pattern| pattern.is_match(&name))
+                    .unwrap_or(self.priority.len()),
+            };
+            let depth = match self.reached.get(&name) {
+                Some(Reach::Dependency(depth) | Reach::Referrer(depth)) => *depth,
+                Some(Reach::Seed) | None => 0,
+            };
+            (priority, depth, name)
+        });
+        classes
+    }
+}
+
+// binary names a class depends on, for record_outside
+pub fn dependencies(class_data: &[u8]) -> Result<BTreeSet<String>, crate::error::Error> {
+    let class_file = crate::classfile::ClassFile::parse(class_data)?;
+    let dependencies = crate::classfile::dependencies::dependency_edges(&class_file)?;
+    Ok(dependencies
+        .into_iter()
+        .map(|(_, dependency)| dependency)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_depth_and_order() {
+        let config: CrawlConfig = serde_json::from_str(
+            r#"{"seeds": ["com.plugin.**"], "max_depth": 2, "order": "priority", "priority": ["com.lib.*"]}"#,
+        )
+        .unwrap();
+        let mut crawl = Crawl::new(&config).unwrap();
+        assert!(crawl.named_seeds().is_empty());
+
+        // classes outside the seeds are only dumped once reached from one
+        assert_eq!(crawl.admit("com/other/A"), None);
+        assert_eq!(crawl.admit("com/plugin/Main"), Some(Reach::Seed));
+        let queued = crawl.follow(
+            "com/plugin/Main",
+            vec!["com.other.A".to_string(), "com.lib.B".to_string()],
+        );
+        assert_eq!(queued, ["com.other.A", "com.lib.B"]);
+        assert_eq!(crawl.admit("com/other/A"), Some(Reach::Dependency(1)));
+        assert_eq!(crawl.order(queued), ["com.lib.B", "com.other.A"]);
+
+        assert_eq!(
+            crawl.follow("com.other.A", vec!["com.deep.C".to_string()]),
+            ["com.deep.C"]
+        );
+        assert!(
+            crawl
+                .follow("com.deep.C", vec!["com.deeper.D".to_string()])
+                .is_empty()
+        );
+        // already reached
+        assert!(
+            crawl
+                .follow("com.plugin.Main", vec!["com.lib.B".to_string()])
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_reverse() {
+        let config = CrawlConfig {
+            seeds: vec!["a.Seed".to_string()],
+            max_depth: Some(1),
+            reverse: true,
+            ..Default::default()
+        };
+        let mut crawl = Crawl::new(&config).unwrap();
+        assert_eq!(crawl.named_seeds(), ["a.Seed"]);
+
+        // b.User extends a.Seed, c.Far extends b.User
+        let class = |name: &str, super_name: &str| {
+            let mut constant_pool = crate::classfile::ConstantPool::new();
+            let this_class = constant_pool.add_class(name).unwrap();
+            let super_class = constant_pool.add_class(super_name).unwrap();
+            crate::classfile::ClassFile {
+                minor_version: 0,
+                major_version: 52,
+                constant_pool,
+                access_flags: 0x0021,
+                this_class,
+                super_class,
+                interfaces: Vec::new(),
+                fields: Vec::new(),
+                methods: Vec::new(),
+                attributes: Vec::new(),
+            }
+            .to_bytes()
+        };
+        for (name, super_name) in [("b/User", "a/Seed"), ("c/Far", "b/User")] {
+            assert_eq!(crawl.admit(name), None);
+            assert!(crawl.keeps_outside());
+            crawl.record_outside(name, dependencies(&class(name, super_name)).unwrap());
+        }
+        assert_eq!(crawl.admit("a/Seed"), Some(Reach::Seed));
+
+        // the second level is beyond the depth limit
+        assert_eq!(crawl.find_referrers(), ["b.User"]);
+        assert_eq!(crawl.admit("b/User"), Some(Reach::Referrer(1)));
+        assert!(
+            crawl
+                .follow("b.User", vec!["d.Dependency".to_string()])
+                .is_empty()
+        );
+        assert!(crawl.find_referrers().is_empty());
+    }
+}
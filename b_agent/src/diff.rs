@@ -0,0 +1,416 @@
+use std::{collections::BTreeMap, fmt::Write as _, path::Path};
+
+use crate::classfile::{ClassFile, Member, disasm};
+
+// lines of unchanged bytecode shown around each change
+const CONTEXT_LINES: usize = 2;
+
+// above this many cells the line diff gives up and reports the whole method as replaced
+const MAX_DIFF_CELLS: usize = 16_000_000;
+
+#[derive(Debug, Default, serde::Serialize)]
+pub struct DumpDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<ClassDiff>,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct ClassDiff {
+    pub name: String,
+    // differences in version, flags, superclass or interfaces
+    pub header: Vec<String>,
+    pub fields: MemberChanges,
+    pub methods: MemberChanges,
+    // set when the bytes differ but nothing above does, e.g. only debug info or annotations
+    pub other_changes: bool,
+}
+
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct MemberChanges {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<MemberDiff>,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct MemberDiff {
+    // name and descriptor, name:descriptor for fields
+    pub name: String,
+    pub changes: Vec<String>,
+    // unified diff of the disassembled code, empty when the code is the same
+    pub bytecode: Vec<String>,
+}
+
+// compare two dumps, each a directory, .jar or .tar
+pub fn diff_dirs(old: &Path, new: &Path) -> Result<DumpDiff, crate::error::Error> {
+    let old = crate::dump::read_classes(old)?;
+    let new = crate::dump::read_classes(new)?;
+    diff_classes(&old, &new)
+}
+
+pub fn diff_classes(
+    old: &BTreeMap<String, Vec<u8>>,
+    new: &BTreeMap<String, Vec<u8>>,
+) -> Result<DumpDiff, crate::error::Error> {
+    let mut diff = DumpDiff::default();
+    for (name, old_data) in old {
+        match new.get(name) {
+            None => diff.removed.push(name.clone()),
+            Some(new_data) if new_data == old_data => {}
+            Some(new_data) => diff.changed.push(diff_class(name, old_data, new_data)),
+        }
+    }
+    diff.added = new
+        .keys()
+        .filter(|name| !old.contains_key(*name))
+        .cloned()
+        .collect();
+
+    // byte level differences that don't show up in the class structure are not worth reporting
+    diff.changed
+        .retain(|class| !class.is_empty() || class.other_changes);
+    Ok(diff)
+}
+
+// compare two versions of a class, classes that don't parse are reported in the header
+pub fn diff_class(name: &str, old_data: &[u8], new_data: &[u8]) -> ClassDiff {
+    try_diff_class(name, old_data, new_data).unwrap_or_else(|e| ClassDiff {
+        name: name.to_string(),
+        header: vec![format!("failed to compare: {e}")],
+        fields: MemberChanges::default(),
+        methods: MemberChanges::default(),
+        other_changes: false,
+    })
+}
+
+fn try_diff_class(
+    name: &str,
+    old_data: &[u8],
+    new_data: &[u8],
+) -> Result<ClassDiff, crate::error::Error> {
+    let old = ClassFile::parse(old_data)?;
+    let new = ClassFile::parse(new_data)?;
+
+    let mut header = Vec::new();
+    let mut compare = |what: &str, old: String, new: String| {
+        if old != new {
+            header.push(format!("{what}: {old} -> {new}"));
+        }
+    };
+    compare(
+        "version",
+        format!("{}.{}", old.major_version, old.minor_version),
+        format!("{}.{}", new.major_version, new.minor_version),
+    );
+    compare(
+        "access",
+        format!("{:#06x}", old.access_flags),
+        format!("{:#06x}", new.access_flags),
+    );
+    compare(
+        "super",
+        old.super_name()?.unwrap_or_default(),
+        new.super_name()?.unwrap_or_default(),
+    );
+    compare(
+        "interfaces",
+        old.interface_names()?.join(", "),
+        new.interface_names()?.join(", "),
+    );
+    compare(
+        "signature",
+        signature(&old, &old.attributes)?,
+        signature(&new, &new.attributes)?,
+    );
+
+    let mut class_diff = ClassDiff {
+        name: name.to_string(),
+        header,
+        fields: diff_members(&old, &old.fields, &new, &new.fields, ":")?,
+        methods: diff_members(&old, &old.methods, &new, &new.methods, "")?,
+        other_changes: false,
+    };
+    // normalized forms only differ when something outside of the compared parts changed
+    class_diff.other_changes = class_diff.is_empty()
+        && crate::classfile::normalize::normalize(&old, false)
+            .and_then(|old| Ok((old, crate::classfile::normalize::normalize(&new, false)?)))
+            .is_ok_and(|(old, new)| old.to_bytes() != new.to_bytes());
+
+    Ok(class_diff)
+}
+
+impl ClassDiff {
+    pub fn is_empty(&self) -> bool {
+        self.header.is_empty() && self.fields.is_empty() && self.methods.is_empty()
+    }
+}
+
+impl MemberChanges {
+    fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+fn diff_members(
+    old_class: &ClassFile,
+    old: &[Member],
+    new_class: &ClassFile,
+    new: &[Member],
+    separator: &str,
+) -> Result<MemberChanges, crate::error::Error> {
+    let key = |class_file: &ClassFile, member: &Member| -> Result<String, crate::error::Error> {
+        Ok(format!(
+            "{}{separator}{}",
+            class_file.member_name(member)?,
+            class_file.member_descriptor(member)?
+        ))
+    };
+    let old = old
+        .iter()
+        .map(|member| Ok((key(old_class, member)?, member)))
+        .collect::<Result<BTreeMap<_, _>, crate::error::Error>>()?;
+    let new = new
+        .iter()
+        .map(|member| Ok((key(new_class, member)?, member)))
+        .collect::<Result<BTreeMap<_, _>, crate::error::Error>>()?;
+
+    let mut changes = MemberChanges::default();
+    for (name, old_member) in &old {
+        let Some(new_member) = new.get(name) else {
+            changes.removed.push(name.clone());
+            continue;
+        };
+
+        let member_diff = diff_member(name, old_class, old_member, new_class, new_member)?;
+        if !member_diff.changes.is_empty() || !member_diff.bytecode.is_empty() {
+            changes.changed.push(member_diff);
+        }
+    }
+    changes.added = new
+        .keys()
+        .filter(|name| !old.contains_key(*name))
+        .cloned()
+        .collect();
+
+    Ok(changes)
+}
+
+fn diff_member(
+    name: &str,
+    old_class: &ClassFile,
+    old: &Member,
+    new_class: &ClassFile,
+    new: &Member,
+) -> Result<MemberDiff, crate::error::Error> {
+    let mut changes = Vec::new();
+    if old.access_flags != new.access_flags {
+        changes.push(format!(
+            "access: {:#06x} -> {:#06x}",
+            old.access_flags, new.access_flags
+        ));
+    }
+    let old_signature = signature(old_class, &old.attributes)?;
+    let new_signature = signature(new_class, &new.attributes)?;
+    if old_signature != new_signature {
+        changes.push(format!("signature: {old_signature} -> {new_signature}"));
+    }
+    let old_value = constant_value(old_class, old)?;
+    let new_value = constant_value(new_class, new)?;
+    if old_value != new_value {
+        changes.push(format!("constant value: {old_value} -> {new_value}"));
+    }
+
+    let old_code = listing(old_class, old)?;
+    let new_code = listing(new_class, new)?;
+    let bytecode = if old_code == new_code {
+        vec![]
+    } else {
+        diff_lines(&old_code, &new_code)
+    };
+
+    Ok(MemberDiff {
+        name: name.to_string(),
+        changes,
+        bytecode,
+    })
+}
+
+fn signature(
+    class_file: &ClassFile,
+    attributes: &[crate::classfile::Attribute],
+) -> Result<String, crate::error::Error> {
+    match class_file.find_attribute(attributes, "Signature") {
+        Some(attribute) if attribute.info.len() == 2 => class_file
+            .constant_pool
+            .utf8(u16::from_be_bytes([attribute.info[0], attribute.info[1]])),
+        _ => Ok(String::new()),
+    }
+}
+
+fn constant_value(class_file: &ClassFile, member: &Member) -> Result<String, crate::error::Error> {
+    match class_file.find_attribute(&member.attributes, "ConstantValue") {
+        Some(attribute) if attribute.info.len() == 2 => disasm::constant(
+            class_file,
+            u16::from_be_bytes([attribute.info[0], attribute.info[1]]),
+        ),
+        _ => Ok(String::new()),
+    }
+}
+
+fn listing(class_file: &ClassFile, member: &Member) -> Result<Vec<String>, crate::error::Error> {
+    match class_file.code(member)? {
+        Some(code) => disasm::disassemble(class_file, &code),
+        None => Ok(vec![]),
+    }
+}
+
+// unified diff of two listings with a few lines of context, hunks are separated by "..."
+fn diff_lines(old: &[String], new: &[String]) -> Vec<String> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old_middle = &old[prefix..old.len() - suffix];
+    let new_middle = &new[prefix..new.len() - suffix];
+
+    // ' ' unchanged, '-' removed, '+' added
+    let mut edits = old[..prefix]
+        .iter()
+        .map(|line| (' ', line))
+        .collect::<Vec<_>>();
+    if old_middle.len() * new_middle.len() > MAX_DIFF_CELLS {
+        edits.extend(old_middle.iter().map(|line| ('-', line)));
+        edits.extend(new_middle.iter().map(|line| ('+', line)));
+    } else {
+        edits.extend(lcs_edits(old_middle, new_middle));
+    }
+    edits.extend(old[old.len() - suffix..].iter().map(|line| (' ', line)));
+
+    let mut lines = Vec::new();
+    let mut last_shown = None;
+    for (index, (kind, _)) in edits.iter().enumerate() {
+        if *kind == ' ' {
+            continue;
+        }
+        let start = index.saturating_sub(CONTEXT_LINES);
+        let start = match last_shown {
+            Some(last) if start <= last + 1 => last + 1,
+            Some(_) => {
+                lines.push("...".to_string());
+                start
+            }
+            None => start,
+        };
+        let end = (index + CONTEXT_LINES).min(edits.len() - 1);
+        for (kind, line) in &edits[start..=end] {
+            lines.push(format!("{kind} {line}"));
+        }
+        last_shown = Some(end);
+    }
+
+    lines
+}
+
+// edit script of a longest common subsequence
+fn lcs_edits<'a>(old: &'a [String], new: &'a [String]) -> Vec<(char, &'a String)> {
+    let (n, m) = (old.len(), new.len());
+    let mut table = vec![0u32; (n + 1) * (m + 1)];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            table[i * (m + 1) + j] = if old[i] == new[j] {
+                table[(i + 1) * (m + 1) + j + 1] + 1
+            } else {
+                table[(i + 1) * (m + 1) + j].max(table[i * (m + 1) + j + 1])
+            };
+        }
+    }
+
+    let mut edits = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if old[i] == new[j] {
+            edits.push((' ', &old[i]));
+            i += 1;
+            j += 1;
+        } else if table[(i + 1) * (m + 1) + j] >= table[i * (m + 1) + j + 1] {
+            edits.push(('-', &old[i]));
+            i += 1;
+        } else {
+            edits.push(('+', &new[j]));
+            j += 1;
+        }
+    }
+    edits.extend(old[i..].iter().map(|line| ('-', line)));
+    edits.extend(new[j..].iter().map(|line| ('+', line)));
+    edits
+}
+
+impl DumpDiff {
+    // human readable report
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        for name in &self.added {
+            let _ = writeln!(out, "+ class {name}");
+        }
+        for name in &self.removed {
+            let _ = writeln!(out, "- class {name}");
+        }
+        for class in &self.changed {
+            let _ = writeln!(out, "~ class {}", class.name);
+            for change in &class.header {
+                let _ = writeln!(out, "    {change}");
+            }
+            for (kind, members) in [("field", &class.fields), ("method", &class.methods)] {
+                for name in &members.added {
+                    let _ = writeln!(out, "    + {kind} {name}");
+                }
+                for name in &members.removed {
+                    let _ = writeln!(out, "    - {kind} {name}");
+                }
+                for member in &members.changed {
+                    let _ = writeln!(out, "    ~ {kind} {}", member.name);
+                    for change in &member.changes {
+                        let _ = writeln!(out, "        {change}");
+                    }
+                    for line in &member.bytecode {
+                        let _ = writeln!(out, "        {line}");
+                    }
+                }
+            }
+            if class.other_changes {
+                let _ = writeln!(out, "    attributes changed");
+            }
+        }
+
+        let _ = writeln!(
+            out,
+            "{} added, {} removed, {} changed",
+            self.added.len(),
+            self.removed.len(),
+            self.changed.len()
+        );
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_lines() {
+        let lines = |s: &str| s.split(' ').map(str::to_string).collect::<Vec<_>>();
+        let old = lines("a b c d e f g h i j");
+        let new = lines("a b c x e f g h i j k");
+        assert_eq!(
+            diff_lines(&old, &new),
+            [
+                "  b", "  c", "- d", "+ x", "  e", "  f", "...", "  i", "  j", "+ k"
+            ]
+        );
+    }
+}
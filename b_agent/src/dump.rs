@@ -0,0 +1,123 @@
+use std::{collections::BTreeMap, path::Path};
+
+// read every class file of a dump, keyed by binary class name. a dump is a directory, possibly
+// holding one jar per origin, or a single .jar or .tar. classes that don't parse are keyed by
+// their path within the dump.
+pub fn read_classes(dump: &Path) -> Result<BTreeMap<String, Vec<u8>>, crate::error::Error> {
+    let mut classes = BTreeMap::new();
+    if dump.is_file() {
+        if !is_archive(dump) {
+            return Err(crate::error::Error::XValueNotOfType(
+                "dump directory, .jar or .tar",
+            ));
+        }
+        read_archive_classes(dump, "", &mut classes)?;
+        return Ok(classes);
+    }
+
+    let mut pending = vec![dump.to_path_buf()];
+    while let Some(current) = pending.pop() {
+        for entry in std::fs::read_dir(&current)? {
+            let path = entry?.path();
+            let relative = path
+                .strip_prefix(dump)
+                .unwrap_or(&path)
+                .display()
+                .to_string();
+            if path.is_dir() {
+                pending.push(path);
+            } else if is_archive(&path) {
+                read_archive_classes(&path, &format!("{relative}/"), &mut classes)?;
+            } else if path
+                .extension()
+                .is_some_and(|extension| extension == "class")
+            {
+                insert_class(&mut classes, relative, std::fs::read(&path)?);
+            }
+        }
+    }
+
+    Ok(classes)
+}
+
+fn is_archive(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension == "jar" || extension == "tar")
+}
+
+// class files of a jar or tar, `prefix` goes before the entry paths of classes that don't parse
+fn read_archive_classes(
+    path: &Path,
+    prefix: &str,
+    classes: &mut BTreeMap<String, Vec<u8>>,
+) -> Result<(), crate::error::Error> {
+    for (entry, data) in crate::manifest::read_archive(path)? {
+        if entry.ends_with(".class") {
+            insert_class(classes, format!("{prefix}{entry}"), data);
+        }
+    }
+    Ok(())
+}
+
+fn insert_class(classes: &mut BTreeMap<String, Vec<u8>>, path: String, data: Vec<u8>) {
+    let name = class_key(&data).unwrap_or(path);
+    classes.insert(name, data);
+}
+
+// binary class name, module descriptors are all called module-info so they get the module name
+fn class_key(data: &[u8]) -> Result<String, crate::error::Error> {
+    let class_file = crate::classfile::ClassFile::parse(data)?;
+    let name = crate::classfile::binary_name(&class_file.name()?);
+    if class_file.access_flags & crate::classfile::ACC_MODULE == 0 {
+        return Ok(name);
+    }
+
+    let module = class_file
+        .find_attribute(&class_file.attributes, "Module")
+        .filter(|attribute| attribute.info.len() >= 2)
+        .map(|attribute| {
+            let index = u16::from_be_bytes([attribute.info[0], attribute.info[1]]);
+            match class_file.constant_pool.get(index)? {
+                crate::classfile::Constant::Module(name) => class_file.constant_pool.utf8(*name),
+                _ => Ok(String::new()),
+            }
+        })
+        .transpose()?
+        .unwrap_or_default();
+    Ok(format!("{name} ({module})"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sink::OutputSink;
+
+    #[test]
+    fn test_archives_read() {
+        let root = std::env::temp_dir().join(format!("b_agent_dump_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+
+        // a grouped dump, one jar per origin next to loose classes
+        let mut sink = crate::sink::DirectorySink::new(root.clone());
+        sink.write("B.class", &[0xCA]).unwrap();
+        sink.write("B.class.json", b"{}").unwrap();
+        let mut jar = crate::sink::JarSink::create(&root.join("app.jar")).unwrap();
+        jar.write("C.class", &[0xFE]).unwrap();
+        jar.finish().unwrap();
+        let classes = read_classes(&root).unwrap();
+        assert_eq!(
+            classes.keys().collect::<Vec<_>>(),
+            ["B.class", "app.jar/C.class"]
+        );
+
+        let tar_path = root.join("dump.tar");
+        let mut tar = crate::sink::TarSink::new(std::fs::File::create(&tar_path).unwrap());
+        tar.write("a/D.class", &[0xBE]).unwrap();
+        tar.finish().unwrap();
+        drop(tar);
+        assert_eq!(read_classes(&tar_path).unwrap()["a/D.class"], [0xBE]);
+        assert!(read_classes(&root.join("B.class.json")).is_err());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,261 @@
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+// ClassLoader.defineClass0 flag of classes defined as hidden
+pub const HIDDEN_CLASS: i32 = 0x2;
+
+// how a class generated at runtime was defined
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DefiningApi {
+    LambdaMetafactory,
+    LambdaForm,
+    Proxy,
+    // MethodHandles.Lookup.defineHiddenClass called by other code
+    DefineHiddenClass,
+    // MethodHandles.Lookup.defineClass
+    LookupDefineClass,
+    // Unsafe.defineAnonymousClass, jdk 14 and older
+    AnonymousClass,
+    // cglib, byte buddy, javassist and the like
+    Framework,
+    #[default]
+    Unknown,
+}
+
+// where a hidden, anonymous or generated class came from. such classes are saved under a
+// synthetic name that is the same across runs as long as the generated bytes are.
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct DynamicOrigin {
+    // name the jvm gave the class, counters and addresses in it change from run to run
+    pub runtime_name: String,
+    // lookup class of a hidden class, or the class a lambda or enhancer was generated for
+    pub host: Option<String>,
+    pub api: DefiningApi,
+    pub hidden: bool,
+}
+
+// name parts generated classes are recognized by, with the api they usually come from
+const MARKERS: [(&str, DefiningApi); 9] = [
+    ("$$Lambda", DefiningApi::LambdaMetafactory),
+    ("LambdaForm$", DefiningApi::LambdaForm),
+    ("$Proxy", DefiningApi::Proxy),
+    ("$$EnhancerBy", DefiningApi::Framework),
+    ("$$FastClassBy", DefiningApi::Framework),
+    ("$ByteBuddy$", DefiningApi::Framework),
+    ("$HibernateProxy$", DefiningApi::Framework),
+    ("$$$", DefiningApi::Framework),
+    ("_$$_jvst", DefiningApi::Framework),
+];
+
+// frames of the defining stack telling which api defined a class. generators come before
+// the lookup methods they call, so they are checked first.
+const DEFINERS: [(&str, DefiningApi); 6] = [
+    (
+        "java.lang.invoke.InnerClassLambdaMetafactory.",
+        DefiningApi::LambdaMetafactory,
+    ),
+    (
+        "java.lang.invoke.InvokerBytecodeGenerator.",
+        DefiningApi::LambdaForm,
+    ),
+    ("java.lang.reflect.Proxy", DefiningApi::Proxy),
+    (
+        "java.lang.invoke.MethodHandles$Lookup.defineHiddenClass",
+        DefiningApi::DefineHiddenClass,
+    ),
+    (
+        "java.lang.invoke.MethodHandles$Lookup.defineClass",
+        DefiningApi::LookupDefineClass,
+    ),
+    (
+        "sun.misc.Unsafe.defineAnonymousClass",
+        DefiningApi::AnonymousClass,
+    ),
+];
+
+// address or counter the jvm appends to hidden and anonymous classes after a slash, it is
+// removed before the name is converted to a binary name
+static SUFFIX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"/(0x[0-9a-fA-F]+|[0-9]+)$").unwrap());
+
+// counters and random suffixes of generated names
+static VARYING: LazyLock<Vec<(Regex, &str)>> = LazyLock::new(|| {
+    [
+        (r"(\$\$Lambda|\$Proxy)\$?[0-9]+", "$1"),
+        (
+            r"(\$\$(EnhancerBy|FastClassBy)[A-Za-z]*\$\$)[0-9a-fA-F]+",
+            "$1",
+        ),
+        (
+            r"(\$ByteBuddy\$|\$HibernateProxy\$|\$\$\$[A-Za-z]+\$)[A-Za-z0-9]+",
+            "$1",
+        ),
+        (r"(_\$\$_jvst)[0-9a-fA-F_]+", "$1"),
+    ]
+    .into_iter()
+    .map(|(pattern, replacement)| (Regex::new(pattern).unwrap(), replacement))
+    .collect()
+});
+
+// whether a class has the name of one generated at runtime
+pub fn is_generated(class_name: &str) -> bool {
+    MARKERS
+        .iter()
+        .any(|(marker, _)| class_name.contains(marker))
+}
+
+// the api that defined a class, from its defining stack or else its name
+pub fn defining_api(class_name: &str, stack: &[String], hidden: bool) -> DefiningApi {
+    let from_stack = DEFINERS
+        .iter()
+        .find(|(prefix, _)| stack.iter().any(|frame| frame.starts_with(prefix)))
+        .map(|(_, api)| *api);
+    let from_name = || {
+        MARKERS
+            .iter()
+            .find(|(marker, _)| class_name.contains(marker))
+            .map(|(_, api)| *api)
+    };
+    from_stack.or_else(from_name).unwrap_or(if hidden {
+        DefiningApi::DefineHiddenClass
+    } else {
+        DefiningApi::Unknown
+    })
+}
+
+// the class a lambda or enhanced class was generated for, named before the marker
+pub fn host_from_name(class_name: &str) -> Option<String> {
+    let name = crate::classfile::binary_name(class_name);
+    [
+        "$$Lambda",
+        "$$EnhancerBy",
+        "$$FastClassBy",
+        "$ByteBuddy$",
+        "$HibernateProxy$",
+    ]
+    .iter()
+    .find_map(|marker| name.find(marker))
+    .filter(|&end| end > 0)
+    .map(|end| name[..end].to_string())
+}
+
+// binary name without the parts that change from run to run
+pub fn stem(class_name: &str) -> String {
+    let mut stem = crate::classfile::binary_name(&SUFFIX.replace(class_name, ""));
+    for (pattern, replacement) in VARYING.iter() {
+        stem = pattern.replace_all(&stem, *replacement).into_owned();
+    }
+    stem
+}
+
+// name a generated class is saved under: its stem and a hash of its bytes, taken with the
+// name written in the bytes replaced by the stem so counters in it don't change the hash
+pub fn synthetic_name(class_data: &[u8]) -> Result<String, crate::error::Error> {
+    let class_file = crate::classfile::ClassFile::parse(class_data)?;
+    let name = class_file.name()?;
+    let stem = stem(&name);
+
+    let internal_stem = crate::classfile::internal_name(&stem);
+    let hashed = replace_bytes(class_data, name.as_bytes(), internal_stem.as_bytes());
+    let hash = crate::store::sha256_hex(&hashed);
+    let separator = if stem.ends_with('$') { "" } else { "$" };
+    Ok(format!("{stem}{separator}{}", &hash[..8]))
+}
+
+fn replace_bytes(data: &[u8], from: &[u8], to: &[u8]) -> Vec<u8> {
+    if from.is_empty() {
+        return data.to_vec();
+    }
+    let mut replaced = Vec::with_capacity(data.len());
+    let mut rest = data;
+    while let Some(position) = rest.windows(from.len()).position(|window| window == from) {
+        replaced.extend_from_slice(&rest[..position]);
+        replaced.extend_from_slice(to);
+        rest = &rest[position + from.len()..];
+    }
+    replaced.extend_from_slice(rest);
+    replaced
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generated_names() {
+        assert_eq!(
+            stem("com/a/Service$$Lambda$14/0x0000000800c02a00"),
+            "com.a.Service$$Lambda"
+        );
+        assert_eq!(
+            stem("com.a.Service$$Lambda/0x0000000800c02a00"),
+            "com.a.Service$$Lambda"
+        );
+        assert_eq!(
+            stem("java/lang/invoke/LambdaForm$MH/1234567"),
+            "java.lang.invoke.LambdaForm$MH"
+        );
+        assert_eq!(stem("jdk.proxy2.$Proxy12"), "jdk.proxy2.$Proxy");
+        assert_eq!(
+            stem("com.a.Repo$$EnhancerBySpringCGLIB$$1a2b3c4d"),
+            "com.a.Repo$$EnhancerBySpringCGLIB$$"
+        );
+        assert!(is_generated("com.a.Service$$Lambda$14"));
+        assert!(!is_generated("com.a.Service$Inner"));
+        assert_eq!(
+            host_from_name("com/a/Service$$Lambda$14").as_deref(),
+            Some("com.a.Service")
+        );
+        assert_eq!(host_from_name("jdk.proxy2.$Proxy12"), None);
+
+        let stack = [
+            "java.lang.ClassLoader.defineClass0(...) native".to_string(),
+            "java.lang.invoke.MethodHandles$Lookup.defineHiddenClass([BZ...) line 1".to_string(),
+            "java.lang.invoke.MethodHandles$Lookup$ClassDefiner.defineClass(Z...) line 2"
+                .to_string(),
+            "java.lang.invoke.InnerClassLambdaMetafactory.spinInnerClass()Ljava/lang/Class; line 3"
+                .to_string(),
+        ];
+        assert_eq!(
+            defining_api("com.a.Service$$Lambda", &stack, true),
+            DefiningApi::LambdaMetafactory
+        );
+        assert_eq!(
+            defining_api("com.a.Gen", &[], true),
+            DefiningApi::DefineHiddenClass
+        );
+
+        // the same lambda spun with another counter gets the same name
+        let lambda = |name: &str| {
+            let mut constant_pool = crate::classfile::ConstantPool::new();
+            let this_class = constant_pool.add_class(name).unwrap();
+            let super_class = constant_pool.add_class("java/lang/Object").unwrap();
+            crate::classfile::ClassFile {
+                minor_version: 0,
+                major_version: 52,
+                constant_pool,
+                access_flags: 0x1030,
+                this_class,
+                super_class,
+                interfaces: Vec::new(),
+                fields: Vec::new(),
+                methods: Vec::new(),
+                attributes: Vec::new(),
+            }
+            .to_bytes()
+        };
+        let first = synthetic_name(&lambda("com/a/Service$$Lambda$14")).unwrap();
+        assert!(first.starts_with("com.a.Service$$Lambda$"), "{first}");
+        assert_eq!(
+            first,
+            synthetic_name(&lambda("com/a/Service$$Lambda$15")).unwrap()
+        );
+        assert_ne!(
+            first,
+            synthetic_name(&lambda("com/a/Other$$Lambda$14")).unwrap()
+        );
+    }
+}
@@ -11,4 +11,22 @@ pub enum Error {
 
     #[error(transparent)]
     IO(#[from] std::io::Error),
+
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+
+    #[error("malformed class file: {0}")]
+    ClassFormat(String),
+
+    #[error("malformed dump stream: {0}")]
+    Stream(String),
+
+    #[error("invalid filter rule: {0}")]
+    Filter(String),
+
+    #[error(transparent)]
+    Sqlite(#[from] rusqlite::Error),
+
+    #[error(transparent)]
+    Zip(#[from] zip::result::ZipError),
 }
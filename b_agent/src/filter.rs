@@ -0,0 +1,224 @@
+use regex::Regex;
+
+use crate::config::{FilterAction, FilterConfig, FilterRule};
+
+// what is known about a class when the filter is asked
+#[derive(Debug, Default)]
+pub struct ClassInfo<'a> {
+    // binary or internal name
+    pub name: &'a str,
+    // as in ClassSource, "class@hash" or "bootstrap"
+    pub loader: Option<&'a str>,
+    pub module: Option<&'a str>,
+    pub code_source: Option<&'a str>,
+    // class file major version
+    pub version: Option<u16>,
+}
+
+impl<'a> ClassInfo<'a> {
+    pub fn new(name: &'a str, class_data: &[u8], source: &'a crate::recorder::ClassSource) -> Self {
+        ClassInfo {
+            name,
+            loader: source.loader.as_deref(),
+            module: source.module.as_deref(),
+            code_source: source.code_source.as_deref(),
+            version: class_data
+                .get(6..8)
+                .map(|version| u16::from_be_bytes([version[0], version[1]])),
+        }
+    }
+}
+
+struct Rule {
+    action: FilterAction,
+    class: Option<Regex>,
+    loader: Option<Regex>,
+    module: Option<Regex>,
+    code_source: Option<Regex>,
+    min_version: Option<u16>,
+    max_version: Option<u16>,
+}
+
+impl Rule {
+    fn new(rule: &FilterRule) -> Result<Self, crate::error::Error> {
+        let pattern = |pattern: &Option<String>| pattern.as_deref().map(compile).transpose();
+        Ok(Rule {
+            action: rule.action,
+            class: pattern(&rule.class)?,
+            loader: pattern(&rule.loader)?,
+            module: pattern(&rule.module)?,
+            code_source: pattern(&rule.code_source)?,
+            min_version: rule.min_version,
+            max_version: rule.max_version,
+        })
+    }
+
+    // None when the rule needs more than the class name and only the name is known
+    fn matches(&self, class: &ClassInfo, name: &str, name_only: bool) -> Option<bool> {
+        if let Some(pattern) = &self.class
+            && !pattern.is_match(name)
+        {
+            return Some(false);
+        }
+
+        let needs_source = self.loader.is_some()
+            || self.module.is_some()
+            || self.code_source.is_some()
+            || self.min_version.is_some()
+            || self.max_version.is_some();
+        if name_only && needs_source {
+            return None;
+        }
+
+        // the loader is matched by its class, without the identity hash
+        let loader = class
+            .loader
+            .map(|loader| loader.split_once('@').map_or(loader, |(class, _)| class));
+        let text = |pattern: &Option<Regex>, value: Option<&str>| {
+            pattern
+                .as_ref()
+                .is_none_or(|pattern| value.is_some_and(|value| pattern.is_match(value)))
+        };
+        let version = |bound: Option<u16>, check: fn(u16, u16) -> bool| {
+            bound.is_none_or(|bound| class.version.is_some_and(|version| check(version, bound)))
+        };
+        Some(
+            text(&self.loader, loader)
+                && text(&self.module, class.module)
+                && text(&self.code_source, class.code_source)
+                && version(self.min_version, |version, min| version >= min)
+                && version(self.max_version, |version, max| version <= max),
+        )
+    }
+}
+
+// compiled filter rules of the config
+pub struct ClassFilter {
+    rules: Vec<Rule>,
+    default: FilterAction,
+}
+
+impl ClassFilter {
+    pub fn new(config: &FilterConfig) -> Result<Self, crate::error::Error> {
+        Ok(ClassFilter {
+            rules: config
+                .rules
+                .iter()
+                .map(Rule::new)
+                .collect::<Result<_, _>>()?,
+            default: config.default,
+        })
+    }
+
+    // whether a class with everything known about it gets saved
+    pub fn accepts(&self, class: &ClassInfo) -> bool {
+        self.decide(class, false)
+    }
+
+    // whether a class known only by name may get saved, e.g. before it is queued.
+    // classes that depend on rules needing more than the name are kept for the hook to decide.
+    pub fn may_accept(&self, class_name: &str) -> bool {
+        self.decide(
+            &ClassInfo {
+                name: class_name,
+                ..Default::default()
+            },
+            true,
+        )
+    }
+
+    fn decide(&self, class: &ClassInfo, name_only: bool) -> bool {
+        let name = crate::classfile::binary_name(class.name);
+        for rule in &self.rules {
+            match rule.matches(class, &name, name_only) {
+                Some(true) => return rule.action == FilterAction::Include,
+                Some(false) => {}
+                None => return true,
+            }
+        }
+        self.default == FilterAction::Include
+    }
+}
+
+// a glob or "regex:" pattern matching the whole value
+pub fn compile(pattern: &str) -> Result<Regex, crate::error::Error> {
+    let expression = match pattern.strip_prefix("regex:") {
+        Some(expression) => format!("^(?:{expression})$"),
+        None => {
+            let mut expression = String::from("^");
+            let mut chars = pattern.chars().peekable();
+            while let Some(c) = chars.next() {
+                match c {
+                    '*' if chars.peek() == Some(&'*') => {
+                        chars.next();
+                        expression.push_str(".*");
+                    }
+                    '*' => expression.push_str("[^./]*"),
+                    '?' => expression.push_str("[^./]"),
+                    c => expression.push_str(&regex::escape(&c.to_string())),
+                }
+            }
+            expression.push('$');
+            expression
+        }
+    };
+    Regex::new(&expression).map_err(|e| crate::error::Error::Filter(format!("{pattern}: {e}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ordered_rules() {
+        let config: FilterConfig = serde_json::from_str(
+            r#"{
+                "rules": [
+                    {"action": "include", "class": "java.util.concurrent.*"},
+                    {"action": "exclude", "class": "java.**"},
+                    {"action": "exclude", "class": "com.a.**", "loader": "regex:.*AppClassLoader"},
+                    {"action": "exclude", "max_version": 50}
+                ]
+            }"#,
+        )
+        .unwrap();
+        let filter = ClassFilter::new(&config).unwrap();
+        let source = crate::recorder::ClassSource {
+            loader: Some("jdk.internal.loader.ClassLoaders$AppClassLoader@1b6d3586".to_string()),
+            ..Default::default()
+        };
+        let class_data = [0xCA, 0xFE, 0xBA, 0xBE, 0, 0, 0, 52];
+
+        assert!(filter.accepts(&ClassInfo::new(
+            "java/util/concurrent/Future",
+            &class_data,
+            &source
+        )));
+        assert!(!filter.accepts(&ClassInfo::new(
+            "java/util/concurrent/atomic/AtomicLong",
+            &class_data,
+            &source
+        )));
+        assert!(!filter.accepts(&ClassInfo::new("com/a/B", &class_data, &source)));
+        assert!(filter.accepts(&ClassInfo::new("com/b/C", &class_data, &source)));
+        assert!(!filter.accepts(&ClassInfo::new(
+            "com/b/C",
+            &[0xCA, 0xFE, 0xBA, 0xBE, 0, 0, 0, 49],
+            &source
+        )));
+
+        // only the name is known before a class is queued
+        assert!(!filter.may_accept("java.lang.String"));
+        assert!(filter.may_accept("com.a.B"));
+        assert!(filter.may_accept("com.b.C"));
+
+        let invalid = FilterConfig {
+            rules: vec![FilterRule {
+                class: Some("regex:(".to_string()),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        assert!(ClassFilter::new(&invalid).is_err());
+    }
+}
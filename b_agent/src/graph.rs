@@ -0,0 +1,309 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write as _,
+};
+
+use crate::classfile::binary_name;
+
+// why one class depends on another, as reported by the retransformer
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum EdgeKind {
+    Extends,
+    Implements,
+    FieldType,
+    Signature,
+    Throws,
+    Annotation,
+    Instantiates,
+    TypeCheck,
+    FieldAccess,
+    Calls,
+    ClassLiteral,
+    Catches,
+    // retransformers that only report class names
+    Unknown,
+}
+
+impl EdgeKind {
+    pub fn parse(kind: &str) -> Self {
+        match kind {
+            "extends" => EdgeKind::Extends,
+            "implements" => EdgeKind::Implements,
+            "field_type" => EdgeKind::FieldType,
+            "signature" => EdgeKind::Signature,
+            "throws" => EdgeKind::Throws,
+            "annotation" => EdgeKind::Annotation,
+            "instantiates" => EdgeKind::Instantiates,
+            "type_check" => EdgeKind::TypeCheck,
+            "field_access" => EdgeKind::FieldAccess,
+            "calls" => EdgeKind::Calls,
+            "class_literal" => EdgeKind::ClassLiteral,
+            "catches" => EdgeKind::Catches,
+            _ => EdgeKind::Unknown,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            EdgeKind::Extends => "extends",
+            EdgeKind::Implements => "implements",
+            EdgeKind::FieldType => "field_type",
+            EdgeKind::Signature => "signature",
+            EdgeKind::Throws => "throws",
+            EdgeKind::Annotation => "annotation",
+            EdgeKind::Instantiates => "instantiates",
+            EdgeKind::TypeCheck => "type_check",
+            EdgeKind::FieldAccess => "field_access",
+            EdgeKind::Calls => "calls",
+            EdgeKind::ClassLiteral => "class_literal",
+            EdgeKind::Catches => "catches",
+            EdgeKind::Unknown => "unknown",
+        }
+    }
+}
+
+#[derive(Debug, serde::Serialize)]
+struct JsonEdge<'a> {
+    from: &'a str,
+    to: &'a str,
+    kinds: &'a BTreeSet<EdgeKind>,
+    // number of class level edges folded into this one
+    weight: usize,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct JsonGraph<'a> {
+    nodes: &'a BTreeSet<String>,
+    edges: Vec<JsonEdge<'a>>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct SavedEdge {
+    from: String,
+    to: String,
+    kinds: BTreeSet<EdgeKind>,
+    weight: usize,
+}
+
+// a graph as written by to_json
+#[derive(Debug, serde::Deserialize)]
+struct SavedGraph {
+    nodes: BTreeSet<String>,
+    edges: Vec<SavedEdge>,
+}
+
+// directed graph from a class to the classes it depends on
+#[derive(Debug, Default)]
+pub struct DependencyGraph {
+    nodes: BTreeSet<String>,
+    edges: BTreeMap<(String, String), (BTreeSet<EdgeKind>, usize)>,
+}
+
+impl DependencyGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // read back a graph written by to_json
+    pub fn from_json(data: &[u8]) -> Result<Self, crate::error::Error> {
+        let saved: SavedGraph = serde_json::from_slice(data)?;
+        Ok(DependencyGraph {
+            nodes: saved.nodes,
+            edges: saved
+                .edges
+                .into_iter()
+                .map(|edge| ((edge.from, edge.to), (edge.kinds, edge.weight)))
+                .collect(),
+        })
+    }
+
+    // names are kept in binary form, hooked classes come in internal form and their
+    // dependencies in binary form
+    pub fn add_node(&mut self, name: &str) {
+        self.nodes.insert(binary_name(name));
+    }
+
+    // self references are dropped
+    pub fn add_edge(&mut self, from: &str, to: &str, kind: EdgeKind) {
+        let (from, to) = (binary_name(from), binary_name(to));
+        if from == to {
+            return;
+        }
+
+        self.add_node(&from);
+        self.add_node(&to);
+        let (kinds, weight) = self.edges.entry((from, to)).or_default();
+        kinds.insert(kind);
+        *weight = 1;
+    }
+
+    // drop the outgoing edges of a class, before the edges of a new version are added
+    pub fn remove_edges_from(&mut self, from: &str) {
+        let from = binary_name(from);
+        self.edges.retain(|(edge_from, _), _| *edge_from != from);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    // fold classes into their packages, edge weights count the class edges in between
+    pub fn packages(&self) -> DependencyGraph {
+        let mut packages = DependencyGraph::new();
+        for node in &self.nodes {
+            packages.add_node(package_of(node));
+        }
+        for ((from, to), (kinds, weight)) in &self.edges {
+            let (from, to) = (package_of(from), package_of(to));
+            if from == to {
+                continue;
+            }
+
+            let (package_kinds, package_weight) = packages
+                .edges
+                .entry((from.to_string(), to.to_string()))
+                .or_default();
+            package_kinds.extend(kinds);
+            *package_weight += weight;
+        }
+        packages
+    }
+
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph dependencies {\n");
+        for node in &self.nodes {
+            let _ = writeln!(out, "    {};", dot_id(node));
+        }
+        for ((from, to), (kinds, weight)) in &self.edges {
+            let _ = writeln!(
+                out,
+                "    {} -> {} [label={}, weight={weight}];",
+                dot_id(from),
+                dot_id(to),
+                dot_id(&kind_names(kinds))
+            );
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    pub fn to_graphml(&self) -> String {
+        let mut out = String::from(concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n",
+            "  <key id=\"kinds\" for=\"edge\" attr.name=\"kinds\" attr.type=\"string\"/>\n",
+            "  <key id=\"weight\" for=\"edge\" attr.name=\"weight\" attr.type=\"int\"/>\n",
+            "  <graph id=\"dependencies\" edgedefault=\"directed\">\n",
+        ));
+        for node in &self.nodes {
+            let _ = writeln!(out, "    <node id=\"{}\"/>", xml_escape(node));
+        }
+        for ((from, to), (kinds, weight)) in &self.edges {
+            let _ = writeln!(
+                out,
+                "    <edge source=\"{}\" target=\"{}\"><data key=\"kinds\">{}</data><data key=\"weight\">{weight}</data></edge>",
+                xml_escape(from),
+                xml_escape(to),
+                kind_names(kinds)
+            );
+        }
+        out.push_str("  </graph>\n</graphml>\n");
+        out
+    }
+
+    pub fn to_json(&self) -> Result<String, crate::error::Error> {
+        let graph = JsonGraph {
+            nodes: &self.nodes,
+            edges: self
+                .edges
+                .iter()
+                .map(|((from, to), (kinds, weight))| JsonEdge {
+                    from,
+                    to,
+                    kinds,
+                    weight: *weight,
+                })
+                .collect(),
+        };
+
+        Ok(serde_json::to_string_pretty(&graph)?)
+    }
+
+    // write <name>.dot, <name>.graphml and <name>.json to the top of a dump
+    pub fn write_all(
+        &self,
+        sink: &mut dyn crate::sink::OutputSink,
+        name: &str,
+    ) -> Result<(), crate::error::Error> {
+        sink.write(&format!("{name}.dot"), self.to_dot().as_bytes())?;
+        sink.write(&format!("{name}.graphml"), self.to_graphml().as_bytes())?;
+        sink.write(&format!("{name}.json"), self.to_json()?.as_bytes())?;
+        Ok(())
+    }
+}
+
+// package of a binary class name, classes in the unnamed package go to "<default>"
+fn package_of(class_name: &str) -> &str {
+    match class_name.rfind('.') {
+        Some(index) => &class_name[..index],
+        None => "<default>",
+    }
+}
+
+fn kind_names(kinds: &BTreeSet<EdgeKind>) -> String {
+    kinds
+        .iter()
+        .map(EdgeKind::name)
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn dot_id(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn xml_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_package_aggregation() {
+        let mut graph = DependencyGraph::new();
+        graph.add_edge("a.A", "b.B", EdgeKind::Calls);
+        graph.add_edge("a.A", "b.B", EdgeKind::Extends);
+        graph.add_edge("a.C", "b.D", EdgeKind::FieldType);
+        graph.add_edge("a.A", "a.C", EdgeKind::Calls);
+        graph.add_edge("a.A", "a.A", EdgeKind::Calls);
+
+        assert!(
+            graph
+                .to_dot()
+                .contains("\"a.A\" -> \"b.B\" [label=\"extends,calls\"")
+        );
+
+        let restored = DependencyGraph::from_json(graph.to_json().unwrap().as_bytes()).unwrap();
+        assert_eq!(restored.to_dot(), graph.to_dot());
+
+        let packages = graph.packages();
+        assert_eq!(packages.nodes.len(), 2);
+        assert_eq!(packages.edges.len(), 1);
+        let (kinds, weight) = &packages.edges[&("a".to_string(), "b".to_string())];
+        assert_eq!(kinds.len(), 3);
+        assert_eq!(*weight, 2);
+
+        graph.remove_edges_from("a.A");
+        assert_eq!(graph.edges.len(), 1);
+        assert!(graph.nodes.contains("a.A"));
+    }
+}
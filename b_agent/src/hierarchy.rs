@@ -0,0 +1,310 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    path::Path,
+};
+
+use crate::classfile::{ACC_PRIVATE, ACC_STATIC, ClassFile, binary_name};
+
+// file name of the index inside a dump directory
+pub const INDEX_FILE_NAME: &str = "hierarchy.json";
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ClassEntry {
+    pub super_class: Option<String>,
+    pub interfaces: Vec<String>,
+    pub is_interface: bool,
+    // methods that can be overridden, as name + descriptor
+    pub methods: BTreeSet<String>,
+}
+
+impl ClassEntry {
+    pub fn from_class_file(class_file: &ClassFile) -> Result<Self, crate::error::Error> {
+        let mut methods = BTreeSet::new();
+        for method in &class_file.methods {
+            let name = class_file.member_name(method)?;
+            if method.access_flags & (ACC_STATIC | ACC_PRIVATE) != 0 || name.starts_with('<') {
+                continue;
+            }
+            methods.insert(format!("{name}{}", class_file.member_descriptor(method)?));
+        }
+
+        Ok(ClassEntry {
+            super_class: class_file.super_name()?.as_deref().map(binary_name),
+            interfaces: class_file
+                .interface_names()?
+                .iter()
+                .map(|name| binary_name(name))
+                .collect(),
+            is_interface: class_file.is_interface(),
+            methods,
+        })
+    }
+}
+
+// where a method is declared above and below a class
+#[derive(Debug, Default, serde::Serialize)]
+pub struct Overrides {
+    // supertype methods the class overrides
+    pub overridden: Vec<String>,
+    // subtype methods overriding the class
+    pub overriding: Vec<String>,
+}
+
+// supertypes and subtypes of dumped classes, keyed by binary class name.
+// classes outside the dump only show up as supertypes of dumped ones.
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct HierarchyIndex {
+    classes: BTreeMap<String, ClassEntry>,
+    // direct subclasses, subinterfaces and implementors
+    subtypes: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl HierarchyIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // index every class of a dump, classes that don't parse are skipped
+    pub fn from_classes(classes: &BTreeMap<String, Vec<u8>>) -> Self {
+        let mut index = HierarchyIndex::new();
+        for data in classes.values() {
+            let _ = index.add(data);
+        }
+        index
+    }
+
+    // the saved index of a dump directory, or one built from its classes
+    pub fn load_or_build(dir: &Path) -> Result<Self, crate::error::Error> {
+        match std::fs::read(dir.join(INDEX_FILE_NAME)) {
+            Ok(data) => Ok(serde_json::from_slice(&data)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                Ok(Self::from_classes(&crate::dump::read_classes(dir)?))
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn to_json(&self) -> Result<Vec<u8>, crate::error::Error> {
+        Ok(serde_json::to_vec_pretty(self)?)
+    }
+
+    pub fn add(&mut self, class_data: &[u8]) -> Result<(), crate::error::Error> {
+        let class_file = ClassFile::parse(class_data)?;
+        let name = binary_name(&class_file.name()?);
+        self.insert(name, ClassEntry::from_class_file(&class_file)?);
+        Ok(())
+    }
+
+    // a class seen again replaces its previous entry
+    pub fn insert(&mut self, name: String, entry: ClassEntry) {
+        if let Some(previous) = self.classes.remove(&name) {
+            for supertype in previous.super_class.iter().chain(&previous.interfaces) {
+                if let Some(subtypes) = self.subtypes.get_mut(supertype) {
+                    subtypes.remove(&name);
+                }
+            }
+        }
+
+        for supertype in entry.super_class.iter().chain(&entry.interfaces) {
+            self.subtypes
+                .entry(supertype.clone())
+                .or_default()
+                .insert(name.clone());
+        }
+        self.classes.insert(name, entry);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ClassEntry> {
+        self.classes.get(name)
+    }
+
+    // superclasses from the direct one up, ending at the first class outside the dump
+    pub fn superclass_chain(&self, name: &str) -> Vec<String> {
+        let mut chain = Vec::new();
+        let mut current = self.classes.get(name);
+        while let Some(super_class) = current.and_then(|entry| entry.super_class.as_ref()) {
+            // guards against cycles in broken dumps
+            if chain.contains(super_class) || super_class == name {
+                break;
+            }
+            chain.push(super_class.clone());
+            current = self.classes.get(super_class);
+        }
+        chain
+    }
+
+    // every supertype, nearest first
+    pub fn supertypes(&self, name: &str) -> Vec<String> {
+        let mut found = Vec::new();
+        let mut seen = BTreeSet::from([name.to_string()]);
+        let mut pending = VecDeque::from([name.to_string()]);
+        while let Some(current) = pending.pop_front() {
+            let Some(entry) = self.classes.get(&current) else {
+                continue;
+            };
+            for supertype in entry.super_class.iter().chain(&entry.interfaces) {
+                if seen.insert(supertype.clone()) {
+                    found.push(supertype.clone());
+                    pending.push_back(supertype.clone());
+                }
+            }
+        }
+        found
+    }
+
+    // every interface the class implements, directly or through supertypes
+    pub fn interfaces(&self, name: &str) -> BTreeSet<String> {
+        let mut found = BTreeSet::new();
+        let mut pending = self.superclass_chain(name);
+        pending.push(name.to_string());
+        while let Some(current) = pending.pop() {
+            let Some(entry) = self.classes.get(&current) else {
+                continue;
+            };
+            for interface in &entry.interfaces {
+                if found.insert(interface.clone()) {
+                    pending.push(interface.clone());
+                }
+            }
+        }
+        found
+    }
+
+    // every dumped class below the given type
+    pub fn subtypes(&self, name: &str) -> BTreeSet<String> {
+        let mut found = BTreeSet::new();
+        let mut pending = vec![name.to_string()];
+        while let Some(current) = pending.pop() {
+            for subtype in self.subtypes.get(&current).into_iter().flatten() {
+                if subtype != name && found.insert(subtype.clone()) {
+                    pending.push(subtype.clone());
+                }
+            }
+        }
+        found
+    }
+
+    // concrete classes and abstract classes implementing an interface, directly or not
+    pub fn implementors(&self, name: &str) -> BTreeSet<String> {
+        self.subtypes(name)
+            .into_iter()
+            .filter(|subtype| {
+                self.classes
+                    .get(subtype)
+                    .is_some_and(|entry| !entry.is_interface)
+            })
+            .collect()
+    }
+
+    // methods of a class matching a name, or a name with descriptor
+    pub fn methods(&self, name: &str, method: &str) -> Vec<String> {
+        self.classes
+            .get(name)
+            .map(|entry| {
+                entry
+                    .methods
+                    .iter()
+                    .filter(|declared| method_matches(declared, method))
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    // supertypes and subtypes declaring the same method, as Class.name(descriptor)
+    pub fn overrides(&self, name: &str, method: &str) -> Overrides {
+        let mut overrides = Overrides::default();
+        for declared in self.methods(name, method) {
+            for supertype in self.supertypes(name) {
+                if self.declares(&supertype, &declared) {
+                    overrides.overridden.push(format!("{supertype}.{declared}"));
+                }
+            }
+            for subtype in self.subtypes(name) {
+                if self.declares(&subtype, &declared) {
+                    overrides.overriding.push(format!("{subtype}.{declared}"));
+                }
+            }
+        }
+        overrides
+    }
+
+    fn declares(&self, name: &str, method: &str) -> bool {
+        self.classes
+            .get(name)
+            .is_some_and(|entry| entry.methods.contains(method))
+    }
+}
+
+fn method_matches(declared: &str, method: &str) -> bool {
+    if method.contains('(') {
+        return declared == method;
+    }
+    declared
+        .split_once('(')
+        .is_some_and(|(declared_name, _)| declared_name == method)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(
+        super_class: &str,
+        interfaces: &[&str],
+        is_interface: bool,
+        methods: &[&str],
+    ) -> ClassEntry {
+        ClassEntry {
+            super_class: Some(super_class.to_string()),
+            interfaces: interfaces.iter().map(|name| name.to_string()).collect(),
+            is_interface,
+            methods: methods.iter().map(|name| name.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_queries() {
+        let mut index = HierarchyIndex::new();
+        index.insert(
+            "a.Filter".to_string(),
+            entry("java.lang.Object", &[], true, &["doFilter()V"]),
+        );
+        index.insert(
+            "a.Chained".to_string(),
+            entry("java.lang.Object", &["a.Filter"], true, &[]),
+        );
+        index.insert(
+            "a.Base".to_string(),
+            entry("java.lang.Object", &["a.Chained"], false, &["doFilter()V"]),
+        );
+        index.insert(
+            "a.Impl".to_string(),
+            entry("a.Base", &[], false, &["doFilter()V", "other()V"]),
+        );
+
+        assert_eq!(
+            index.superclass_chain("a.Impl"),
+            ["a.Base", "java.lang.Object"]
+        );
+        assert_eq!(
+            index.implementors("a.Filter"),
+            BTreeSet::from(["a.Base".to_string(), "a.Impl".to_string()])
+        );
+        assert!(index.interfaces("a.Impl").contains("a.Filter"));
+
+        let overrides = index.overrides("a.Base", "doFilter");
+        assert_eq!(overrides.overridden, ["a.Filter.doFilter()V"]);
+        assert_eq!(overrides.overriding, ["a.Impl.doFilter()V"]);
+
+        // a redefined class moves in the hierarchy
+        index.insert(
+            "a.Impl".to_string(),
+            entry("java.lang.Object", &[], false, &[]),
+        );
+        assert_eq!(
+            index.implementors("a.Filter"),
+            BTreeSet::from(["a.Base".to_string()])
+        );
+    }
+}
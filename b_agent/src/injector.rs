@@ -2,19 +2,30 @@ use jvmti::environment::jvmti::JVMTI;
 use jvmti::{
     environment::{Environment, jni::JNIEnvironment, jvmti::JVMTIEnvironment},
     native::{
-        JNIEnvPtr, JVMTIEnvPtr, JavaClass, JavaObject, RawString,
+        JNIEnvPtr, JVMTIEnvPtr, JavaClass, JavaObject, JavaThread, RawString,
         jvmti_native::{jint, jvmtiEventCallbacks},
     },
 };
 use libc::{c_char, c_uchar};
+use std::cell::{Cell, RefCell};
 use std::collections::{HashMap, HashSet};
-use std::sync::LazyLock;
+use std::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+use std::sync::{Arc, LazyLock};
 use std::{ffi::CStr, ptr::copy_nonoverlapping, sync::Mutex};
 
 use crate::console::{alloc_console, free_console};
 
 const CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_millis(100);
 
+// lookups of a queued class before it is given up as unresolved
+const MAX_FIND_ATTEMPTS: usize = 100;
+
+// frames kept of the stack a class was loaded from
+const MAX_STACK_DEPTH: usize = 64;
+
+// bytes as loaded kept per thread for the load hook, loads nest when transformers load classes
+const MAX_PENDING_ORIGINALS: usize = 16;
+
 pub trait ClientTrait: Send + Sync + 'static {
     fn new() -> Self
     where
@@ -25,6 +36,26 @@ pub trait ClientTrait: Send + Sync + 'static {
     fn retransformer_class_name(&self) -> &str;
 
     fn retransform_method_name(&self) -> &str;
+
+    // optional method of the retransformer class returning "<kind> <class name>" entries,
+    // used to label edges of the dependency graph
+    fn dependency_edges_method_name(&self) -> Option<&str> {
+        None
+    }
+
+    // whether transform may change classes, they are only parsed for it when it does
+    fn transforms(&self) -> bool {
+        false
+    }
+
+    // modify a class before it gets defined, return true if it was changed.
+    // frames of changed classes are recomputed against the target jvm.
+    fn transform(
+        &self,
+        _class_file: &mut crate::classfile::ClassFile,
+    ) -> Result<bool, crate::error::Error> {
+        Ok(false)
+    }
 }
 
 // global bridge instance
@@ -33,12 +64,66 @@ static BRIDGE: Mutex<Option<crate::bridge::JavaBridge>> = Mutex::new(None);
 // global client instance
 static CLIENT: Mutex<Option<Box<dyn ClientTrait>>> = Mutex::new(None);
 
+// whether the client transforms classes, read by the hook without locking the client
+static TRANSFORMS: AtomicBool = AtomicBool::new(false);
+
+// saves and analyzes hooked classes off the loading threads while the hook is enabled
+static PIPELINE: Mutex<Option<crate::pipeline::Pipeline>> = Mutex::new(None);
+
+// decides which hooked and queued classes are dumped, read by the loading threads
+static FILTER: Mutex<Option<Arc<crate::filter::ClassFilter>>> = Mutex::new(None);
+
+// hooked classes the filter rejected
+static FILTERED: AtomicUsize = AtomicUsize::new(0);
+
+// which hooked classes are dumped and which of their dependencies are queued
+static CRAWL: Mutex<Option<crate::crawl::Crawl>> = Mutex::new(None);
+
+// hooked classes not reached from a seed
+static OUTSIDE_CRAWL: AtomicUsize = AtomicUsize::new(0);
+
 static CLASSES_TO_LOAD: LazyLock<Mutex<HashSet<String>>> =
     LazyLock::new(|| Mutex::new(HashSet::new()));
 
+// ClassLoader.defineClass0 as bound before the agent wrapped it to capture hidden classes,
+// with the jvmti environment reading where they came from
+static DEFINE_CLASS0: Mutex<Option<crate::jvm::DefineClass0>> = Mutex::new(None);
+static DEFINE_CLASS0_JVMTI: AtomicPtr<jvmti::native::jvmti_native::jvmtiEnv> =
+    AtomicPtr::new(std::ptr::null_mut());
+static CAPTURE_HIDDEN: AtomicBool = AtomicBool::new(false);
+
+// calls of the defineClass0 wrapper in progress, the dll is only unloaded once they returned
+static DEFINING: AtomicUsize = AtomicUsize::new(0);
+
+// set when the agent was loaded at vm start with -agentpath. classes are then captured as they
+// are defined by the startup environment, the hook of the main one only sees retransformations.
+static STARTUP: AtomicBool = AtomicBool::new(false);
+static STARTUP_JVM: AtomicPtr<jni::sys::JavaVM> = AtomicPtr::new(std::ptr::null_mut());
+static STARTUP_JVMTI: AtomicPtr<jvmti::native::jvmti_native::jvmtiEnv> =
+    AtomicPtr::new(std::ptr::null_mut());
+
+// name and bytes of a class defined before the agent was ready
+type EarlyClass = (String, Vec<u8>);
+
+// classes defined before the agent was ready, None once it took them. jni can't be used before
+// VMInit and the load context is read through it, so they are kept without.
+static EARLY_CLASSES: Mutex<Option<Vec<EarlyClass>>> = Mutex::new(Some(Vec::new()));
+
+// agent started at VMInit, joined on VMDeath so the dump is finished before the vm exits
+static STARTUP_AGENT: Mutex<Option<std::thread::JoinHandle<()>>> = Mutex::new(None);
+static VM_DEATH: AtomicBool = AtomicBool::new(false);
+
 static LOADED_CLASSES: LazyLock<Mutex<HashSet<String>>> =
     LazyLock::new(|| Mutex::new(HashSet::new()));
 
+thread_local! {
+    // set on the thread running the agent, hooks there are the agent's own retransforms
+    static AGENT_THREAD: Cell<bool> = const { Cell::new(false) };
+    // classes as loaded, captured by the environment without retransform capability and
+    // taken by the load hook of the same load
+    static ORIGINALS: RefCell<Vec<(String, Vec<u8>)>> = const { RefCell::new(Vec::new()) };
+}
+
 #[cfg(target_os = "windows")]
 unsafe fn exit_dll() {
     use winapi::um::libloaderapi::{FreeLibraryAndExitThread, GetModuleHandleA};
@@ -53,17 +138,90 @@ unsafe fn exit_dll() {
     }
 }
 
+// keep the dll loaded until the process exits, FreeLibraryAndExitThread then only ends the thread
+#[cfg(target_os = "windows")]
+unsafe fn pin_dll() -> Result<(), crate::error::Error> {
+    use winapi::um::libloaderapi::{
+        GET_MODULE_HANDLE_EX_FLAG_FROM_ADDRESS, GET_MODULE_HANDLE_EX_FLAG_PIN, GetModuleHandleExW,
+    };
+
+    let mut module = std::ptr::null_mut();
+    let pinned = unsafe {
+        GetModuleHandleExW(
+            GET_MODULE_HANDLE_EX_FLAG_PIN | GET_MODULE_HANDLE_EX_FLAG_FROM_ADDRESS,
+            pin_dll as *const u16,
+            &mut module,
+        )
+    };
+    if pinned == 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
 pub struct BAgentInjector {
     jvm: jni::JavaVM,
     jvmti: jvmti::environment::jvmti::JVMTIEnvironment,
     jvmti_raw: jvmti::native::JVMTIEnvPtr,
+    snapshot: crate::config::SnapshotConfig,
+    watch: crate::config::WatchConfig,
+    hidden_classes: crate::config::HiddenClassesConfig,
+    // sees classes before agents able to retransform, when instrumentation is captured
+    original_jvmti: Option<jvmti::environment::jvmti::JVMTIEnvironment>,
+}
+
+// written to snapshot.json when the loaded classes were retransformed after attach
+#[derive(Debug, Default, serde::Serialize)]
+struct SnapshotReport {
+    loaded: usize,
+    // arrays, primitives and classes the jvm doesn't allow to modify
+    skipped: usize,
+    // rejected by the filter by name or not matching the crawl seeds, they are not retransformed
+    filtered: usize,
+    retransformed: usize,
+    failed: Vec<String>,
+}
+
+// written to watch.json once watching stopped
+#[derive(Debug, serde::Serialize)]
+struct WatchReport {
+    stopped_by: StopReason,
+    watched_secs: u64,
+    // saved when the queue first drained and watching started
+    saved_before: usize,
+    saved: usize,
+    // queued classes left for the next attach
+    pending: usize,
+}
+
+#[derive(Clone, Copy, Debug, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+enum StopReason {
+    Command,
+    Duration,
+    Signal,
+    VmDeath,
 }
 
 impl Drop for BAgentInjector {
     fn drop(&mut self) {
+        // left bound when the agent stopped on an error
+        if let Err(e) = self.release_define_class0() {
+            println!("failed to restore ClassLoader.defineClass0: {e}");
+        }
         unsafe { self.jvm.detach_current_thread() };
+        if let Some(pipeline) = PIPELINE.lock().unwrap().take() {
+            pipeline.shutdown();
+        }
         BRIDGE.lock().unwrap().take();
         CLIENT.lock().unwrap().take();
+        FILTER.lock().unwrap().take();
+        CRAWL.lock().unwrap().take();
+
+        // the jvm loaded the dll and keeps calling into it, the console is shared with it
+        if STARTUP.load(Ordering::SeqCst) {
+            return;
+        }
 
         println!("You may close this window now.");
         unsafe {
@@ -79,7 +237,68 @@ impl BAgentInjector {
     pub fn run(client: impl ClientTrait) -> Result<Self, crate::error::Error> {
         unsafe { alloc_console()? }
 
-        let jvm = crate::jvm::get_jvm()?;
+        Self::start(crate::jvm::get_jvm()?, Box::new(client))
+    }
+
+    // loaded with -agentpath: classes are captured from vm start on and the rest of the agent
+    // starts at VMInit. nothing here may call jni.
+    pub fn on_load(
+        client: impl ClientTrait,
+        jvm_ptr: *mut jni::sys::JavaVM,
+    ) -> Result<(), crate::error::Error> {
+        // set first, the thread started when the dll was loaded leaves the agent to this mode
+        STARTUP.store(true, Ordering::SeqCst);
+        unsafe { alloc_console()? }
+
+        // early classes are filtered by name before they are buffered
+        let config = crate::config::Config::load()?;
+        let filter = crate::filter::ClassFilter::new(&config.filter)?;
+        FILTER.lock().unwrap().replace(Arc::new(filter));
+        // taken back by the agent started at VMInit
+        CLIENT.lock().unwrap().replace(Box::new(client));
+        STARTUP_JVM.store(jvm_ptr, Ordering::SeqCst);
+
+        // without retransform capability, so its hook gets the bytes as passed to defineClass
+        // before agents able to retransform changed them
+        let callbacks = jvmtiEventCallbacks {
+            ClassFileLoadHook: Some(local_cb_startup_class_file_load_hook),
+            VMInit: Some(local_cb_vm_init),
+            VMDeath: Some(local_cb_vm_death),
+            ..Default::default()
+        };
+        let jvmti_raw = unsafe { callback_env(jvm_ptr, &callbacks)? };
+        STARTUP_JVMTI.store(jvmti_raw, Ordering::SeqCst);
+        let mut jvmti = jvmti::environment::jvmti::JVMTIEnvironment::new(jvmti_raw);
+        // lines of the load stacks read through it once the agent is ready
+        let mut capabilities = jvmti.get_capabilities();
+        capabilities.can_get_line_numbers = true;
+        if let Err(e) = jvmti.add_capabilities(&capabilities) {
+            return Err(crate::error::Error::JVMTI(jvmti::error::translate_error(
+                &e,
+            )));
+        }
+        jvmti.set_event_notification_mode(jvmti::event::VMEvent::VMInit, true);
+        jvmti.set_event_notification_mode(jvmti::event::VMEvent::VMDeath, true);
+        jvmti.set_event_notification_mode(jvmti::event::VMEvent::ClassFileLoadHook, true);
+
+        println!("Capturing classes from vm start...");
+        Ok(())
+    }
+
+    // whether the agent was loaded with -agentpath
+    pub fn is_startup() -> bool {
+        STARTUP.load(Ordering::SeqCst)
+    }
+
+    fn start(jvm: jni::JavaVM, client: Box<dyn ClientTrait>) -> Result<Self, crate::error::Error> {
+        let config = crate::config::Config::load()?;
+        let pipeline_config = config.pipeline.clone();
+        let snapshot = config.snapshot.clone();
+        let watch = config.watch.clone();
+        let hidden_classes = config.hidden_classes.clone();
+        let capture_original = config.instrumentation.enabled;
+        let filter = crate::filter::ClassFilter::new(&config.filter)?;
+        let crawl = crate::crawl::Crawl::new(&config.crawl)?;
         // NOTE: _env is not used, but it is required to keep the thread attached to the JVM
         let mut _env = jvm.attach_current_thread()?;
         let jvm_ptr = jvm.get_java_vm_pointer();
@@ -110,11 +329,28 @@ impl BAgentInjector {
             capabilities.can_redefine_any_class = true;
             capabilities.can_retransform_classes = true;
             capabilities.can_retransform_any_class = true;
+            // lines of the load stacks saved with each class
+            capabilities.can_get_line_numbers = true;
             if let Result::Err(e) = jvmti.add_capabilities(&capabilities) {
                 return Err(crate::error::Error::JVMTI(jvmti::error::translate_error(
                     &e,
                 )));
             }
+            // what the jvm actually granted, for the session manifest
+            let granted = jvmti.get_capabilities();
+            let capabilities = [
+                ("can_redefine_classes", granted.can_redefine_classes),
+                ("can_redefine_any_class", granted.can_redefine_any_class),
+                ("can_retransform_classes", granted.can_retransform_classes),
+                (
+                    "can_retransform_any_class",
+                    granted.can_retransform_any_class,
+                ),
+                ("can_get_line_numbers", granted.can_get_line_numbers),
+            ]
+            .into_iter()
+            .map(|(name, granted)| (name.to_string(), granted))
+            .collect();
 
             // set class file load hook
             let native_callbacks = jvmti::native::jvmti_native::jvmtiEventCallbacks {
@@ -131,19 +367,46 @@ impl BAgentInjector {
                 size_of::<jvmtiEventCallbacks>() as i32,
             );
 
-            BRIDGE
+            let original_jvmti = if capture_original {
+                let callbacks = jvmtiEventCallbacks {
+                    ClassFileLoadHook: Some(local_cb_original_class_file_load_hook),
+                    ..Default::default()
+                };
+                Some(jvmti::environment::jvmti::JVMTIEnvironment::new(
+                    callback_env(jvm_ptr, &callbacks)?,
+                ))
+            } else {
+                None
+            };
+
+            let mut bridge =
+                crate::bridge::JavaBridge::new(jni::JavaVM::from_raw(jvm_ptr)?, config)?;
+            bridge
+                .recorder()
+                .set_jvm(crate::jvm::jvm_info(&mut _env), capabilities);
+            BRIDGE.lock().unwrap().replace(bridge);
+
+            TRANSFORMS.store(client.transforms(), Ordering::SeqCst);
+            CLIENT.lock().unwrap().replace(client);
+            FILTER.lock().unwrap().replace(Arc::new(filter));
+            CRAWL.lock().unwrap().replace(crawl);
+
+            PIPELINE
                 .lock()
                 .unwrap()
-                .replace(crate::bridge::JavaBridge::new(jni::JavaVM::from_raw(
-                    jvm_ptr,
-                )?));
-
-            CLIENT.lock().unwrap().replace(Box::new(client));
+                .replace(crate::pipeline::Pipeline::start(
+                    &pipeline_config,
+                    process_class,
+                ));
 
             let mut me = Self {
                 jvm: jni::JavaVM::from_raw(jvm_ptr)?,
                 jvmti,
                 jvmti_raw: *jvmti_raw as jvmti::native::JVMTIEnvPtr,
+                snapshot,
+                watch,
+                hidden_classes,
+                original_jvmti,
             };
             me.run_internal()?;
 
@@ -151,7 +414,36 @@ impl BAgentInjector {
         }
     }
 
+    // hidden classes never pass through the load hook, they are captured where they are defined
+    fn wrap_define_class0(&mut self) -> Result<(), crate::error::Error> {
+        let Some(define_class0) = crate::jvm::define_class0() else {
+            println!("hidden classes are not captured, the jvm has no ClassLoader.defineClass0");
+            return Ok(());
+        };
+        // a thread may still be entering or leaving the wrapper after it was unbound, so the
+        // dll stays loaded for the rest of the process
+        unsafe { pin_dll()? };
+        DEFINE_CLASS0.lock().unwrap().replace(define_class0);
+        DEFINE_CLASS0_JVMTI.store(self.jvmti_raw, Ordering::SeqCst);
+        CAPTURE_HIDDEN.store(true, Ordering::SeqCst);
+        crate::jvm::bind_define_class0(&mut self.jvm.get_env()?, local_define_class0)
+    }
+
+    fn release_define_class0(&mut self) -> Result<(), crate::error::Error> {
+        if !CAPTURE_HIDDEN.swap(false, Ordering::SeqCst) {
+            return Ok(());
+        }
+        if let Some(define_class0) = *DEFINE_CLASS0.lock().unwrap() {
+            crate::jvm::bind_define_class0(&mut self.jvm.get_env()?, define_class0)?;
+        }
+        while DEFINING.load(Ordering::SeqCst) > 0 {
+            std::thread::sleep(CHECK_INTERVAL);
+        }
+        Ok(())
+    }
+
     fn run_internal(&mut self) -> Result<(), crate::error::Error> {
+        AGENT_THREAD.set(true);
         load_client_classes(
             &mut self.jvm.get_env()?,
             self.jvmti_raw,
@@ -160,15 +452,199 @@ impl BAgentInjector {
             BRIDGE.lock().unwrap().as_mut().unwrap(),
         )?;
 
+        // continue with what an earlier attach left queued
+        let pending = BRIDGE
+            .lock()
+            .unwrap()
+            .as_mut()
+            .unwrap()
+            .recorder()
+            .take_pending();
+        // seeds naming a class are looked up, the others wait for a matching class to load
+        let (named_seeds, seeded, scans_loaded_classes) = {
+            let mut crawl = CRAWL.lock().unwrap();
+            let crawl = crawl.as_mut().unwrap();
+            for class_name in &pending {
+                crawl.resume(class_name);
+            }
+            (
+                crawl.named_seeds().to_vec(),
+                crawl.is_seeded(),
+                crawl.scans_loaded_classes(),
+            )
+        };
+        CLASSES_TO_LOAD.lock().unwrap().extend(
+            pending
+                .into_iter()
+                .chain(named_seeds)
+                .filter(|class_name| may_accept(class_name)),
+        );
+
         self.jvmti
             .set_event_notification_mode(jvmti::event::VMEvent::ClassFileLoadHook, true);
+        if let Some(original_jvmti) = &mut self.original_jvmti {
+            original_jvmti
+                .set_event_notification_mode(jvmti::event::VMEvent::ClassFileLoadHook, true);
+        }
+        if self.hidden_classes.enabled {
+            self.wrap_define_class0()?;
+        }
+        let startup = STARTUP.load(Ordering::SeqCst);
+        if startup {
+            // classes defined from now on are captured right away
+            let early = EARLY_CLASSES.lock().unwrap().take().unwrap_or_default();
+            println!(
+                "saving {} classes defined before the agent was ready",
+                early.len()
+            );
+            for (class_name, class_data) in early {
+                capture_class(
+                    std::ptr::null_mut(),
+                    std::ptr::null_mut(),
+                    &class_name,
+                    class_data,
+                    None,
+                    std::ptr::null_mut(),
+                    std::ptr::null_mut(),
+                    std::ptr::null_mut(),
+                    None,
+                );
+            }
+        }
+
+        // referrers of the seeds are found among the hooked classes outside the crawl
+        if self.snapshot.enabled || scans_loaded_classes {
+            let report = self.retransform_loaded_classes(may_accept)?;
+            if self.snapshot.enabled {
+                BRIDGE
+                    .lock()
+                    .unwrap()
+                    .as_mut()
+                    .unwrap()
+                    .recorder()
+                    .write_report("snapshot.json", &serde_json::to_vec_pretty(&report)?)?;
+            }
+        } else if seeded {
+            // seeds loaded before attach are never hooked otherwise
+            self.retransform_loaded_classes(|class_name| {
+                may_accept(class_name)
+                    && CRAWL
+                        .lock()
+                        .unwrap()
+                        .as_ref()
+                        .is_some_and(|crawl| crawl.is_seed(class_name))
+            })?;
+        }
+
+        // a stop file left by an earlier session would end watching right away
+        let stop_path = self.watch.stop_path();
+        if self.watch.enabled && stop_path.exists() {
+            std::fs::remove_file(&stop_path)?;
+        }
 
         println!("Waiting for classes to be loaded...");
 
-        std::thread::sleep(std::time::Duration::from_secs(10));
-        while !CLASSES_TO_LOAD.lock().unwrap().is_empty() {
+        // in startup mode classes are captured as they are defined, there is nothing to wait for
+        if !startup {
+            std::thread::sleep(std::time::Duration::from_secs(10));
+        }
+        // when watching started and how many classes were saved by then
+        let mut watching: Option<(std::time::Instant, usize)> = None;
+        let mut last_status = std::time::Instant::now();
+        let mut stopped_by = None;
+        loop {
+            // the vm going away ends the dump whether or not watching started,
+            // the report is finished while VMDeath waits for this thread
+            if VM_DEATH.load(Ordering::SeqCst) {
+                stopped_by = Some(StopReason::VmDeath);
+                break;
+            }
+            if let Some((started, _)) = watching
+                && let Some(reason) = self.watch_stop(started)
+            {
+                stopped_by = Some(reason);
+                break;
+            }
+
             let taken = CLASSES_TO_LOAD.lock().unwrap().drain().collect::<Vec<_>>();
-            let classes = load_classes_to_retransform(&mut self.jvm.get_env()?, taken.clone())?;
+            if taken.is_empty() {
+                // referrers turn up as classes outside the crawl are hooked
+                let referrers = CRAWL
+                    .lock()
+                    .unwrap()
+                    .as_mut()
+                    .map(crate::crawl::Crawl::find_referrers)
+                    .unwrap_or_default();
+                if !referrers.is_empty() {
+                    CLASSES_TO_LOAD.lock().unwrap().extend(
+                        referrers
+                            .into_iter()
+                            .filter(|class_name| may_accept(class_name)),
+                    );
+                    continue;
+                }
+
+                // classes still in the pipeline may queue more dependencies
+                if pipeline_idle() && CLASSES_TO_LOAD.lock().unwrap().is_empty() {
+                    // in startup mode capturing goes on until the vm exits
+                    if !self.watch.enabled && !startup {
+                        break;
+                    }
+                    if watching.is_none() && startup {
+                        // ctrl+c is left to the application, the vm exiting stops watching
+                        println!(
+                            "Watching for newly defined classes until the vm exits or `b_cli stop` is run..."
+                        );
+                        watching = Some((std::time::Instant::now(), saved_count()));
+                        last_status = std::time::Instant::now();
+                    } else if watching.is_none() {
+                        println!(
+                            "Watching for newly loaded classes, run `b_cli stop` or press ctrl+c to stop..."
+                        );
+                        if let Err(e) = crate::console::catch_interrupts(true) {
+                            println!("failed to catch ctrl+c: {e}");
+                        }
+                        watching = Some((std::time::Instant::now(), saved_count()));
+                        last_status = std::time::Instant::now();
+                    } else if last_status.elapsed().as_secs() >= self.watch.status_interval_secs {
+                        println!("watching, {} classes saved", saved_count());
+                        last_status = std::time::Instant::now();
+                    }
+                }
+                std::thread::sleep(CHECK_INTERVAL);
+                continue;
+            }
+
+            // the taken classes count as pending until they are hooked
+            let pending = CLASSES_TO_LOAD
+                .lock()
+                .unwrap()
+                .iter()
+                .chain(&taken)
+                .cloned()
+                .collect::<Vec<_>>();
+            BRIDGE
+                .lock()
+                .unwrap()
+                .as_mut()
+                .unwrap()
+                .recorder()
+                .save_state(pending)?;
+
+            let taken = match CRAWL.lock().unwrap().as_ref() {
+                Some(crawl) => crawl.order(taken),
+                None => taken,
+            };
+            let (classes, unresolved) =
+                load_classes_to_retransform(&mut self.jvm.get_env()?, taken)?;
+            if !unresolved.is_empty() {
+                println!("could not find classes: {unresolved:?}");
+                let mut bridge = BRIDGE.lock().unwrap();
+                let recorder = bridge.as_mut().unwrap().recorder();
+                for class_name in &unresolved {
+                    recorder.mark_unresolved(class_name);
+                }
+            }
             for class in classes {
                 unsafe {
                     let Some(retransform_classes) = (**self.jvmti_raw).RetransformClasses else {
@@ -186,9 +662,150 @@ impl BAgentInjector {
 
         self.jvmti
             .set_event_notification_mode(jvmti::event::VMEvent::ClassFileLoadHook, false);
+        if let Some(original_jvmti) = &mut self.original_jvmti {
+            original_jvmti
+                .set_event_notification_mode(jvmti::event::VMEvent::ClassFileLoadHook, false);
+        }
+        self.release_define_class0()?;
+        stop_startup_capture();
+
+        if watching.is_some() {
+            let _ = crate::console::catch_interrupts(false);
+            crate::console::INTERRUPTED.store(false, Ordering::SeqCst);
+            if stop_path.exists() {
+                std::fs::remove_file(&stop_path)?;
+            }
+        }
+
+        println!(
+            "filter rejected {} hooked classes",
+            FILTERED.load(Ordering::SeqCst)
+        );
+        println!(
+            "crawl left out {} hooked classes",
+            OUTSIDE_CRAWL.load(Ordering::SeqCst)
+        );
+
+        let report = PIPELINE
+            .lock()
+            .unwrap()
+            .take()
+            .map(|pipeline| pipeline.shutdown());
+        let mut bridge = BRIDGE.lock().unwrap();
+        let bridge = bridge.as_mut().unwrap();
+        if let Some(report) = report {
+            println!(
+                "pipeline processed {} classes, spilled {}, dropped {}",
+                report.processed,
+                report.spilled,
+                report.dropped.len()
+            );
+            bridge
+                .recorder()
+                .write_report("pipeline.json", &serde_json::to_vec_pretty(&report)?)?;
+        }
+        // watching may stop with classes still queued, they are resumed by the next attach
+        let pending = CLASSES_TO_LOAD.lock().unwrap().drain().collect::<Vec<_>>();
+        if let (Some((started, saved_before)), Some(stopped_by)) = (watching, stopped_by) {
+            let report = WatchReport {
+                stopped_by,
+                watched_secs: started.elapsed().as_secs(),
+                saved_before,
+                saved: bridge.recorder().saved_count(),
+                pending: pending.len(),
+            };
+            println!(
+                "watched for {}s, saved {} classes while watching",
+                report.watched_secs,
+                report.saved - report.saved_before
+            );
+            bridge
+                .recorder()
+                .write_report("watch.json", &serde_json::to_vec_pretty(&report)?)?;
+        }
+        bridge.recorder().save_state(pending)?;
+        bridge.finish()?;
 
         Ok(())
     }
+
+    // why watching should stop, None while it goes on
+    fn watch_stop(&self, started: std::time::Instant) -> Option<StopReason> {
+        if crate::console::INTERRUPTED.load(Ordering::SeqCst) {
+            Some(StopReason::Signal)
+        } else if self.watch.stop_path().exists() {
+            Some(StopReason::Command)
+        } else if self
+            .watch
+            .duration_secs
+            .is_some_and(|duration| started.elapsed().as_secs() >= duration)
+        {
+            Some(StopReason::Duration)
+        } else {
+            None
+        }
+    }
+
+    // send the selected classes already loaded through the hook, in batches. a batch that fails
+    // is retried class by class so one bad class doesn't keep the others out of the dump.
+    fn retransform_loaded_classes(
+        &mut self,
+        select: impl Fn(&str) -> bool,
+    ) -> Result<SnapshotReport, crate::error::Error> {
+        let mut env = self.jvm.get_env()?;
+        let (classes, skipped) = crate::jvm::modifiable_loaded_classes(&mut env, self.jvmti_raw)?;
+        let mut report = SnapshotReport {
+            loaded: classes.len() + skipped,
+            skipped,
+            ..Default::default()
+        };
+        println!(
+            "retransforming {} loaded classes, skipped {skipped}",
+            classes.len()
+        );
+
+        let (names, classes): (Vec<_>, Vec<_>) = classes
+            .into_iter()
+            .filter(|(name, class)| {
+                if select(name) {
+                    return true;
+                }
+                report.filtered += 1;
+                let _ =
+                    env.delete_local_ref(unsafe { jni::objects::JClass::from_raw(class.as_raw()) });
+                false
+            })
+            .unzip();
+        let batch_size = self.snapshot.batch_size.max(1);
+        for (names, classes) in names.chunks(batch_size).zip(classes.chunks(batch_size)) {
+            if crate::jvm::retransform_classes(self.jvmti_raw, classes).is_ok() {
+                report.retransformed += classes.len();
+            } else {
+                for (name, class) in names.iter().zip(classes) {
+                    match crate::jvm::retransform_classes(
+                        self.jvmti_raw,
+                        std::slice::from_ref(class),
+                    ) {
+                        Ok(()) => report.retransformed += 1,
+                        Err(e) => {
+                            println!("failed to retransform {name}: {e}");
+                            report.failed.push(name.clone());
+                        }
+                    }
+                }
+            }
+            for class in classes {
+                env.delete_local_ref(unsafe { jni::objects::JClass::from_raw(class.as_raw()) })?;
+            }
+        }
+
+        println!(
+            "retransformed {} loaded classes, {} failed",
+            report.retransformed,
+            report.failed.len()
+        );
+        Ok(report)
+    }
 }
 
 fn load_client_classes<'a>(
@@ -244,7 +861,7 @@ fn load_client_classes<'a>(
                     loaded_class_names.push(class_name.clone());
                     bridge.insert_cache(
                         class_name.clone(),
-                        jni::objects::JClass::from_raw(class_copy),
+                        env.new_global_ref(jni::objects::JClass::from_raw(class_copy))?,
                     )?;
                 }
             }
@@ -276,27 +893,249 @@ fn load_client_classes<'a>(
     Ok(())
 }
 
-fn class_file_load_hook(class_name: &str, class_data: Vec<u8>) -> Option<Vec<u8>> {
-    LOADED_CLASSES
-        .lock()
-        .unwrap()
-        .insert(class_name.to_string());
+fn class_file_load_hook(
+    jvmti_env: JVMTIEnvPtr,
+    jni_env: JNIEnvPtr,
+    class_name: &str,
+    class_data: Vec<u8>,
+    class_being_redefined: JavaClass,
+    loader: JavaObject,
+    protection_domain: JavaObject,
+) -> Option<Vec<u8>> {
+    // taken even for classes left out, so it isn't paired with a later load
+    let original = take_original(class_name);
 
-    match BRIDGE
-        .lock()
-        .unwrap()
-        .as_mut()
-        .unwrap()
-        .on_classfile_load_hook(
+    // classes the analysis loads on a worker are left as they are, transforming them
+    // would wait on the client while the worker analyzes
+    let transformed = if crate::pipeline::is_worker() || !TRANSFORMS.load(Ordering::SeqCst) {
+        Ok(None)
+    } else {
+        transform_class(jni_env, &class_data, loader)
+    };
+    let transformed = match transformed {
+        Ok(transformed) => transformed,
+        Err(e) => {
+            println!("failed to transform {class_name}: {e}");
+            None
+        }
+    };
+
+    // in startup mode loaded classes were captured as they were defined
+    if !(STARTUP.load(Ordering::SeqCst) && class_being_redefined.is_null()) {
+        capture_class(
+            jvmti_env,
+            jni_env,
             class_name,
             class_data,
-            CLIENT.lock().unwrap().as_mut().unwrap(),
+            None,
+            class_being_redefined,
+            loader,
+            protection_domain,
+            original,
+        );
+    }
+
+    transformed
+}
+
+// name a hooked class as it is saved. anonymous classes come without a name and generated ones
+// with counters in it, both are saved under a synthetic name. the hook passes internal names,
+// the rest of the agent works with binary ones.
+fn name_class(class: &mut crate::pipeline::LoadedClass) {
+    let generated = class.source.dynamic.is_some()
+        || class.name == "(NULL)"
+        || crate::dynamic::is_generated(&class.name);
+    if !generated {
+        class.name = crate::classfile::binary_name(&class.name);
+        return;
+    }
+
+    match generated_class(&class.data, class.source.dynamic.clone()) {
+        Ok((name, mut origin)) => {
+            origin.api = crate::dynamic::defining_api(
+                &origin.runtime_name,
+                &class.source.stack,
+                origin.hidden,
+            );
+            class.name = name;
+            class.source.dynamic = Some(origin);
+        }
+        Err(e) => {
+            println!("failed to name generated class {}: {e}", class.name);
+            class.name = crate::classfile::binary_name(&class.name);
+        }
+    }
+}
+
+// synthetic name and origin of a class generated at runtime. the origin of hidden classes is
+// read when they are defined, the host of others is known from the name of lambdas and
+// enhanced classes.
+fn generated_class(
+    class_data: &[u8],
+    origin: Option<crate::dynamic::DynamicOrigin>,
+) -> Result<(String, crate::dynamic::DynamicOrigin), crate::error::Error> {
+    let origin = match origin {
+        Some(origin) => origin,
+        None => {
+            let runtime_name = crate::classfile::binary_name(
+                &crate::classfile::ClassFile::parse(class_data)?.name()?,
+            );
+            crate::dynamic::DynamicOrigin {
+                host: crate::dynamic::host_from_name(&runtime_name),
+                runtime_name,
+                ..Default::default()
+            }
+        }
+    };
+    Ok((crate::dynamic::synthetic_name(class_data)?, origin))
+}
+
+// hand a class to the workers on the thread loading or defining it. only what is gone once the
+// hook returns is read here, naming, filtering and the crawl are left to the workers.
+#[allow(clippy::too_many_arguments)]
+fn capture_class(
+    jvmti_env: JVMTIEnvPtr,
+    jni_env: JNIEnvPtr,
+    class_name: &str,
+    class_data: Vec<u8>,
+    dynamic: Option<crate::dynamic::DynamicOrigin>,
+    class_being_redefined: JavaClass,
+    loader: JavaObject,
+    protection_domain: JavaObject,
+    original: Option<Vec<u8>>,
+) {
+    let (mut source, resolve) = class_source(
+        jvmti_env,
+        jni_env,
+        class_name,
+        class_being_redefined,
+        loader,
+        protection_domain,
+    );
+    source.dynamic = dynamic;
+
+    let class = crate::pipeline::LoadedClass {
+        name: class_name.to_string(),
+        data: class_data,
+        source,
+        original,
+        resolve,
+    };
+    // the pipeline lock is not held while submitting, blocking on a full queue would
+    // keep workers re-entering this hook from submitting
+    let submitter = PIPELINE
+        .lock()
+        .unwrap()
+        .as_ref()
+        .and_then(crate::pipeline::Pipeline::submitter);
+    match submitter {
+        Some(submitter) => submitter.submit(class),
+        None => process_class(class),
+    }
+}
+
+// save a hooked class and queue its dependencies for retransformation, runs on pipeline workers
+fn process_class(mut class: crate::pipeline::LoadedClass) {
+    name_class(&mut class);
+    // the name alone often decides, which saves reading the load context
+    if !may_accept(&class.name) {
+        FILTERED.fetch_add(1, Ordering::SeqCst);
+        return;
+    }
+
+    // classes outside the crawl are left for when a seed reaches them, generated ones are
+    // reached through their host. the dependencies of outside classes are kept while
+    // referrers of the seeds are searched.
+    let (admitted, keeps_outside) = match CRAWL.lock().unwrap().as_mut() {
+        Some(crawl) => {
+            let admitted = match &class.source.dynamic {
+                Some(origin) => crawl.admit_dynamic(&class.name, origin.host.as_deref()),
+                None => crawl.admit(&class.name),
+            }
+            .is_some();
+            (admitted, crawl.keeps_outside())
+        }
+        None => (true, false),
+    };
+    if !admitted {
+        OUTSIDE_CRAWL.fetch_add(1, Ordering::SeqCst);
+        if keeps_outside {
+            match crate::crawl::dependencies(&class.data) {
+                Ok(dependencies) => {
+                    if let Some(crawl) = CRAWL.lock().unwrap().as_mut() {
+                        crawl.record_outside(&class.name, dependencies);
+                    }
+                }
+                Err(e) => println!("failed to read dependencies of {}: {e}", class.name),
+            }
+        }
+        return;
+    }
+    LOADED_CLASSES.lock().unwrap().insert(class.name.clone());
+
+    class.resolve_source();
+    let filter = FILTER.lock().unwrap().clone();
+    if filter.is_some_and(|filter| {
+        !filter.accepts(&crate::filter::ClassInfo::new(
+            &class.name,
+            &class.data,
+            &class.source,
+        ))
+    }) {
+        FILTERED.fetch_add(1, Ordering::SeqCst);
+        return;
+    }
+
+    // neither the bridge nor the client is locked while calling into java. classes the analysis
+    // loads re-enter the hook on this worker, and other threads would wait for it.
+    let analyzer = {
+        let mut bridge = BRIDGE.lock().unwrap();
+        // the bridge is gone when the agent shut down with classes still queued
+        let Some(bridge) = bridge.as_mut() else {
+            return;
+        };
+        let client = CLIENT.lock().unwrap();
+        let Some(client) = client.as_ref() else {
+            return;
+        };
+        match bridge.save(
+            &class.name,
+            &class.data,
+            class.original.as_deref(),
+            &class.source,
         ) {
+            Ok(true) => bridge.analyzer(client.as_ref()),
+            // class already saved, no need to retransform
+            Ok(false) => return,
+            Err(e) => Err(e),
+        }
+    };
+    let edges = analyzer.and_then(|analyzer| analyzer.dependency_edges(&class.data));
+
+    let dependencies = {
+        let mut bridge = BRIDGE.lock().unwrap();
+        let Some(bridge) = bridge.as_mut() else {
+            return;
+        };
+        match edges {
+            Ok(edges) => Ok(bridge.recorder().record_dependencies(&class.name, edges)),
+            Err(e) => {
+                bridge.recorder().mark_failed(&class.name);
+                Err(e)
+            }
+        }
+    };
+
+    match dependencies {
         Ok(dependencies) => {
+            let dependencies = match CRAWL.lock().unwrap().as_mut() {
+                Some(crawl) => crawl.follow(&class.name, dependencies),
+                None => dependencies,
+            };
             let mut to_loade_lock = CLASSES_TO_LOAD.lock().unwrap();
             let loaded_lock = LOADED_CLASSES.lock().unwrap();
             for name in dependencies {
-                if loaded_lock.contains(&name) {
+                if loaded_lock.contains(&name) || !may_accept(&name) {
                     continue;
                 }
 
@@ -307,18 +1146,233 @@ fn class_file_load_hook(class_name: &str, class_data: Vec<u8>) -> Option<Vec<u8>
             println!("Error in class_file_load_hook: {e}");
         }
     }
+}
 
-    None
+// bytes the same load of a class had before agents able to retransform changed them
+fn take_original(class_name: &str) -> Option<Vec<u8>> {
+    ORIGINALS.with_borrow_mut(|originals| {
+        let position = originals.iter().rposition(|(name, _)| name == class_name)?;
+        Some(originals.remove(position).1)
+    })
+}
+
+// a jvmti environment with the given callbacks and without the retransform capability. the jvm
+// calls its load hook before the ones of environments with it, so it sees classes before most
+// agents transformed them.
+unsafe fn callback_env(
+    jvm_ptr: *mut jni::sys::JavaVM,
+    callbacks: &jvmtiEventCallbacks,
+) -> Result<JVMTIEnvPtr, crate::error::Error> {
+    unsafe {
+        let Some(get_env) = (**jvm_ptr).GetEnv else {
+            return Err(crate::error::Error::XValueNotOfType("get_env"));
+        };
+        let mut env_ptr = std::ptr::null_mut();
+        if get_env(
+            jvm_ptr,
+            &mut env_ptr,
+            jvmti::native::jvmti_native::JVMTI_VERSION_1_2 as i32,
+        ) != jvmti::native::jvmti_native::JVMTI_ERROR_NONE as i32
+        {
+            return Err(crate::error::Error::XValueNotOfType("jvm env"));
+        }
+
+        let env_ptr = env_ptr as jvmti::native::JVMTIEnvPtr;
+        let Some(set_event_callbacks) = (**env_ptr).SetEventCallbacks else {
+            return Err(crate::error::Error::XValueNotOfType("set event callbacks"));
+        };
+        set_event_callbacks(env_ptr, callbacks, size_of::<jvmtiEventCallbacks>() as i32);
+        Ok(env_ptr)
+    }
+}
+
+// whether a class known by name only may pass the filter
+fn may_accept(class_name: &str) -> bool {
+    FILTER
+        .lock()
+        .unwrap()
+        .as_ref()
+        .is_none_or(|filter| filter.may_accept(class_name))
+}
+
+fn saved_count() -> usize {
+    BRIDGE
+        .lock()
+        .unwrap()
+        .as_mut()
+        .map_or(0, |bridge| bridge.recorder().saved_count())
+}
+
+fn pipeline_idle() -> bool {
+    PIPELINE
+        .lock()
+        .unwrap()
+        .as_ref()
+        .is_none_or(crate::pipeline::Pipeline::is_idle)
+}
+
+// load context of a hooked class, parts that can't be read are left out. the thread and stack
+// are read on the loading thread, they are gone once the hook returns. loader, code source and
+// module are left to the worker through global references.
+fn class_source(
+    jvmti_env: JVMTIEnvPtr,
+    jni_env: JNIEnvPtr,
+    class_name: &str,
+    class_being_redefined: JavaClass,
+    loader: JavaObject,
+    protection_domain: JavaObject,
+) -> (
+    crate::recorder::ClassSource,
+    Option<crate::pipeline::Resolve>,
+) {
+    let mut source = crate::recorder::ClassSource {
+        loaded_at: std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .ok()
+            .map(|duration| duration.as_millis() as u64),
+        // e.g. a framework or hot reload tool changing the class while the hook is enabled
+        redefined: !class_being_redefined.is_null() && !AGENT_THREAD.get(),
+        ..Default::default()
+    };
+    let mut env = match unsafe { jni::JNIEnv::from_raw(jni_env as *mut jni::sys::JNIEnv) } {
+        Ok(env) => env,
+        Err(_) => return (source, None),
+    };
+
+    match crate::jvm::current_thread_name(&mut env) {
+        Ok(thread) => source.thread = Some(thread),
+        Err(e) => println!("failed to read loading thread of {class_name}: {e}"),
+    }
+
+    match crate::jvm::current_stack_trace(&mut env, jvmti_env, MAX_STACK_DEPTH) {
+        Ok(stack) => source.stack = stack,
+        Err(e) => println!("failed to read load stack of {class_name}: {e}"),
+    }
+
+    // null stays None, the bootstrap loader and classes without protection domain
+    let global = |object: jni::sys::jobject| {
+        let object = unsafe { jni::objects::JObject::from_raw(object) };
+        (!object.is_null())
+            .then(|| env.new_global_ref(object))
+            .transpose()
+    };
+    let references = (|| -> Result<_, crate::error::Error> {
+        Ok((
+            global(loader as jni::sys::jobject)?,
+            global(protection_domain as jni::sys::jobject)?,
+            global(class_being_redefined as jni::sys::jobject)?,
+        ))
+    })();
+    let (loader, protection_domain, class_being_redefined) = match references {
+        Ok(references) => references,
+        Err(e) => {
+            println!("failed to keep the loader of {class_name}: {e}");
+            return (source, None);
+        }
+    };
+    let jvm = match env.get_java_vm() {
+        Ok(jvm) => jvm,
+        Err(_) => return (source, None),
+    };
+
+    let class_name = class_name.to_string();
+    // the environment pointer is valid on every thread
+    let jvmti_env = jvmti_env as usize;
+    let resolve: crate::pipeline::Resolve = Box::new(move |source| {
+        resolve_source(
+            &jvm,
+            jvmti_env as JVMTIEnvPtr,
+            &class_name,
+            [loader, protection_domain, class_being_redefined],
+            source,
+        )
+    });
+    (source, Some(resolve))
+}
+
+// loader, code source and module of a hooked class, read on a worker
+fn resolve_source(
+    jvm: &jni::JavaVM,
+    jvmti_env: JVMTIEnvPtr,
+    class_name: &str,
+    [loader, protection_domain, class_being_redefined]: [Option<jni::objects::GlobalRef>; 3],
+    source: &mut crate::recorder::ClassSource,
+) {
+    let env = match jvm.get_env() {
+        Ok(env) => Ok(env),
+        Err(_) => jvm.attach_current_thread_as_daemon(),
+    };
+    let mut env = match env {
+        Ok(env) => env,
+        Err(e) => {
+            println!("failed to read the load context of {class_name}: {e}");
+            return;
+        }
+    };
+    let null = jni::objects::JObject::null();
+    let [loader, protection_domain, class_being_redefined] =
+        [&loader, &protection_domain, &class_being_redefined].map(|global| {
+            global
+                .as_ref()
+                .map_or(&null, jni::objects::GlobalRef::as_obj)
+        });
+
+    match crate::jvm::class_loader_name(&mut env, loader) {
+        Ok(name) => source.loader = Some(name),
+        Err(e) => println!("failed to read loader of {class_name}: {e}"),
+    }
+
+    match crate::jvm::code_source_location(&mut env, protection_domain) {
+        Ok(code_source) => source.code_source = code_source,
+        Err(e) => println!("failed to read code source of {class_name}: {e}"),
+    }
+
+    match crate::jvm::class_module_name(
+        &mut env,
+        jvmti_env,
+        class_being_redefined,
+        loader,
+        class_name,
+    ) {
+        Ok(module) => source.module = module,
+        Err(e) => println!("failed to read module of {class_name}: {e}"),
+    }
+}
+
+fn transform_class(
+    jni_env: JNIEnvPtr,
+    class_data: &[u8],
+    loader: JavaObject,
+) -> Result<Option<Vec<u8>>, crate::error::Error> {
+    let mut class_file = crate::classfile::ClassFile::parse(class_data)?;
+    // the client lock must be released before the writer looks classes up,
+    // since loading them re-enters this hook. a client taken on shutdown transforms nothing.
+    let transformed = match CLIENT.lock().unwrap().as_ref() {
+        Some(client) => client.transform(&mut class_file)?,
+        None => false,
+    };
+    if !transformed {
+        return Ok(None);
+    }
+
+    let mut env = unsafe { jni::JNIEnv::from_raw(jni_env as *mut jni::sys::JNIEnv)? };
+    // classes referenced by the transformed one resolve through its own loader
+    let loader = unsafe { jni::objects::JObject::from_raw(loader as jni::sys::jobject) };
+    let mut writer = crate::classfile::writer::ClassWriter::new(
+        crate::bridge::hierarchy::JvmHierarchy::new(&mut env, &loader),
+    );
+
+    Ok(Some(writer.write(&class_file)?))
 }
 
 #[allow(warnings)]
 unsafe extern "C" fn local_cb_class_file_load_hook(
     jvmti_env: JVMTIEnvPtr,
     jni_env: JNIEnvPtr,
-    _class_being_redefined: JavaClass,
-    _loader: JavaObject,
+    class_being_redefined: JavaClass,
+    loader: JavaObject,
     name: *const c_char,
-    _protection_domain: JavaObject,
+    protection_domain: JavaObject,
     class_data_len: jint,
     class_data: *const c_uchar,
     new_class_data_len: *mut jint,
@@ -329,7 +1383,15 @@ unsafe extern "C" fn local_cb_class_file_load_hook(
 
     copy_nonoverlapping(class_data, data_ptr, class_data_len as usize);
     raw_data.set_len(class_data_len as usize);
-    if let Some(transformed) = class_file_load_hook(&stringify(name), raw_data) {
+    if let Some(transformed) = class_file_load_hook(
+        jvmti_env,
+        jni_env,
+        &stringify(name),
+        raw_data,
+        class_being_redefined,
+        loader,
+        protection_domain,
+    ) {
         let env = Environment::new(
             JVMTIEnvironment::new(jvmti_env),
             JNIEnvironment::new(jni_env),
@@ -348,26 +1410,275 @@ unsafe extern "C" fn local_cb_class_file_load_hook(
     };
 }
 
+// keeps the bytes as loaded for the load hook of the retransform capable environment
+#[allow(warnings)]
+unsafe extern "C" fn local_cb_original_class_file_load_hook(
+    _jvmti_env: JVMTIEnvPtr,
+    _jni_env: JNIEnvPtr,
+    _class_being_redefined: JavaClass,
+    _loader: JavaObject,
+    name: *const c_char,
+    _protection_domain: JavaObject,
+    class_data_len: jint,
+    class_data: *const c_uchar,
+    _new_class_data_len: *mut jint,
+    _new_class_data: *mut *mut c_uchar,
+) {
+    let class_name = stringify(name);
+    if !may_accept(&class_name) {
+        return;
+    }
+    let data = std::slice::from_raw_parts(class_data, class_data_len as usize).to_vec();
+    ORIGINALS.with_borrow_mut(|originals| {
+        // entries of loads the other hook never saw
+        if originals.len() >= MAX_PENDING_ORIGINALS {
+            originals.remove(0);
+        }
+        originals.push((class_name, data));
+    });
+}
+
+// bound to ClassLoader.defineClass0 while hidden classes are captured
+#[allow(clippy::too_many_arguments)]
+unsafe extern "system" fn local_define_class0(
+    env: *mut jni::sys::JNIEnv,
+    class: jni::sys::jclass,
+    loader: jni::sys::jobject,
+    lookup: jni::sys::jclass,
+    name: jni::sys::jstring,
+    bytes: jni::sys::jbyteArray,
+    offset: jni::sys::jint,
+    length: jni::sys::jint,
+    protection_domain: jni::sys::jobject,
+    initialize: jni::sys::jboolean,
+    flags: jni::sys::jint,
+    class_data: jni::sys::jobject,
+) -> jni::sys::jclass {
+    DEFINING.fetch_add(1, Ordering::SeqCst);
+    // a panic here would abort the jvm
+    let define_class0 = *DEFINE_CLASS0
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner);
+    let Some(define_class0) = define_class0 else {
+        if let Ok(mut env) = unsafe { jni::JNIEnv::from_raw(env) } {
+            let _ = env.throw_new(
+                "java/lang/InternalError",
+                "ClassLoader.defineClass0 is not bound",
+            );
+        }
+        DEFINING.fetch_sub(1, Ordering::SeqCst);
+        return std::ptr::null_mut();
+    };
+    let defined = unsafe {
+        define_class0(
+            env,
+            class,
+            loader,
+            lookup,
+            name,
+            bytes,
+            offset,
+            length,
+            protection_domain,
+            initialize,
+            flags,
+            class_data,
+        )
+    };
+
+    // a failed definition left its exception pending, it is not touched
+    if flags & crate::dynamic::HIDDEN_CLASS != 0
+        && !defined.is_null()
+        && CAPTURE_HIDDEN.load(Ordering::SeqCst)
+        && let Err(e) = capture_hidden_class(
+            env,
+            defined,
+            lookup,
+            bytes,
+            offset,
+            length,
+            loader,
+            protection_domain,
+        )
+    {
+        println!("failed to capture hidden class: {e}");
+        if let Ok(env) = unsafe { jni::JNIEnv::from_raw(env) } {
+            let _ = env.exception_clear();
+        }
+    }
+    DEFINING.fetch_sub(1, Ordering::SeqCst);
+
+    defined
+}
+
+#[allow(clippy::too_many_arguments)]
+fn capture_hidden_class(
+    jni_env: *mut jni::sys::JNIEnv,
+    defined: jni::sys::jclass,
+    lookup: jni::sys::jclass,
+    bytes: jni::sys::jbyteArray,
+    offset: jni::sys::jint,
+    length: jni::sys::jint,
+    loader: jni::sys::jobject,
+    protection_domain: jni::sys::jobject,
+) -> Result<(), crate::error::Error> {
+    let mut env = unsafe { jni::JNIEnv::from_raw(jni_env)? };
+    let bytes = unsafe { jni::objects::JByteArray::from_raw(bytes) };
+    let mut class_data = vec![0; length.max(0) as usize];
+    env.get_byte_array_region(&bytes, offset, &mut class_data)?;
+    let class_data = class_data
+        .into_iter()
+        .map(|byte| byte as u8)
+        .collect::<Vec<_>>();
+
+    let defined = unsafe { jni::objects::JObject::from_raw(defined) };
+    let runtime_name = crate::jvm::class_name(&mut env, &defined)?;
+    let host = if lookup.is_null() {
+        None
+    } else {
+        let lookup = unsafe { jni::objects::JObject::from_raw(lookup) };
+        Some(crate::jvm::class_name(&mut env, &lookup)?)
+    };
+    let origin = crate::dynamic::DynamicOrigin {
+        runtime_name,
+        host,
+        hidden: true,
+        ..Default::default()
+    };
+
+    capture_class(
+        DEFINE_CLASS0_JVMTI.load(Ordering::SeqCst),
+        jni_env as JNIEnvPtr,
+        // named by the worker, the hook's internal form locates the module
+        &crate::classfile::internal_name(&origin.runtime_name),
+        class_data,
+        Some(origin),
+        std::ptr::null_mut(),
+        loader as JavaObject,
+        protection_domain as JavaObject,
+        None,
+    );
+    Ok(())
+}
+
+// hook of the startup environment, buffers classes until the agent is ready
+#[allow(warnings)]
+unsafe extern "C" fn local_cb_startup_class_file_load_hook(
+    jvmti_env: JVMTIEnvPtr,
+    jni_env: JNIEnvPtr,
+    class_being_redefined: JavaClass,
+    loader: JavaObject,
+    name: *const c_char,
+    protection_domain: JavaObject,
+    class_data_len: jint,
+    class_data: *const c_uchar,
+    _new_class_data_len: *mut jint,
+    _new_class_data: *mut *mut c_uchar,
+) {
+    // redefinitions pass through every environment, the main one records them
+    if !class_being_redefined.is_null() {
+        return;
+    }
+    let class_name = stringify(name);
+    // generated classes are filtered by their synthetic name once captured
+    let generated = class_name == "(NULL)" || crate::dynamic::is_generated(&class_name);
+    if !generated && !may_accept(&class_name) {
+        return;
+    }
+    let data = std::slice::from_raw_parts(class_data, class_data_len as usize).to_vec();
+
+    // checked under the lock, so no class is buffered after the agent took the others
+    let mut early = EARLY_CLASSES.lock().unwrap();
+    if let Some(early) = early.as_mut() {
+        early.push((class_name, data));
+        return;
+    }
+    drop(early);
+
+    capture_class(
+        jvmti_env,
+        jni_env,
+        &class_name,
+        data,
+        None,
+        class_being_redefined,
+        loader,
+        protection_domain,
+        None,
+    );
+}
+
+// jni can be used from here on, the agent starts on its own thread
+#[allow(warnings)]
+unsafe extern "C" fn local_cb_vm_init(
+    _jvmti_env: JVMTIEnvPtr,
+    _jni_env: JNIEnvPtr,
+    _thread: JavaThread,
+) {
+    let agent = std::thread::spawn(|| {
+        let started = match (
+            jni::JavaVM::from_raw(STARTUP_JVM.load(Ordering::SeqCst)),
+            CLIENT.lock().unwrap().take(),
+        ) {
+            (Ok(jvm), Some(client)) => BAgentInjector::start(jvm, client).map(drop),
+            (Err(e), _) => Err(e.into()),
+            (_, None) => Err(crate::error::Error::XValueNotOfType("client")),
+        };
+        if let Err(e) = started {
+            println!("error: {e:?}");
+            // nothing takes the buffered classes anymore
+            stop_startup_capture();
+            EARLY_CLASSES.lock().unwrap().take();
+        }
+    });
+    STARTUP_AGENT.lock().unwrap().replace(agent);
+}
+
+fn stop_startup_capture() {
+    let startup_jvmti = STARTUP_JVMTI.load(Ordering::SeqCst);
+    if !startup_jvmti.is_null() {
+        jvmti::environment::jvmti::JVMTIEnvironment::new(startup_jvmti)
+            .set_event_notification_mode(jvmti::event::VMEvent::ClassFileLoadHook, false);
+    }
+}
+
+// the agent saves what is left before the vm goes away
+#[allow(warnings)]
+unsafe extern "C" fn local_cb_vm_death(_jvmti_env: JVMTIEnvPtr, _jni_env: JNIEnvPtr) {
+    VM_DEATH.store(true, Ordering::SeqCst);
+    if let Some(agent) = STARTUP_AGENT.lock().unwrap().take() {
+        let _ = agent.join();
+    }
+}
+
+// classes to retransform, and the names that were not found after MAX_FIND_ATTEMPTS lookups.
+// names left when the vm dies are queued again, so they are saved as pending.
 fn load_classes_to_retransform<'a>(
     env: &mut jni::JNIEnv<'a>,
     class_names_to_retransform: Vec<String>,
-) -> Result<Vec<jni::objects::JClass<'a>>, crate::error::Error> {
-    let classes = class_names_to_retransform
-        .into_iter()
-        .map(|class_name| {
-            loop {
-                let a = crate::jvm::find_class(env, &class_name);
-                match a {
-                    Ok(class) => {
-                        break unsafe { jni::objects::JClass::from_raw(class.as_raw()) };
-                    }
-                    _ => std::thread::sleep(CHECK_INTERVAL),
-                }
+) -> Result<(Vec<jni::objects::JClass<'a>>, Vec<String>), crate::error::Error> {
+    let mut classes = Vec::new();
+    let mut unresolved = Vec::new();
+    let mut class_names = class_names_to_retransform.into_iter();
+    'classes: while let Some(class_name) = class_names.next() {
+        for _ in 0..MAX_FIND_ATTEMPTS {
+            if VM_DEATH.load(Ordering::SeqCst) {
+                CLASSES_TO_LOAD
+                    .lock()
+                    .unwrap()
+                    .extend(std::iter::once(class_name).chain(class_names));
+                break 'classes;
             }
-        })
-        .collect::<Vec<_>>();
+            if let Ok(class) = crate::jvm::find_class(env, &class_name) {
+                classes.push(unsafe { jni::objects::JClass::from_raw(class.as_raw()) });
+                continue 'classes;
+            }
+            std::thread::sleep(CHECK_INTERVAL);
+        }
+        unresolved.push(class_name);
+    }
 
-    Ok(classes)
+    Ok((classes, unresolved))
 }
 
 pub fn stringify(input: RawString) -> String {
@@ -54,6 +54,71 @@ pub fn get_jvm() -> Result<jni::JavaVM, crate::error::Error> {
     Ok(jvm)
 }
 
+// whether the jvm of this process finished starting. read through a jvmti environment, which
+// doesn't attach the calling thread while the jvm may still be loading its agents.
+pub fn jvm_live() -> Result<bool, crate::error::Error> {
+    let Some(get_jvms) = get_jni_get_created_jvms() else {
+        return Err(crate::error::Error::XValueNotOfType(
+            "get_jni_get_created_jvms",
+        ));
+    };
+    let mut jvm = std::ptr::null_mut();
+    let mut jvm_count = 0;
+    if get_jvms(&mut jvm, 1, &mut jvm_count) != jni::sys::JNI_OK || jvm_count < 1 {
+        return Ok(false);
+    }
+
+    let jvm = jvm as *mut jni::sys::JavaVM;
+    unsafe {
+        let Some(get_env) = (**jvm).GetEnv else {
+            return Ok(false);
+        };
+        let mut env = std::ptr::null_mut();
+        if get_env(
+            jvm,
+            &mut env,
+            jvmti::native::jvmti_native::JVMTI_VERSION_1_2 as i32,
+        ) != jni::sys::JNI_OK
+        {
+            return Ok(false);
+        }
+
+        let env = env as jvmti::native::JVMTIEnvPtr;
+        let mut phase = 0;
+        let live = (**env).GetPhase.is_some_and(|get_phase| {
+            get_phase(env, &mut phase) == jvmti::native::jvmti_native::JVMTI_ERROR_NONE
+        }) && phase == jvmti::native::jvmti_native::JVMTI_PHASE_LIVE;
+        if let Some(dispose_environment) = (**env).DisposeEnvironment {
+            dispose_environment(env);
+        }
+        Ok(live)
+    }
+}
+
+// looks a class up the way the given loader resolves it, without initializing it.
+// a null loader stands for the bootstrap loader.
+pub fn load_class<'a>(
+    env: &mut jni::JNIEnv<'a>,
+    class_name: &str,
+    loader: &jni::objects::JObject,
+) -> Result<jni::objects::JClass<'a>, crate::error::Error> {
+    let name = env.new_string(class_name)?;
+    let class = env.call_static_method(
+        "java/lang/Class",
+        "forName",
+        "(Ljava/lang/String;ZLjava/lang/ClassLoader;)Ljava/lang/Class;",
+        &[(&name).into(), false.into(), loader.into()],
+    );
+    match class.and_then(|class| class.l()) {
+        Ok(class) => Ok(jni::objects::JClass::from(class)),
+        Err(e) => {
+            // a failed lookup leaves a ClassNotFoundException pending
+            env.exception_clear()?;
+            Err(e.into())
+        }
+    }
+}
+
 pub fn find_class<'a>(
     env: &mut jni::JNIEnv<'a>,
     class_name: &str,
@@ -63,6 +128,8 @@ pub fn find_class<'a>(
     {
         return Ok(unsafe { jni::objects::JObject::from_raw(class.as_raw()) });
     }
+    // a failed lookup leaves an exception pending, which would fail every following call
+    env.exception_clear()?;
 
     let stack_traces_map = env
         .call_static_method(
@@ -110,6 +177,7 @@ pub fn find_class<'a>(
                 )
             };
             if class.is_err() {
+                env.exception_clear()?;
                 continue;
             }
             let class = class?.l();
@@ -143,3 +211,582 @@ pub fn get_url_class<'a>(
 
     Ok(unsafe { jni::objects::JClass::from_raw(url_class.as_raw()) })
 }
+
+// location of a protection domain's code source, None when the class has none
+pub fn code_source_location(
+    env: &mut jni::JNIEnv,
+    protection_domain: &jni::objects::JObject,
+) -> Result<Option<String>, crate::error::Error> {
+    if protection_domain.is_null() {
+        return Ok(None);
+    }
+
+    let location = env.with_local_frame(4, |env| -> Result<_, crate::error::Error> {
+        let code_source = env
+            .call_method(
+                protection_domain,
+                "getCodeSource",
+                "()Ljava/security/CodeSource;",
+                &[],
+            )?
+            .l()?;
+        if code_source.is_null() {
+            return Ok(None);
+        }
+        let url = env
+            .call_method(&code_source, "getLocation", "()Ljava/net/URL;", &[])?
+            .l()?;
+        if url.is_null() {
+            return Ok(None);
+        }
+        let location = jni::objects::JString::from(
+            env.call_method(&url, "toString", "()Ljava/lang/String;", &[])?
+                .l()?,
+        );
+        Ok(Some(
+            env.get_string(&location)?.to_string_lossy().into_owned(),
+        ))
+    });
+    if location.is_err() {
+        // a throwing call leaves the exception pending
+        env.exception_clear()?;
+    }
+
+    location
+}
+
+// class name and identity hash of a class loader, like Object.toString would print it
+pub fn class_loader_name(
+    env: &mut jni::JNIEnv,
+    loader: &jni::objects::JObject,
+) -> Result<String, crate::error::Error> {
+    if loader.is_null() {
+        return Ok("bootstrap".to_string());
+    }
+
+    let name = env.with_local_frame(4, |env| -> Result<_, crate::error::Error> {
+        let class = env.get_object_class(loader)?;
+        let class_name = jni::objects::JString::from(
+            env.call_method(&class, "getName", "()Ljava/lang/String;", &[])?
+                .l()?,
+        );
+        let class_name = env.get_string(&class_name)?.to_string_lossy().into_owned();
+        let hash = env
+            .call_static_method(
+                "java/lang/System",
+                "identityHashCode",
+                "(Ljava/lang/Object;)I",
+                &[jni::objects::JValue::Object(loader)],
+            )?
+            .i()?;
+        Ok(format!("{class_name}@{hash:x}"))
+    });
+    if name.is_err() {
+        env.exception_clear()?;
+    }
+
+    name
+}
+
+fn check_jvmti(
+    function: &str,
+    error: jvmti::native::jvmti_native::jvmtiError,
+) -> Result<(), crate::error::Error> {
+    if error == jvmti::native::jvmti_native::JVMTI_ERROR_NONE {
+        return Ok(());
+    }
+    Err(crate::error::Error::JVMTI(format!(
+        "{function} failed with error {error}"
+    )))
+}
+
+// memory handed out by jvmti must be given back to it
+unsafe fn deallocate<T>(jvmti: jvmti::native::JVMTIEnvPtr, memory: *mut T) {
+    unsafe {
+        if let Some(deallocate) = (**jvmti).Deallocate
+            && !memory.is_null()
+        {
+            deallocate(jvmti, memory as *mut libc::c_uchar);
+        }
+    }
+}
+
+// frames of the current thread, innermost first, as "class.method(descriptor) line n".
+// the bytecode index is given instead of the line for methods without line numbers.
+pub fn current_stack_trace(
+    env: &mut jni::JNIEnv,
+    jvmti: jvmti::native::JVMTIEnvPtr,
+    max_depth: usize,
+) -> Result<Vec<String>, crate::error::Error> {
+    use jvmti::native::jvmti_native::{jvmtiFrameInfo, jvmtiLineNumberEntry};
+
+    unsafe {
+        let (
+            Some(get_stack_trace),
+            Some(get_method_name),
+            Some(get_method_declaring_class),
+            Some(get_class_signature),
+            Some(get_line_number_table),
+        ) = (
+            (**jvmti).GetStackTrace,
+            (**jvmti).GetMethodName,
+            (**jvmti).GetMethodDeclaringClass,
+            (**jvmti).GetClassSignature,
+            (**jvmti).GetLineNumberTable,
+        )
+        else {
+            return Err(crate::error::Error::XValueNotOfType(
+                "stack trace functions",
+            ));
+        };
+
+        let mut frames = Vec::<jvmtiFrameInfo>::with_capacity(max_depth);
+        let mut count = 0;
+        check_jvmti(
+            "GetStackTrace",
+            get_stack_trace(
+                jvmti,
+                std::ptr::null_mut(),
+                0,
+                max_depth as i32,
+                frames.as_mut_ptr(),
+                &mut count,
+            ),
+        )?;
+        frames.set_len(count as usize);
+
+        let mut stack = Vec::with_capacity(frames.len());
+        for frame in frames {
+            let mut name = std::ptr::null_mut();
+            let mut descriptor = std::ptr::null_mut();
+            let mut generic = std::ptr::null_mut();
+            check_jvmti(
+                "GetMethodName",
+                get_method_name(
+                    jvmti,
+                    frame.method,
+                    &mut name,
+                    &mut descriptor,
+                    &mut generic,
+                ),
+            )?;
+            let method = format!(
+                "{}{}",
+                crate::injector::stringify(name),
+                crate::injector::stringify(descriptor)
+            );
+            deallocate(jvmti, name);
+            deallocate(jvmti, descriptor);
+            deallocate(jvmti, generic);
+
+            let mut class = std::ptr::null_mut();
+            check_jvmti(
+                "GetMethodDeclaringClass",
+                get_method_declaring_class(jvmti, frame.method, &mut class),
+            )?;
+            let mut signature = std::ptr::null_mut();
+            let mut generic = std::ptr::null_mut();
+            let error = get_class_signature(jvmti, class, &mut signature, &mut generic);
+            env.delete_local_ref(jni::objects::JObject::from_raw(class as jni::sys::jobject))?;
+            check_jvmti("GetClassSignature", error)?;
+            // Ljava/lang/Object; -> java.lang.Object
+            let class_name = crate::injector::stringify(signature)
+                .trim_start_matches('L')
+                .trim_end_matches(';')
+                .replace('/', ".");
+            deallocate(jvmti, signature);
+            deallocate(jvmti, generic);
+
+            // the entry with the greatest start not after the frame's location
+            let mut entry_count = 0;
+            let mut table = std::ptr::null_mut::<jvmtiLineNumberEntry>();
+            let line = if get_line_number_table(jvmti, frame.method, &mut entry_count, &mut table)
+                == jvmti::native::jvmti_native::JVMTI_ERROR_NONE
+            {
+                let line = std::slice::from_raw_parts(table, entry_count as usize)
+                    .iter()
+                    .filter(|entry| entry.start_location <= frame.location)
+                    .max_by_key(|entry| entry.start_location)
+                    .map(|entry| entry.line_number);
+                deallocate(jvmti, table);
+                line
+            } else {
+                None
+            };
+
+            stack.push(match line {
+                Some(line) => format!("{class_name}.{method} line {line}"),
+                None if frame.location < 0 => format!("{class_name}.{method} native"),
+                None => format!("{class_name}.{method} bci {}", frame.location),
+            });
+        }
+
+        Ok(stack)
+    }
+}
+
+// classes loaded by the jvm that can be retransformed, with their binary names, and the
+// number left out: arrays, primitives and classes the jvm doesn't allow to modify.
+// the classes are local references of the calling thread.
+pub fn modifiable_loaded_classes<'a>(
+    env: &mut jni::JNIEnv<'a>,
+    jvmti: jvmti::native::JVMTIEnvPtr,
+) -> Result<(Vec<(String, jni::objects::JClass<'a>)>, usize), crate::error::Error> {
+    unsafe {
+        let (Some(get_loaded_classes), Some(get_class_signature), Some(is_modifiable_class)) = (
+            (**jvmti).GetLoadedClasses,
+            (**jvmti).GetClassSignature,
+            (**jvmti).IsModifiableClass,
+        ) else {
+            return Err(crate::error::Error::XValueNotOfType(
+                "loaded classes functions",
+            ));
+        };
+
+        let mut count = 0;
+        let mut loaded = std::ptr::null_mut();
+        check_jvmti(
+            "GetLoadedClasses",
+            get_loaded_classes(jvmti, &mut count, &mut loaded),
+        )?;
+        let all = std::slice::from_raw_parts(loaded, count as usize).to_vec();
+        deallocate(jvmti, loaded);
+
+        let mut classes = Vec::new();
+        let mut skipped = 0;
+        for class in all {
+            let mut signature = std::ptr::null_mut();
+            let mut generic = std::ptr::null_mut();
+            let name = if get_class_signature(jvmti, class, &mut signature, &mut generic)
+                == jvmti::native::jvmti_native::JVMTI_ERROR_NONE
+            {
+                Some(crate::injector::stringify(signature))
+            } else {
+                None
+            };
+            deallocate(jvmti, signature);
+            deallocate(jvmti, generic);
+
+            let mut modifiable = 0;
+            let keep = match &name {
+                // primitives have one letter signatures, arrays start with '['
+                Some(name) if name.starts_with('L') => {
+                    is_modifiable_class(jvmti, class, &mut modifiable)
+                        == jvmti::native::jvmti_native::JVMTI_ERROR_NONE
+                        && modifiable != 0
+                }
+                _ => false,
+            };
+            let class = jni::objects::JClass::from_raw(class as jni::sys::jclass);
+            match name {
+                Some(name) if keep => {
+                    // Ljava/lang/Object; -> java.lang.Object
+                    let name = name[1..].trim_end_matches(';').replace('/', ".");
+                    classes.push((name, class));
+                }
+                _ => {
+                    env.delete_local_ref(class)?;
+                    skipped += 1;
+                }
+            }
+        }
+
+        Ok((classes, skipped))
+    }
+}
+
+// retransform classes in one call, fails as a whole when one of them can't be retransformed
+pub fn retransform_classes(
+    jvmti: jvmti::native::JVMTIEnvPtr,
+    classes: &[jni::objects::JClass],
+) -> Result<(), crate::error::Error> {
+    let raw = classes
+        .iter()
+        .map(|class| class.as_raw() as jvmti::native::JavaClass)
+        .collect::<Vec<_>>();
+    unsafe {
+        let Some(retransform_classes) = (**jvmti).RetransformClasses else {
+            return Err(crate::error::Error::XValueNotOfType("retransform classes"));
+        };
+        check_jvmti(
+            "RetransformClasses",
+            retransform_classes(jvmti, raw.len() as i32, raw.as_ptr()),
+        )
+    }
+}
+
+type GetNamedModule = unsafe extern "C" fn(
+    jvmti::native::JVMTIEnvPtr,
+    jvmti::native::jvmti_native::jobject,
+    *const libc::c_char,
+    *mut jvmti::native::jvmti_native::jobject,
+) -> jvmti::native::jvmti_native::jvmtiError;
+
+// module a class is defined to, None on jvms without modules.
+// a class loaded for the first time has no class object yet, so its module is looked up
+// from the loader and package, otherwise it is asked for its module.
+pub fn class_module_name(
+    env: &mut jni::JNIEnv,
+    jvmti: jvmti::native::JVMTIEnvPtr,
+    class_being_redefined: &jni::objects::JObject,
+    loader: &jni::objects::JObject,
+    class_name: &str,
+) -> Result<Option<String>, crate::error::Error> {
+    // GetNamedModule takes the reserved slot 40 of jvmti 1.2 since java 9
+    let get_named_module = unsafe { (**jvmti).reserved40 };
+    if get_named_module.is_null() {
+        return Ok(None);
+    }
+    let get_named_module: GetNamedModule = unsafe { std::mem::transmute(get_named_module) };
+
+    let name = env.with_local_frame(4, |env| -> Result<_, crate::error::Error> {
+        let module = if class_being_redefined.is_null() {
+            let package = class_name
+                .rsplit_once('/')
+                .map_or("", |(package, _)| package);
+            let package = std::ffi::CString::new(package)
+                .map_err(|_| crate::error::Error::XValueNotOfType("package name"))?;
+            let mut module = std::ptr::null_mut();
+            check_jvmti("GetNamedModule", unsafe {
+                get_named_module(
+                    jvmti,
+                    loader.as_raw() as jvmti::native::jvmti_native::jobject,
+                    package.as_ptr(),
+                    &mut module,
+                )
+            })?;
+            unsafe { jni::objects::JObject::from_raw(module as jni::sys::jobject) }
+        } else {
+            env.call_method(
+                class_being_redefined,
+                "getModule",
+                "()Ljava/lang/Module;",
+                &[],
+            )?
+            .l()?
+        };
+        if module.is_null() {
+            return Ok("unnamed".to_string());
+        }
+
+        let name = env
+            .call_method(&module, "getName", "()Ljava/lang/String;", &[])?
+            .l()?;
+        if name.is_null() {
+            return Ok("unnamed".to_string());
+        }
+        let name = jni::objects::JString::from(name);
+        Ok(env.get_string(&name)?.to_string_lossy().into_owned())
+    });
+    if name.is_err() {
+        env.exception_clear()?;
+    }
+
+    name.map(Some)
+}
+
+// name of the thread running the caller
+pub fn current_thread_name(env: &mut jni::JNIEnv) -> Result<String, crate::error::Error> {
+    let name = env.with_local_frame(4, |env| -> Result<_, crate::error::Error> {
+        let thread = env
+            .call_static_method(
+                "java/lang/Thread",
+                "currentThread",
+                "()Ljava/lang/Thread;",
+                &[],
+            )?
+            .l()?;
+        let name = jni::objects::JString::from(
+            env.call_method(&thread, "getName", "()Ljava/lang/String;", &[])?
+                .l()?,
+        );
+        Ok(env.get_string(&name)?.to_string_lossy().into_owned())
+    });
+    if name.is_err() {
+        env.exception_clear()?;
+    }
+
+    name
+}
+
+// name of a class as the jvm reports it, e.g. with the address of a hidden class
+pub fn class_name(
+    env: &mut jni::JNIEnv,
+    class: &jni::objects::JObject,
+) -> Result<String, crate::error::Error> {
+    let name = env.with_local_frame(4, |env| -> Result<_, crate::error::Error> {
+        let name = jni::objects::JString::from(
+            env.call_method(class, "getName", "()Ljava/lang/String;", &[])?
+                .l()?,
+        );
+        Ok(env.get_string(&name)?.to_string_lossy().into_owned())
+    });
+    if name.is_err() {
+        env.exception_clear()?;
+    }
+
+    name
+}
+
+// native ClassLoader.defineClass0, every hidden class is defined through it
+pub type DefineClass0 = unsafe extern "system" fn(
+    env: *mut jni::sys::JNIEnv,
+    class: jni::sys::jclass,
+    loader: jni::sys::jobject,
+    lookup: jni::sys::jclass,
+    name: jni::sys::jstring,
+    bytes: jni::sys::jbyteArray,
+    offset: jni::sys::jint,
+    length: jni::sys::jint,
+    protection_domain: jni::sys::jobject,
+    initialize: jni::sys::jboolean,
+    flags: jni::sys::jint,
+    class_data: jni::sys::jobject,
+) -> jni::sys::jclass;
+
+const DEFINE_CLASS0_SIGNATURE: &str = "(Ljava/lang/ClassLoader;Ljava/lang/Class;Ljava/lang/String;[BIILjava/security/ProtectionDomain;ZILjava/lang/Object;)Ljava/lang/Class;";
+
+// defineClass0 as exported by java.dll, None before jdk 15 where it doesn't exist
+#[cfg(target_os = "windows")]
+pub fn define_class0() -> Option<DefineClass0> {
+    use winapi::um::libloaderapi::{GetModuleHandleA, GetProcAddress};
+    use windows::core::s;
+    let java_module = unsafe { GetModuleHandleA(s!("java.dll").as_ptr() as *const i8) };
+    if java_module.is_null() {
+        return None;
+    }
+
+    let define_class0 = unsafe {
+        GetProcAddress(
+            java_module,
+            s!("Java_java_lang_ClassLoader_defineClass0").as_ptr() as *const i8,
+        )
+    };
+    if define_class0.is_null() {
+        return None;
+    }
+
+    Some(unsafe {
+        std::mem::transmute::<winapi::shared::minwindef::FARPROC, DefineClass0>(define_class0)
+    })
+}
+
+// bind ClassLoader.defineClass0 to another function, the binding applies to every later call
+pub fn bind_define_class0(
+    env: &mut jni::JNIEnv,
+    function: DefineClass0,
+) -> Result<(), crate::error::Error> {
+    let class_loader = env.find_class("java/lang/ClassLoader")?;
+    env.register_native_methods(
+        &class_loader,
+        &[jni::NativeMethod {
+            name: "defineClass0".into(),
+            sig: DEFINE_CLASS0_SIGNATURE.into(),
+            fn_ptr: function as *mut c_void,
+        }],
+    )?;
+
+    Ok(())
+}
+
+// system properties copied into the session manifest
+const MANIFEST_PROPERTIES: [&str; 10] = [
+    "java.home",
+    "java.runtime.name",
+    "java.runtime.version",
+    "java.vm.name",
+    "java.vm.version",
+    "os.name",
+    "os.arch",
+    "os.version",
+    "user.dir",
+    "file.encoding",
+];
+
+fn system_property(
+    env: &mut jni::JNIEnv,
+    name: &str,
+) -> Result<Option<String>, crate::error::Error> {
+    env.with_local_frame(4, |env| -> Result<_, crate::error::Error> {
+        let name = env.new_string(name)?;
+        let value = env
+            .call_static_method(
+                "java/lang/System",
+                "getProperty",
+                "(Ljava/lang/String;)Ljava/lang/String;",
+                &[jni::objects::JValue::Object(&name)],
+            )?
+            .l()?;
+        if value.is_null() {
+            return Ok(None);
+        }
+        let value = jni::objects::JString::from(value);
+        Ok(Some(env.get_string(&value)?.to_string_lossy().into_owned()))
+    })
+}
+
+// jvm options from the runtime mxbean
+fn input_arguments(env: &mut jni::JNIEnv) -> Result<Vec<String>, crate::error::Error> {
+    env.with_local_frame(16, |env| -> Result<_, crate::error::Error> {
+        let runtime = env
+            .call_static_method(
+                "java/lang/management/ManagementFactory",
+                "getRuntimeMXBean",
+                "()Ljava/lang/management/RuntimeMXBean;",
+                &[],
+            )?
+            .l()?;
+        let arguments = env
+            .call_method(&runtime, "getInputArguments", "()Ljava/util/List;", &[])?
+            .l()?;
+        let arguments = jni::objects::JObjectArray::from(
+            env.call_method(&arguments, "toArray", "()[Ljava/lang/Object;", &[])?
+                .l()?,
+        );
+        (0..env.get_array_length(&arguments)?)
+            .map(|i| {
+                let argument =
+                    jni::objects::JString::from(env.get_object_array_element(&arguments, i)?);
+                let value = env.get_string(&argument)?.to_string_lossy().into_owned();
+                env.delete_local_ref(argument)?;
+                Ok(value)
+            })
+            .collect()
+    })
+}
+
+// version, command line and selected properties of the jvm, for the session manifest.
+// parts that can't be read are left out.
+pub fn jvm_info(env: &mut jni::JNIEnv) -> crate::manifest::JvmInfo {
+    let mut property = |name: &str| match system_property(env, name) {
+        Ok(value) => value,
+        Err(e) => {
+            println!("failed to read system property {name}: {e}");
+            let _ = env.exception_clear();
+            None
+        }
+    };
+
+    let mut info = crate::manifest::JvmInfo {
+        version: property("java.version"),
+        vendor: property("java.vendor"),
+        class_path: property("java.class.path"),
+        command: property("sun.java.command"),
+        ..Default::default()
+    };
+    for name in MANIFEST_PROPERTIES {
+        if let Some(value) = property(name) {
+            info.properties.insert(name.to_string(), value);
+        }
+    }
+
+    match input_arguments(env) {
+        Ok(arguments) => info.input_arguments = arguments,
+        Err(e) => {
+            println!("failed to read jvm input arguments: {e}");
+            let _ = env.exception_clear();
+        }
+    }
+
+    info
+}
@@ -4,17 +4,58 @@ mod injector;
 mod jvm;
 
 pub mod bridge;
+pub mod classfile;
+pub mod classpath;
 pub mod client;
+pub mod closure;
+pub mod config;
+pub mod crawl;
 pub mod console;
+pub mod diff;
+pub mod dump;
+pub mod dynamic;
 pub mod error;
+pub mod filter;
+pub mod graph;
+pub mod hierarchy;
+pub mod manifest;
+pub mod origin;
+pub mod pipeline;
+pub mod recorder;
+pub mod runnable;
+pub mod sink;
+pub mod sqlite;
+pub mod store;
+
+// how often the thread started on load looks again while the jvm is starting
+const STARTUP_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(10);
 
 fn process_attach() -> Result<(), error::Error> {
+    // a dll given with -agentpath is loaded before the jvm is live and Agent_OnLoad is called
+    // right after, an injected one finds a live jvm and attaches at once
+    loop {
+        if injector::BAgentInjector::is_startup() {
+            return Ok(());
+        }
+        if jvm::jvm_live()? {
+            break;
+        }
+        std::thread::sleep(STARTUP_POLL_INTERVAL);
+    }
+
     let client = client::Client::new();
     injector::BAgentInjector::run(client)?;
 
     Ok(())
 }
 
+fn process_load(vm: *mut jni::sys::JavaVM) -> Result<(), error::Error> {
+    let client = client::Client::new();
+    injector::BAgentInjector::on_load(client, vm)?;
+
+    Ok(())
+}
+
 #[cfg(target_os = "windows")]
 mod win {
     use windows::Win32::{Foundation::HINSTANCE, System::SystemServices::DLL_PROCESS_ATTACH};
@@ -32,4 +73,20 @@ mod win {
 
         true
     }
+
+    // loaded with -agentpath when the jvm starts
+    #[unsafe(no_mangle)]
+    extern "system" fn Agent_OnLoad(
+        vm: *mut jni::sys::JavaVM,
+        _options: *mut std::ffi::c_char,
+        _reserved: *mut std::ffi::c_void,
+    ) -> jni::sys::jint {
+        match super::process_load(vm) {
+            Ok(_) => jni::sys::JNI_OK,
+            Err(e) => {
+                println!("error: {e:?}");
+                jni::sys::JNI_ERR
+            }
+        }
+    }
 }
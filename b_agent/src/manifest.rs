@@ -0,0 +1,294 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+};
+
+use crate::sink::OutputSink;
+
+pub const MANIFEST_FILE_NAME: &str = "session.json";
+
+// the jvm a session dumped from
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct JvmInfo {
+    pub version: Option<String>,
+    pub vendor: Option<String>,
+    pub class_path: Option<String>,
+    // main class or jar and its arguments
+    pub command: Option<String>,
+    // jvm options the process was started with
+    pub input_arguments: Vec<String>,
+    pub properties: BTreeMap<String, String>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct FileEntry {
+    // bucket of the jar or directory a class was loaded from, None for reports
+    pub origin: Option<String>,
+    pub sha256: String,
+    pub size: usize,
+}
+
+// what a dump came from and what it contains, written to session.json at the end of a session
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct SessionManifest {
+    pub agent_version: String,
+    pub options: crate::config::Config,
+    // None for offline extraction
+    pub jvm: Option<JvmInfo>,
+    // jvmti capabilities the agent asked for, and whether the jvm granted them
+    pub capabilities: BTreeMap<String, bool>,
+    // milliseconds since the unix epoch
+    pub started_at: u64,
+    pub finished_at: u64,
+    pub dumped: usize,
+    pub failed: usize,
+    pub unresolved: usize,
+    // every file of the dump by its path relative to the dump root, except this manifest
+    pub files: BTreeMap<String, FileEntry>,
+}
+
+impl SessionManifest {
+    pub fn new(options: crate::config::Config) -> Self {
+        SessionManifest {
+            agent_version: env!("CARGO_PKG_VERSION").to_string(),
+            options,
+            started_at: unix_millis(),
+            ..Default::default()
+        }
+    }
+}
+
+// passes writes through and remembers the hash of every file for the manifest
+pub struct HashingSink {
+    inner: Box<dyn OutputSink>,
+    files: BTreeMap<String, FileEntry>,
+}
+
+impl HashingSink {
+    pub fn new(inner: Box<dyn OutputSink>) -> Self {
+        HashingSink {
+            inner,
+            files: BTreeMap::new(),
+        }
+    }
+
+    // a file that reached the dump some other way, e.g. a class put into the store
+    pub fn record(&mut self, origin: Option<&str>, path: &str, data: &[u8]) {
+        self.files.insert(
+            path.to_string(),
+            FileEntry {
+                origin: origin.map(str::to_string),
+                sha256: crate::store::sha256_hex(data),
+                size: data.len(),
+            },
+        );
+    }
+
+    pub fn files(&self) -> &BTreeMap<String, FileEntry> {
+        &self.files
+    }
+
+    // files of an earlier attach to the same output
+    pub fn restore(&mut self, files: BTreeMap<String, FileEntry>) {
+        for (path, entry) in files {
+            self.files.entry(path).or_insert(entry);
+        }
+    }
+}
+
+impl OutputSink for HashingSink {
+    fn write(&mut self, path: &str, data: &[u8]) -> Result<(), crate::error::Error> {
+        self.record(None, path, data);
+        self.inner.write(path, data)
+    }
+
+    fn write_class(
+        &mut self,
+        origin: &str,
+        path: &str,
+        data: &[u8],
+    ) -> Result<(), crate::error::Error> {
+        self.record(Some(origin), path, data);
+        self.inner.write_class(origin, path, data)
+    }
+
+    fn finish(&mut self) -> Result<(), crate::error::Error> {
+        self.inner.finish()
+    }
+}
+
+#[derive(Debug, Default, serde::Serialize)]
+pub struct Verification {
+    pub checked: usize,
+    pub missing: Vec<String>,
+    pub mismatched: Vec<String>,
+}
+
+impl Verification {
+    pub fn is_ok(&self) -> bool {
+        self.missing.is_empty() && self.mismatched.is_empty()
+    }
+}
+
+// check the files of a dump directory, jar or tar against the manifest inside it.
+// origins are looked up as directories or archives below the root, like grouped outputs.
+pub fn verify(output: &Path) -> Result<Verification, crate::error::Error> {
+    let mut reader = OutputReader::open(output)?;
+    let manifest = reader.manifest()?;
+
+    let mut verification = Verification::default();
+    for (path, entry) in &manifest.files {
+        verification.checked += 1;
+        match reader.read(entry.origin.as_deref(), path)? {
+            None => verification.missing.push(path.clone()),
+            Some(data) if crate::store::sha256_hex(&data) != entry.sha256 => {
+                verification.mismatched.push(path.clone())
+            }
+            Some(_) => {}
+        }
+    }
+
+    Ok(verification)
+}
+
+// files of a dump directory, jar or tar, including grouped outputs
+pub struct OutputReader {
+    root: PathBuf,
+    // entries of the output when it is a single archive
+    archive: Option<BTreeMap<String, Vec<u8>>>,
+    // entries of per origin archives read so far
+    groups: HashMap<String, BTreeMap<String, Vec<u8>>>,
+}
+
+impl OutputReader {
+    pub fn open(root: &Path) -> Result<Self, crate::error::Error> {
+        let archive = if root.is_file() {
+            Some(read_archive(root)?)
+        } else {
+            None
+        };
+        Ok(OutputReader {
+            root: root.to_path_buf(),
+            archive,
+            groups: HashMap::new(),
+        })
+    }
+
+    pub fn manifest(&mut self) -> Result<SessionManifest, crate::error::Error> {
+        let Some(manifest) = self.read(None, MANIFEST_FILE_NAME)? else {
+            return Err(crate::error::Error::XValueNotOfType(
+                "session.json in the output",
+            ));
+        };
+        Ok(serde_json::from_slice(&manifest)?)
+    }
+
+    // a file by its path in the dump, classes are also looked up below their origin
+    pub fn read(
+        &mut self,
+        origin: Option<&str>,
+        path: &str,
+    ) -> Result<Option<Vec<u8>>, crate::error::Error> {
+        if let Some(archive) = &self.archive {
+            return Ok(archive.get(path).cloned());
+        }
+
+        if let Some(data) = read_file(&self.root, path)? {
+            return Ok(Some(data));
+        }
+        let Some(origin) = origin else {
+            return Ok(None);
+        };
+        if let Some(data) = read_file(&self.root.join(origin), path)? {
+            return Ok(Some(data));
+        }
+        if !self.groups.contains_key(origin) {
+            let archive = ["jar", "tar"]
+                .iter()
+                .map(|extension| self.root.join(format!("{origin}.{extension}")))
+                .find(|archive| archive.is_file());
+            let entries = match archive {
+                Some(archive) => read_archive(&archive)?,
+                None => BTreeMap::new(),
+            };
+            self.groups.insert(origin.to_string(), entries);
+        }
+        Ok(self.groups[origin].get(path).cloned())
+    }
+}
+
+fn read_file(root: &Path, path: &str) -> Result<Option<Vec<u8>>, crate::error::Error> {
+    let mut file_path = root.to_path_buf();
+    file_path.extend(path.split('/'));
+    match std::fs::read(file_path) {
+        Ok(data) => Ok(Some(data)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+// every file of a jar or tar, told apart by the zip signature
+pub fn read_archive(path: &Path) -> Result<BTreeMap<String, Vec<u8>>, crate::error::Error> {
+    let data = std::fs::read(path)?;
+    if !data.starts_with(b"PK") {
+        return crate::sink::tar::read_entries(data.as_slice());
+    }
+
+    let mut archive = zip::ZipArchive::new(std::io::Cursor::new(data))?;
+    let mut entries = BTreeMap::new();
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        if file.is_dir() {
+            continue;
+        }
+        let mut data = Vec::new();
+        std::io::Read::read_to_end(&mut file, &mut data)?;
+        entries.insert(file.name().to_string(), data);
+    }
+    Ok(entries)
+}
+
+pub fn unix_millis() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_millis() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify_tar() {
+        let path =
+            std::env::temp_dir().join(format!("b_agent_manifest_{}.tar", std::process::id()));
+        let mut sink = HashingSink::new(Box::new(crate::sink::TarSink::new(
+            std::fs::File::create(&path).unwrap(),
+        )));
+        sink.write_class("foo", "a/B.class", &[0xCA, 0xFE]).unwrap();
+        sink.write("origins.json", b"{}").unwrap();
+
+        let mut manifest = SessionManifest::new(Default::default());
+        manifest.files = sink.files().clone();
+        manifest.files.insert(
+            "a/C.class".to_string(),
+            FileEntry {
+                origin: Some("foo".to_string()),
+                sha256: crate::store::sha256_hex(b""),
+                size: 0,
+            },
+        );
+        sink.write(MANIFEST_FILE_NAME, &serde_json::to_vec(&manifest).unwrap())
+            .unwrap();
+        sink.finish().unwrap();
+
+        let verification = verify(&path).unwrap();
+        assert_eq!(verification.checked, 3);
+        assert_eq!(verification.missing, ["a/C.class"]);
+        assert!(verification.mismatched.is_empty());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,198 @@
+use std::collections::{BTreeMap, HashMap};
+
+// bucket of classes without a code source, e.g. generated or defined at runtime
+pub const NO_CODE_SOURCE: &str = "no-code-source";
+
+const ARCHIVE_EXTENSIONS: [&str; 4] = [".jar", ".zip", ".war", ".ear"];
+
+// short name of the jar or directory a code source location points at, without extension.
+// nested archives resolve to the innermost one, so spring boot's
+// jar:file:/app.jar!/BOOT-INF/lib/foo-1.0.jar!/ and nested:/app.jar/!BOOT-INF/lib/foo-1.0.jar
+// are both foo-1.0, and directories inside an archive are prefixed by it (app-classes).
+pub fn bucket_name(location: &str) -> String {
+    let decoded = percent_decode(location);
+    let mut rest = decoded.as_str();
+    while let Some((scheme, after)) = rest.split_once(':') {
+        // a single letter is a windows drive, not a scheme
+        if scheme.len() < 2 || !scheme.chars().all(|c| c.is_ascii_alphabetic()) {
+            break;
+        }
+        rest = after;
+    }
+
+    let parts = rest
+        .split('!')
+        .map(|part| part.trim_matches(['/', '\\']))
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>();
+    let Some(innermost) = parts.last() else {
+        return "unnamed".to_string();
+    };
+
+    let name = match strip_archive_extension(file_name(innermost)) {
+        Some(stem) => stem.to_string(),
+        None if parts.len() > 1 => {
+            let outer = file_name(parts[parts.len() - 2]);
+            let outer = strip_archive_extension(outer).unwrap_or(outer);
+            format!("{outer}-{}", file_name(innermost))
+        }
+        None => file_name(innermost).to_string(),
+    };
+
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+fn file_name(path: &str) -> &str {
+    path.rsplit(['/', '\\']).next().unwrap_or(path)
+}
+
+fn strip_archive_extension(name: &str) -> Option<&str> {
+    ARCHIVE_EXTENSIONS.iter().find_map(|extension| {
+        name.len()
+            .checked_sub(extension.len())
+            .filter(|&end| name[end..].eq_ignore_ascii_case(extension))
+            .map(|end| &name[..end])
+    })
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct Bucket {
+    location: Option<String>,
+    classes: usize,
+}
+
+// buckets handed out so far, written to origins.json
+#[derive(Debug, Default)]
+pub struct Origins {
+    buckets: BTreeMap<String, Bucket>,
+    by_location: HashMap<Option<String>, String>,
+}
+
+impl Origins {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // bucket for a class loaded from a location, different locations sharing a name
+    // get numbered buckets
+    pub fn bucket(&mut self, location: Option<&str>) -> String {
+        let key = location.map(str::to_string);
+        let name = match self.by_location.get(&key) {
+            Some(name) => name.clone(),
+            None => {
+                let base = location.map_or(NO_CODE_SOURCE.to_string(), bucket_name);
+                let mut name = base.clone();
+                let mut suffix = 1;
+                while self.buckets.contains_key(&name) {
+                    suffix += 1;
+                    name = format!("{base}-{suffix}");
+                }
+                self.by_location.insert(key.clone(), name.clone());
+                self.buckets.insert(
+                    name.clone(),
+                    Bucket {
+                        location: key,
+                        classes: 0,
+                    },
+                );
+                name
+            }
+        };
+
+        if let Some(bucket) = self.buckets.get_mut(&name) {
+            bucket.classes += 1;
+        }
+        name
+    }
+
+    // bucket handed out for a location, without counting another class in it
+    pub fn name(&self, location: Option<&str>) -> Option<&str> {
+        self.by_location
+            .get(&location.map(str::to_string))
+            .map(String::as_str)
+    }
+
+    // read back buckets written by to_json, so classes keep going to the same buckets
+    pub fn from_json(data: &[u8]) -> Result<Self, crate::error::Error> {
+        let buckets: BTreeMap<String, Bucket> = serde_json::from_slice(data)?;
+        let by_location = buckets
+            .iter()
+            .map(|(name, bucket)| (bucket.location.clone(), name.clone()))
+            .collect();
+        Ok(Origins {
+            buckets,
+            by_location,
+        })
+    }
+
+    pub fn to_json(&self) -> Result<Vec<u8>, crate::error::Error> {
+        Ok(serde_json::to_vec_pretty(&self.buckets)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bucket_names() {
+        for (location, expected) in [
+            ("file:/C:/app/lib/foo-1.0.jar", "foo-1.0"),
+            ("file:/C:/app/My%20Classes/", "My_Classes"),
+            (
+                "jar:file:/C:/app/app.jar!/BOOT-INF/lib/foo-1.0.jar!/",
+                "foo-1.0",
+            ),
+            (
+                "jar:file:/C:/app/app.jar!/BOOT-INF/classes!/",
+                "app-classes",
+            ),
+            (
+                "nested:/C:/app/app.jar/!BOOT-INF/lib/foo-1.0.jar",
+                "foo-1.0",
+            ),
+            ("nested:/C:/app/app.jar/!BOOT-INF/classes/!/", "app-classes"),
+            ("jrt:/java.sql", "java.sql"),
+            ("file:C:\\app\\bar.JAR", "bar"),
+        ] {
+            assert_eq!(bucket_name(location), expected, "{location}");
+        }
+
+        let mut origins = Origins::new();
+        assert_eq!(origins.bucket(Some("file:/a/foo.jar")), "foo");
+        assert_eq!(origins.bucket(Some("file:/b/foo.jar")), "foo-2");
+        assert_eq!(origins.bucket(Some("file:/a/foo.jar")), "foo");
+        assert_eq!(origins.bucket(None), NO_CODE_SOURCE);
+    }
+}
@@ -0,0 +1,371 @@
+use std::{
+    cell::Cell,
+    collections::VecDeque,
+    path::PathBuf,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+        mpsc,
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use crate::config::Backpressure;
+
+// how long an idle worker waits before looking at spilled classes
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+thread_local! {
+    static IS_WORKER: Cell<bool> = const { Cell::new(false) };
+}
+
+// whether the caller runs on a pipeline worker, classes loaded there come from the analysis
+pub fn is_worker() -> bool {
+    IS_WORKER.get()
+}
+
+// reads the part of the load context the hook left to the worker
+pub type Resolve = Box<dyn FnOnce(&mut crate::recorder::ClassSource) + Send>;
+
+// a class handed over by the load hook
+pub struct LoadedClass {
+    // as passed to the hook, generated classes are named by the worker
+    pub name: String,
+    pub data: Vec<u8>,
+    pub source: crate::recorder::ClassSource,
+    // bytes as loaded, before agents able to retransform changed them, when captured
+    pub original: Option<Vec<u8>>,
+    pub resolve: Option<Resolve>,
+}
+
+impl LoadedClass {
+    // complete the source, done by the worker or the spill thread
+    pub fn resolve_source(&mut self) {
+        if let Some(resolve) = self.resolve.take() {
+            resolve(&mut self.source);
+        }
+    }
+}
+
+// a spilled class, its bytes are in a .class file next to it
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SpillHeader {
+    name: String,
+    source: crate::recorder::ClassSource,
+    // bytes as loaded are in a .original file
+    #[serde(default)]
+    original: bool,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct DroppedClass {
+    // as passed to the hook, dropped classes are not named
+    pub class: String,
+    // only known for classes dropped after their source was read
+    pub loader: Option<String>,
+}
+
+// written to pipeline.json at the end of a session
+#[derive(Debug, Default, serde::Serialize)]
+pub struct PipelineReport {
+    pub processed: usize,
+    pub spilled: usize,
+    // classes given up because the queue was full, they are not in the dump
+    pub dropped: Vec<DroppedClass>,
+}
+
+struct Shared {
+    backpressure: Backpressure,
+    spill_dir: PathBuf,
+    // numbers of the spilled classes, oldest first
+    spilled: Mutex<VecDeque<usize>>,
+    next_spill: AtomicUsize,
+    // classes submitted and not processed yet
+    in_flight: AtomicUsize,
+    report: Mutex<PipelineReport>,
+}
+
+impl Shared {
+    fn spill(&self, class: &mut LoadedClass) -> Result<(), crate::error::Error> {
+        class.resolve_source();
+        let number = self.next_spill.fetch_add(1, Ordering::SeqCst);
+        std::fs::create_dir_all(&self.spill_dir)?;
+        std::fs::write(self.spill_dir.join(format!("{number}.class")), &class.data)?;
+        if let Some(original) = &class.original {
+            std::fs::write(self.spill_dir.join(format!("{number}.original")), original)?;
+        }
+        let header = SpillHeader {
+            name: class.name.clone(),
+            source: class.source.clone(),
+            original: class.original.is_some(),
+        };
+        std::fs::write(
+            self.spill_dir.join(format!("{number}.json")),
+            serde_json::to_vec(&header)?,
+        )?;
+
+        self.spilled.lock().unwrap().push_back(number);
+        self.report.lock().unwrap().spilled += 1;
+        Ok(())
+    }
+
+    fn take_spilled(&self) -> Option<Result<LoadedClass, crate::error::Error>> {
+        let number = self.spilled.lock().unwrap().pop_front()?;
+        let class_path = self.spill_dir.join(format!("{number}.class"));
+        let header_path = self.spill_dir.join(format!("{number}.json"));
+        let original_path = self.spill_dir.join(format!("{number}.original"));
+        let read = || -> Result<LoadedClass, crate::error::Error> {
+            let header: SpillHeader = serde_json::from_slice(&std::fs::read(&header_path)?)?;
+            let data = std::fs::read(&class_path)?;
+            let original = header
+                .original
+                .then(|| std::fs::read(&original_path))
+                .transpose()?;
+            let _ = std::fs::remove_file(&class_path);
+            let _ = std::fs::remove_file(&original_path);
+            let _ = std::fs::remove_file(&header_path);
+            Ok(LoadedClass {
+                name: header.name,
+                data,
+                source: header.source,
+                original,
+                resolve: None,
+            })
+        };
+        Some(read())
+    }
+
+    fn drop_class(&self, class: &LoadedClass) {
+        println!("queue full, dropped class: {}", class.name);
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+        self.report.lock().unwrap().dropped.push(DroppedClass {
+            class: class.name.clone(),
+            loader: class.source.loader.clone(),
+        });
+    }
+
+    fn done(&self) {
+        self.report.lock().unwrap().processed += 1;
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+// hands classes from the load hook to the workers, cheap to clone
+#[derive(Clone)]
+pub struct Submitter {
+    sender: mpsc::SyncSender<LoadedClass>,
+    // classes to spill, written by the spill thread so the loading thread doesn't wait on disk
+    spill: mpsc::Sender<LoadedClass>,
+    shared: Arc<Shared>,
+}
+
+impl Submitter {
+    // queue a class, applying the backpressure policy when the queue is full.
+    // workers never block on the queue they drain, they spill instead.
+    pub fn submit(&self, class: LoadedClass) {
+        self.shared.in_flight.fetch_add(1, Ordering::SeqCst);
+        let class = match self.sender.try_send(class) {
+            Ok(()) => return,
+            Err(mpsc::TrySendError::Full(class)) => class,
+            Err(mpsc::TrySendError::Disconnected(class)) => {
+                self.shared.drop_class(&class);
+                return;
+            }
+        };
+
+        match self.shared.backpressure {
+            Backpressure::Block if !IS_WORKER.get() => {
+                if let Err(mpsc::SendError(class)) = self.sender.send(class) {
+                    self.shared.drop_class(&class);
+                }
+            }
+            Backpressure::Drop => self.shared.drop_class(&class),
+            Backpressure::Block | Backpressure::Spill => {
+                if let Err(mpsc::SendError(class)) = self.spill.send(class) {
+                    self.shared.drop_class(&class);
+                }
+            }
+        }
+    }
+}
+
+// bounded queue of hooked classes and the threads saving and analyzing them,
+// so the loading thread only copies the bytes
+pub struct Pipeline {
+    submitter: Option<Submitter>,
+    shared: Arc<Shared>,
+    workers: Vec<JoinHandle<()>>,
+    spiller: Option<JoinHandle<()>>,
+}
+
+impl Pipeline {
+    pub fn start(
+        config: &crate::config::PipelineConfig,
+        process: impl Fn(LoadedClass) + Send + Sync + 'static,
+    ) -> Self {
+        let (sender, receiver) = mpsc::sync_channel(config.capacity);
+        let receiver = Arc::new(Mutex::new(receiver));
+        let process = Arc::new(process);
+        let shared = Arc::new(Shared {
+            backpressure: config.backpressure,
+            spill_dir: config.spill_path.clone().unwrap_or_else(|| {
+                std::env::temp_dir().join(format!("b_agent_spill_{}", std::process::id()))
+            }),
+            spilled: Mutex::new(VecDeque::new()),
+            next_spill: AtomicUsize::new(0),
+            in_flight: AtomicUsize::new(0),
+            report: Mutex::new(PipelineReport::default()),
+        });
+
+        let workers = (0..config.workers.max(1))
+            .map(|_| {
+                let receiver = Arc::clone(&receiver);
+                let process = Arc::clone(&process);
+                let shared = Arc::clone(&shared);
+                std::thread::spawn(move || {
+                    IS_WORKER.set(true);
+                    loop {
+                        let received = receiver.lock().unwrap().recv_timeout(POLL_INTERVAL);
+                        let class = match received {
+                            Ok(class) => class,
+                            // spilled classes are picked up once the queue is drained
+                            Err(timeout_or_closed) => match shared.take_spilled() {
+                                Some(Ok(class)) => class,
+                                Some(Err(e)) => {
+                                    println!("failed to read a spilled class: {e}");
+                                    shared.in_flight.fetch_sub(1, Ordering::SeqCst);
+                                    continue;
+                                }
+                                // classes may still be on their way to the spill directory
+                                None if timeout_or_closed
+                                    == mpsc::RecvTimeoutError::Disconnected =>
+                                {
+                                    if shared.in_flight.load(Ordering::SeqCst) == 0 {
+                                        break;
+                                    }
+                                    std::thread::sleep(POLL_INTERVAL);
+                                    continue;
+                                }
+                                None => continue,
+                            },
+                        };
+                        process(class);
+                        shared.done();
+                    }
+                })
+            })
+            .collect();
+
+        // reading the source of a spilled class may load classes, they are left as they are
+        // like the ones loaded on workers
+        let (spill, spill_receiver) = mpsc::channel::<LoadedClass>();
+        let spiller = std::thread::spawn({
+            let shared = Arc::clone(&shared);
+            move || {
+                IS_WORKER.set(true);
+                for mut class in spill_receiver {
+                    if let Err(e) = shared.spill(&mut class) {
+                        println!("failed to spill {}: {e}", class.name);
+                        shared.drop_class(&class);
+                    }
+                }
+            }
+        });
+
+        Pipeline {
+            submitter: Some(Submitter {
+                sender,
+                spill,
+                shared: Arc::clone(&shared),
+            }),
+            shared,
+            workers,
+            spiller: Some(spiller),
+        }
+    }
+
+    pub fn submitter(&self) -> Option<Submitter> {
+        self.submitter.clone()
+    }
+
+    // nothing queued, spilled or being processed
+    pub fn is_idle(&self) -> bool {
+        self.shared.in_flight.load(Ordering::SeqCst) == 0
+    }
+
+    // let the workers finish what was submitted and stop them
+    pub fn shutdown(mut self) -> PipelineReport {
+        self.submitter.take();
+        for worker in self.workers.drain(..).chain(self.spiller.take()) {
+            if worker.join().is_err() {
+                println!("a pipeline worker panicked");
+            }
+        }
+        // only removed when every spilled class was read back
+        let _ = std::fs::remove_dir(&self.shared.spill_dir);
+
+        std::mem::take(&mut self.shared.report.lock().unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spill_when_full() {
+        let spill_dir =
+            std::env::temp_dir().join(format!("b_agent_spill_test_{}", std::process::id()));
+        let config = crate::config::PipelineConfig {
+            workers: 1,
+            capacity: 1,
+            backpressure: Backpressure::Spill,
+            spill_path: Some(spill_dir.clone()),
+        };
+        // the worker is held up on the first class until every class is submitted
+        let (release, gate) = mpsc::channel::<()>();
+        let gate = Mutex::new(gate);
+        let (started, worker_busy) = mpsc::channel::<()>();
+        let processed = Arc::new(Mutex::new(Vec::new()));
+        let pipeline = Pipeline::start(&config, {
+            let processed = Arc::clone(&processed);
+            let started = Mutex::new(started);
+            move |class: LoadedClass| {
+                let _ = started.lock().unwrap().send(());
+                let _ = gate.lock().unwrap().recv();
+                processed.lock().unwrap().push((class.name, class.data));
+            }
+        });
+
+        let submitter = pipeline.submitter().unwrap();
+        let class = |i: u8| LoadedClass {
+            name: format!("a.C{i}"),
+            data: vec![i],
+            source: Default::default(),
+            original: None,
+            resolve: None,
+        };
+        submitter.submit(class(0));
+        worker_busy.recv().unwrap();
+        // one class fits the queue, the others are spilled
+        for i in 1..5 {
+            submitter.submit(class(i));
+        }
+        assert!(!pipeline.is_idle());
+        drop(release);
+        drop(submitter);
+
+        let report = pipeline.shutdown();
+        assert_eq!(report.processed, 5);
+        assert_eq!(report.spilled, 3);
+        assert!(report.dropped.is_empty());
+        assert_eq!(
+            *processed.lock().unwrap(),
+            (0..5)
+                .map(|i| (format!("a.C{i}"), vec![i]))
+                .collect::<Vec<_>>()
+        );
+        assert!(!spill_dir.exists());
+    }
+}
@@ -0,0 +1,80 @@
+use std::collections::{BTreeMap, HashMap};
+
+// one distinct set of bytes a class was saved with
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct Version {
+    pub sequence: usize,
+    pub sha256: String,
+    // milliseconds since the unix epoch
+    pub recorded_at: Option<u64>,
+    // hooked while someone other than the agent redefined or retransformed the class
+    pub redefined: bool,
+    pub thread: Option<String>,
+    // stack of the redefining thread, innermost frame first
+    pub stack: Vec<String>,
+    // what changed since the previous version, None for the first or when it isn't known
+    pub changes: Option<crate::diff::ClassDiff>,
+}
+
+// every version of the classes saved more than once, written to history.json.
+// the latest bytes of each class are kept to compare the next version with.
+#[derive(Default)]
+pub struct VersionHistory {
+    versions: BTreeMap<String, Vec<Version>>,
+    latest: HashMap<String, Vec<u8>>,
+}
+
+impl VersionHistory {
+    // record the bytes a class was just saved with, returns the versions to write next to it.
+    // nothing is written until a second version turns up, then the first is written as well.
+    // the key tells classes of the same name from different loaders apart in store mode.
+    pub fn record(
+        &mut self,
+        key: &str,
+        class_name: &str,
+        class_data: &[u8],
+        source: &super::ClassSource,
+    ) -> Vec<(usize, Vec<u8>)> {
+        let previous = self.latest.insert(key.to_string(), class_data.to_vec());
+        let versions = self.versions.entry(key.to_string()).or_default();
+        let version = |sequence, changes| Version {
+            sequence,
+            sha256: crate::store::sha256_hex(class_data),
+            recorded_at: source.loaded_at,
+            redefined: source.redefined,
+            thread: source.thread.clone(),
+            stack: source.stack.clone(),
+            changes,
+        };
+
+        let changes = previous
+            .as_deref()
+            .map(|previous| crate::diff::diff_class(class_name, previous, class_data));
+        let sequence = versions.len() + 1;
+        versions.push(version(sequence, changes));
+
+        match (sequence, previous) {
+            // a first version is only kept in memory
+            (1, _) => Vec::new(),
+            (2, Some(first)) => vec![(1, first), (2, class_data.to_vec())],
+            _ => vec![(sequence, class_data.to_vec())],
+        }
+    }
+
+    // read back a history written by to_json, the latest bytes are not in it
+    pub fn from_json(data: &[u8]) -> Result<Self, crate::error::Error> {
+        Ok(VersionHistory {
+            versions: serde_json::from_slice(data)?,
+            latest: HashMap::new(),
+        })
+    }
+
+    pub fn to_json(&self) -> Result<Vec<u8>, crate::error::Error> {
+        let versions = self
+            .versions
+            .iter()
+            .filter(|(_, versions)| versions.len() > 1)
+            .collect::<BTreeMap<_, _>>();
+        Ok(serde_json::to_vec_pretty(&versions)?)
+    }
+}
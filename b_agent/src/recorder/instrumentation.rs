@@ -0,0 +1,55 @@
+// compares the bytes classes were loaded with to the bytes they run with after other
+// agents transformed them, written to instrumentation.json
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+pub struct InstrumentationReport {
+    // classes hooked with their bytes as loaded
+    compared: usize,
+    unchanged: usize,
+    // hooked without them, e.g. classes loaded before attach and retransformed
+    not_captured: usize,
+    changed: Vec<crate::diff::ClassDiff>,
+}
+
+impl InstrumentationReport {
+    pub fn is_empty(&self) -> bool {
+        self.compared == 0 && self.not_captured == 0
+    }
+
+    // compare a hooked class to its bytes as loaded, returns whether other agents changed it.
+    // byte level differences not showing in the class structure don't count as a change.
+    pub fn compare(
+        &mut self,
+        class_name: &str,
+        original: Option<&[u8]>,
+        class_data: &[u8],
+    ) -> bool {
+        let Some(original) = original else {
+            self.not_captured += 1;
+            return false;
+        };
+        self.compared += 1;
+        // a class saved again replaces what was found for it before
+        self.changed.retain(|diff| diff.name != class_name);
+        if original == class_data {
+            self.unchanged += 1;
+            return false;
+        }
+
+        let diff = crate::diff::diff_class(class_name, original, class_data);
+        if diff.is_empty() && !diff.other_changes {
+            self.unchanged += 1;
+            return false;
+        }
+        self.changed.push(diff);
+        true
+    }
+
+    // read back a report written by to_json
+    pub fn from_json(data: &[u8]) -> Result<Self, crate::error::Error> {
+        Ok(serde_json::from_slice(data)?)
+    }
+
+    pub fn to_json(&self) -> Result<Vec<u8>, crate::error::Error> {
+        Ok(serde_json::to_vec_pretty(self)?)
+    }
+}
@@ -0,0 +1,646 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
+
+use crate::graph::EdgeKind;
+use crate::sink::OutputSink as _;
+
+mod history;
+mod instrumentation;
+mod state;
+mod validation;
+
+// where and how a class was loaded, as far as it is known.
+// everything but the code source is only known for classes hooked in a jvm.
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct ClassSource {
+    // class name and identity hash of the defining loader, "bootstrap" for the boot loader
+    pub loader: Option<String>,
+    // protection domain url
+    pub code_source: Option<String>,
+    // "unnamed" outside named modules, None on jvms without modules
+    pub module: Option<String>,
+    // name of the loading thread
+    pub thread: Option<String>,
+    // milliseconds since the unix epoch
+    pub loaded_at: Option<u64>,
+    // java stack of the loading thread, innermost frame first.
+    // empty for classes hooked while the agent retransforms them.
+    pub stack: Vec<String>,
+    // hooked while someone other than the agent redefined or retransformed the class,
+    // the thread and stack are the redefiner's
+    pub redefined: bool,
+    // set for hidden, anonymous and generated classes, which are saved under a synthetic name
+    pub dynamic: Option<crate::dynamic::DynamicOrigin>,
+}
+
+#[derive(serde::Serialize)]
+struct Metadata<'a> {
+    class: &'a str,
+    #[serde(flatten)]
+    source: &'a ClassSource,
+}
+
+// contents of the `.class.json` file written next to a class loaded in a jvm
+pub fn metadata_sidecar(
+    class_name: &str,
+    source: &ClassSource,
+) -> Result<Vec<u8>, crate::error::Error> {
+    Ok(serde_json::to_vec_pretty(&Metadata {
+        class: class_name,
+        source,
+    })?)
+}
+
+// saves classes into an output sink and collects the reports written next to them.
+// shared by the agent and offline extraction so both produce the same dumps.
+pub struct DumpRecorder {
+    // hashes everything passing through for the session manifest
+    sink: crate::manifest::HashingSink,
+    // class bytes go here instead of the sink in store mode, the sink only gets reports
+    store: Option<crate::store::ClassStore>,
+    config: crate::config::Config,
+    manifest: crate::manifest::SessionManifest,
+    failed: BTreeSet<String>,
+    graph: crate::graph::DependencyGraph,
+    hierarchy: crate::hierarchy::HierarchyIndex,
+    origins: crate::origin::Origins,
+    state: state::SessionState,
+    // where the state is kept between attaches, None when the output can't be resumed
+    state_path: Option<PathBuf>,
+    validation: validation::ValidationReport,
+    instrumentation: instrumentation::InstrumentationReport,
+    history: history::VersionHistory,
+    // generated classes by synthetic name, written to dynamic.json
+    dynamic: BTreeMap<String, crate::dynamic::DynamicOrigin>,
+}
+
+impl DumpRecorder {
+    pub fn new(sink: Box<dyn crate::sink::OutputSink>, config: crate::config::Config) -> Self {
+        DumpRecorder {
+            sink: crate::manifest::HashingSink::new(sink),
+            store: None,
+            manifest: crate::manifest::SessionManifest::new(config.clone()),
+            failed: BTreeSet::new(),
+            config,
+            graph: crate::graph::DependencyGraph::new(),
+            hierarchy: crate::hierarchy::HierarchyIndex::new(),
+            origins: crate::origin::Origins::new(),
+            state: state::SessionState::default(),
+            state_path: None,
+            validation: validation::ValidationReport::new(),
+            instrumentation: instrumentation::InstrumentationReport::default(),
+            history: history::VersionHistory::default(),
+            dynamic: BTreeMap::new(),
+        }
+    }
+
+    // recorder for the agent, saving into the configured sink or class store.
+    // directory outputs and store sessions continue where an earlier attach left off,
+    // archives and streams are written anew on every attach.
+    pub fn open(
+        config: crate::config::Config,
+        default_location: &Path,
+    ) -> Result<Self, crate::error::Error> {
+        if !config.store.enabled {
+            let sink = crate::sink::open(&config.output, default_location)?;
+            let mut recorder = Self::new(sink, config);
+            if recorder.config.output.kind == crate::config::OutputKind::Directory
+                && recorder.config.output.socket.is_none()
+            {
+                let root = recorder
+                    .config
+                    .output
+                    .path
+                    .clone()
+                    .unwrap_or_else(|| default_location.to_path_buf());
+                recorder.resume(&root)?;
+            }
+            return Ok(recorder);
+        }
+
+        let root = config
+            .store
+            .path
+            .clone()
+            .unwrap_or_else(|| default_location.with_file_name("store"));
+        let session = config
+            .store
+            .session
+            .clone()
+            .unwrap_or_else(crate::store::new_session_name);
+        let store = crate::store::ClassStore::open(&root, session)?;
+        println!(
+            "storing classes in {} as session {}",
+            root.display(),
+            store.session()
+        );
+
+        let session_dir = store.session_dir();
+        let sink = Box::new(crate::sink::DirectorySink::new(session_dir.clone()));
+        let mut recorder = DumpRecorder {
+            store: Some(store),
+            ..Self::new(sink, config)
+        };
+        recorder.resume(&session_dir)?;
+        Ok(recorder)
+    }
+
+    // keep the state in a dump directory, and load it and the reports when there is one
+    fn resume(&mut self, dir: &Path) -> Result<(), crate::error::Error> {
+        let state_path = dir.join(state::STATE_FILE_NAME);
+        if let Some(state) = state::SessionState::load(&state_path)? {
+            // the reports are rewritten at the end, so they have to cover the earlier classes too
+            self.hierarchy = crate::hierarchy::HierarchyIndex::load_or_build(dir)?;
+            if let Some(data) = read_report(dir, "origins.json")? {
+                self.origins = crate::origin::Origins::from_json(&data)?;
+            }
+            if let Some(data) = read_report(dir, "validation.json")? {
+                self.validation = validation::ValidationReport::from_json(&data)?;
+            }
+            if let Some(data) = read_report(dir, "dynamic.json")? {
+                self.dynamic = serde_json::from_slice(&data)?;
+            }
+            if let Some(data) = read_report(dir, "history.json")? {
+                self.history = history::VersionHistory::from_json(&data)?;
+            }
+            if let Some(data) = read_report(dir, "instrumentation.json")? {
+                self.instrumentation = instrumentation::InstrumentationReport::from_json(&data)?;
+            }
+            if let Some(data) = read_report(dir, "dependencies.json")? {
+                self.graph = crate::graph::DependencyGraph::from_json(&data)?;
+            }
+            if let Some(data) = read_report(dir, crate::manifest::MANIFEST_FILE_NAME)? {
+                let manifest: crate::manifest::SessionManifest = serde_json::from_slice(&data)?;
+                self.sink.restore(manifest.files);
+            }
+            println!(
+                "resuming {}: {} classes saved, {} pending, {} unresolved",
+                dir.display(),
+                state.saved.len(),
+                state.pending.len(),
+                state.unresolved.len()
+            );
+            self.state = state;
+        }
+
+        self.state_path = Some(state_path);
+        Ok(())
+    }
+
+    pub fn config(&self) -> &crate::config::Config {
+        &self.config
+    }
+
+    pub fn saved_count(&self) -> usize {
+        self.state.saved.len()
+    }
+
+    // classes an earlier attach still had to retransform. unresolved classes are tried again,
+    // they may have been loaded since.
+    pub fn take_pending(&mut self) -> BTreeSet<String> {
+        let mut pending = std::mem::take(&mut self.state.pending);
+        pending.append(&mut self.state.unresolved);
+        pending
+    }
+
+    pub fn mark_unresolved(&mut self, class_name: &str) {
+        self.state.unresolved.insert(class_name.to_string());
+    }
+
+    // a class that was hooked but could not be saved or analyzed
+    pub fn mark_failed(&mut self, class_name: &str) {
+        self.failed.insert(class_name.to_string());
+    }
+
+    // the jvm the session dumps from and the jvmti capabilities granted, for the manifest
+    pub fn set_jvm(
+        &mut self,
+        jvm: crate::manifest::JvmInfo,
+        capabilities: std::collections::BTreeMap<String, bool>,
+    ) {
+        self.manifest.jvm = Some(jvm);
+        self.manifest.capabilities = capabilities;
+    }
+
+    // persist the state with the classes still queued, so an interrupted session can resume
+    pub fn save_state(
+        &mut self,
+        pending: impl IntoIterator<Item = String>,
+    ) -> Result<(), crate::error::Error> {
+        self.state.pending = pending.into_iter().collect();
+        match &self.state_path {
+            Some(path) => self.state.save(path),
+            None => Ok(()),
+        }
+    }
+
+    // canonical form of the class when normalization is enabled, the original bytes otherwise
+    fn normalize(&self, class_name: &str, class_data: &[u8]) -> Vec<u8> {
+        if !self.config.normalize.enabled {
+            return class_data.to_vec();
+        }
+
+        crate::classfile::ClassFile::parse(class_data)
+            .and_then(|class_file| {
+                crate::classfile::normalize::normalize(
+                    &class_file,
+                    self.config.normalize.strip_debug,
+                )
+            })
+            .map(|class_file| class_file.to_bytes())
+            .unwrap_or_else(|e| {
+                println!("failed to normalize {class_name}, saving it as is: {e}");
+                class_data.to_vec()
+            })
+    }
+
+    // save a class, returns false if it was saved before with the same bytes.
+    // the store keeps classes of the same name from different loaders apart.
+    pub fn save(
+        &mut self,
+        class_name: &str,
+        class_data: &[u8],
+        source: &ClassSource,
+    ) -> Result<bool, crate::error::Error> {
+        let key = match (&self.store, &source.loader) {
+            (Some(_), Some(loader)) => format!("{loader}/{class_name}"),
+            _ => class_name.to_string(),
+        };
+        let hash = crate::store::sha256_hex(class_data);
+        if self.state.saved.get(&key) == Some(&hash) {
+            return Ok(false);
+        }
+
+        let save_path = format!("{}.class", class_name.replace('.', "/"));
+        let saved_data = self.normalize(class_name, class_data);
+        let origin = self.origins.bucket(source.code_source.as_deref());
+        match &mut self.store {
+            Some(store) => {
+                store.put(class_name, &saved_data, source)?;
+                self.sink.record(Some(&origin), &save_path, &saved_data);
+            }
+            None => {
+                self.sink.write_class(&origin, &save_path, &saved_data)?;
+                if source.loader.is_some() {
+                    self.sink.write_class(
+                        &origin,
+                        &format!("{save_path}.json"),
+                        &metadata_sidecar(class_name, source)?,
+                    )?;
+                }
+            }
+        }
+        println!("saved class: {class_name}");
+
+        // check the dump is structurally sound, broken bytes are reported but still saved
+        let problems = self.validation.check(class_name, &saved_data);
+        if !problems.is_empty() {
+            println!(
+                "suspicious class: {class_name} ({} problems)",
+                problems.len()
+            );
+            validation::mark_suspicious(&mut self.sink, &origin, &save_path, &problems)?;
+        }
+
+        if self.config.history.enabled {
+            let versions = self.history.record(&key, class_name, &saved_data, source);
+            if let Some((sequence, _)) = versions.last() {
+                println!("new version of class: {class_name} (v{sequence})");
+            }
+            for (sequence, data) in versions {
+                self.sink
+                    .write_class(&origin, &format!("{save_path}.v{sequence}"), &data)?;
+            }
+        }
+
+        if let Some(dynamic) = &source.dynamic {
+            self.dynamic.insert(class_name.to_string(), dynamic.clone());
+        }
+        self.state.saved.insert(key, hash);
+        self.graph.add_node(class_name);
+        if let Err(e) = self.hierarchy.add(&saved_data) {
+            println!("failed to index {class_name}: {e}");
+        }
+
+        Ok(true)
+    }
+
+    // compare a class just saved to its bytes as loaded, they are written next to it as
+    // .class.original when other agents changed it. only done when capturing them is enabled.
+    pub fn record_original(
+        &mut self,
+        class_name: &str,
+        original: Option<&[u8]>,
+        class_data: &[u8],
+        source: &ClassSource,
+    ) -> Result<(), crate::error::Error> {
+        if !self.config.instrumentation.enabled {
+            return Ok(());
+        }
+        let Some(original) = original else {
+            self.instrumentation.compare(class_name, None, class_data);
+            return Ok(());
+        };
+        if !self
+            .instrumentation
+            .compare(class_name, Some(original), class_data)
+        {
+            return Ok(());
+        }
+
+        println!("instrumented class: {class_name}");
+        let origin = self
+            .origins
+            .name(source.code_source.as_deref())
+            .map(str::to_string)
+            .unwrap_or_else(|| self.origins.bucket(source.code_source.as_deref()));
+        let save_path = format!("{}.class.original", class_name.replace('.', "/"));
+        let original = self.normalize(class_name, original);
+        self.sink.write_class(&origin, &save_path, &original)
+    }
+
+    // set the edges of a class in the dependency graph, returns the classes to follow. edges of
+    // an earlier version are replaced. annotations are only loaded when read reflectively,
+    // so they are not followed.
+    pub fn record_dependencies(
+        &mut self,
+        class_name: &str,
+        edges: impl IntoIterator<Item = (EdgeKind, String)>,
+    ) -> Vec<String> {
+        self.graph.remove_edges_from(class_name);
+        let mut dependencies = BTreeSet::new();
+        for (kind, dependency) in edges {
+            self.graph.add_edge(class_name, &dependency, kind);
+            if kind != EdgeKind::Annotation {
+                dependencies.insert(dependency);
+            }
+        }
+
+        dependencies.into_iter().collect()
+    }
+
+    // extra report at the top of the dump
+    pub fn write_report(&mut self, name: &str, data: &[u8]) -> Result<(), crate::error::Error> {
+        self.sink.write(name, data)
+    }
+
+    // write the collected reports and complete the output, nothing is saved afterwards
+    pub fn finish(&mut self) -> Result<(), crate::error::Error> {
+        self.sink
+            .write("validation.json", &self.validation.to_json()?)?;
+        self.sink.write("origins.json", &self.origins.to_json()?)?;
+        if !self.dynamic.is_empty() {
+            self.sink
+                .write("dynamic.json", &serde_json::to_vec_pretty(&self.dynamic)?)?;
+        }
+        if self.config.history.enabled {
+            self.sink.write("history.json", &self.history.to_json()?)?;
+        }
+        if !self.instrumentation.is_empty() {
+            self.sink
+                .write("instrumentation.json", &self.instrumentation.to_json()?)?;
+        }
+        self.sink.write(
+            crate::hierarchy::INDEX_FILE_NAME,
+            &self.hierarchy.to_json()?,
+        )?;
+        if !self.graph.is_empty() {
+            self.graph.write_all(&mut self.sink, "dependencies")?;
+            self.graph
+                .packages()
+                .write_all(&mut self.sink, "packages")?;
+        }
+
+        if let Some(path) = &self.state_path {
+            self.state.save(path)?;
+        }
+
+        // written last so it lists every other file
+        self.manifest.finished_at = crate::manifest::unix_millis();
+        self.manifest.dumped = self.saved_count();
+        self.manifest.failed = self.failed.len();
+        self.manifest.unresolved = self.state.unresolved.len();
+        self.manifest.files = self.sink.files().clone();
+        self.sink.write(
+            crate::manifest::MANIFEST_FILE_NAME,
+            &serde_json::to_vec_pretty(&self.manifest)?,
+        )?;
+
+        self.sink.finish()
+    }
+}
+
+// a report of an earlier attach, None if it wasn't written
+fn read_report(dir: &Path, name: &str) -> Result<Option<Vec<u8>>, crate::error::Error> {
+    match std::fs::read(dir.join(name)) {
+        Ok(data) => Ok(Some(data)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sink::MemorySink;
+
+    #[test]
+    fn test_saved_into_sink() {
+        let sink = MemorySink::new();
+        let mut recorder =
+            DumpRecorder::new(Box::new(sink.clone()), crate::config::Config::default());
+        let source = ClassSource {
+            loader: Some("bootstrap".to_string()),
+            ..Default::default()
+        };
+        assert!(recorder.save("a.B", &[0xCA, 0xFE], &source).unwrap());
+        assert!(!recorder.save("a.B", &[0xCA, 0xFE], &source).unwrap());
+        recorder.record_dependencies("a.B", [(EdgeKind::Calls, "a.C".to_string())]);
+        recorder.finish().unwrap();
+
+        let files = sink.files();
+        assert_eq!(files["a/B.class"], [0xCA, 0xFE]);
+        // truncated bytes are still saved, but flagged
+        assert!(files.contains_key("a/B.class.invalid"));
+        let metadata: serde_json::Value = serde_json::from_slice(&files["a/B.class.json"]).unwrap();
+        assert_eq!(metadata["class"], "a.B");
+        assert_eq!(metadata["loader"], "bootstrap");
+        for report in [
+            "validation.json",
+            "origins.json",
+            "hierarchy.json",
+            "dependencies.dot",
+            "packages.json",
+            "session.json",
+        ] {
+            assert!(files.contains_key(report), "{report} missing");
+        }
+    }
+
+    #[test]
+    fn test_hooked_names_connect() {
+        let sink = MemorySink::new();
+        let mut recorder =
+            DumpRecorder::new(Box::new(sink.clone()), crate::config::Config::default());
+        // hooked classes are named in internal form, their dependencies in binary form
+        recorder
+            .save("a/B", &[0xCA, 0xFE], &ClassSource::default())
+            .unwrap();
+        recorder.record_dependencies(
+            "a/B",
+            [
+                (EdgeKind::Calls, "a.B".to_string()),
+                (EdgeKind::Calls, "b.C".to_string()),
+            ],
+        );
+        recorder.finish().unwrap();
+
+        let files = sink.files();
+        let graph: serde_json::Value = serde_json::from_slice(&files["dependencies.json"]).unwrap();
+        assert_eq!(graph["nodes"], serde_json::json!(["a.B", "b.C"]));
+        assert_eq!(graph["edges"].as_array().unwrap().len(), 1);
+        let packages: serde_json::Value = serde_json::from_slice(&files["packages.json"]).unwrap();
+        assert_eq!(packages["nodes"], serde_json::json!(["a", "b"]));
+        assert_eq!(packages["edges"][0]["from"], "a");
+    }
+
+    #[test]
+    fn test_resume() {
+        let dir = std::env::temp_dir().join(format!("b_agent_resume_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let source = ClassSource::default();
+
+        let open = || {
+            let sink = crate::sink::DirectorySink::new(dir.clone());
+            let mut recorder = DumpRecorder::new(Box::new(sink), crate::config::Config::default());
+            recorder.resume(&dir).unwrap();
+            recorder
+        };
+        let mut recorder = open();
+        recorder.save("a.B", &[0xCA, 0xFE], &source).unwrap();
+        recorder.mark_unresolved("a.D");
+        recorder.save_state(["a.C".to_string()]).unwrap();
+        recorder.finish().unwrap();
+
+        let mut recorder = open();
+        assert_eq!(recorder.saved_count(), 1);
+        assert_eq!(
+            recorder.take_pending(),
+            BTreeSet::from(["a.C".into(), "a.D".into()])
+        );
+        // only changed bytes are saved again
+        assert!(!recorder.save("a.B", &[0xCA, 0xFE], &source).unwrap());
+        assert!(recorder.save("a.B", &[0xCA, 0xFE, 0xBA], &source).unwrap());
+        assert!(std::fs::read(dir.join("origins.json")).is_ok());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_original_bytes() {
+        let sink = MemorySink::new();
+        let mut config = crate::config::Config::default();
+        config.instrumentation.enabled = true;
+        let mut recorder = DumpRecorder::new(Box::new(sink.clone()), config);
+        let source = ClassSource::default();
+
+        // another agent added a field to a/B
+        let original = class_with_field(None);
+        let instrumented = class_with_field(Some("agent$timer"));
+        recorder.save("a.B", &instrumented, &source).unwrap();
+        recorder
+            .record_original("a.B", Some(&original), &instrumented, &source)
+            .unwrap();
+        recorder
+            .record_original("a.C", Some(&original), &original, &source)
+            .unwrap();
+        recorder
+            .record_original("a.D", None, &original, &source)
+            .unwrap();
+        recorder.finish().unwrap();
+
+        let files = sink.files();
+        assert_eq!(files["a/B.class.original"], original);
+        assert!(!files.contains_key("a/C.class.original"));
+        let report: serde_json::Value =
+            serde_json::from_slice(&files["instrumentation.json"]).unwrap();
+        assert_eq!(report["compared"], 2);
+        assert_eq!(report["unchanged"], 1);
+        assert_eq!(report["not_captured"], 1);
+        assert_eq!(report["changed"][0]["fields"]["added"][0], "agent$timer:I");
+    }
+
+    #[test]
+    fn test_version_history() {
+        let sink = MemorySink::new();
+        let mut config = crate::config::Config::default();
+        config.history.enabled = true;
+        let mut recorder = DumpRecorder::new(Box::new(sink.clone()), config);
+
+        let first = class_with_field(None);
+        let second = class_with_field(Some("reloaded"));
+        recorder
+            .save("a.B", &first, &ClassSource::default())
+            .unwrap();
+        recorder
+            .save("a.C", &first, &ClassSource::default())
+            .unwrap();
+        let redefiner = ClassSource {
+            redefined: true,
+            stack: vec!["org.reload.Agent.reload(Agent.java:10)".to_string()],
+            ..Default::default()
+        };
+        assert!(recorder.save("a.B", &second, &redefiner).unwrap());
+        recorder.finish().unwrap();
+
+        let files = sink.files();
+        assert_eq!(files["a/B.class"], second);
+        assert_eq!(files["a/B.class.v1"], first);
+        assert_eq!(files["a/B.class.v2"], second);
+        // classes saved once have no history
+        assert!(!files.contains_key("a/C.class.v1"));
+        let history: serde_json::Value = serde_json::from_slice(&files["history.json"]).unwrap();
+        let versions = history["a.B"].as_array().unwrap();
+        assert_eq!(versions.len(), 2);
+        assert_eq!(versions[1]["sequence"], 2);
+        assert_eq!(versions[1]["redefined"], true);
+        assert_eq!(
+            versions[1]["stack"][0],
+            "org.reload.Agent.reload(Agent.java:10)"
+        );
+        assert_eq!(versions[1]["changes"]["fields"]["added"][0], "reloaded:I");
+        assert!(history.get("a.C").is_none());
+    }
+
+    // a/B with an optional int field
+    fn class_with_field(field: Option<&str>) -> Vec<u8> {
+        let mut constant_pool = crate::classfile::ConstantPool::new();
+        let this_class = constant_pool.add_class("a/B").unwrap();
+        let super_class = constant_pool.add_class("java/lang/Object").unwrap();
+        let fields = field
+            .map(|name| crate::classfile::Member {
+                access_flags: 0x0002,
+                name_index: constant_pool.add_utf8(name).unwrap(),
+                descriptor_index: constant_pool.add_utf8("I").unwrap(),
+                attributes: Vec::new(),
+            })
+            .into_iter()
+            .collect();
+        crate::classfile::ClassFile {
+            minor_version: 0,
+            major_version: 52,
+            constant_pool,
+            access_flags: 0x0021,
+            this_class,
+            super_class,
+            interfaces: Vec::new(),
+            fields,
+            methods: Vec::new(),
+            attributes: Vec::new(),
+        }
+        .to_bytes()
+    }
+}
@@ -0,0 +1,40 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+};
+
+pub const STATE_FILE_NAME: &str = "state.json";
+
+// what a session got done, kept in the output so a later attach can pick up from there
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct SessionState {
+    // saved classes and the sha-256 of the bytes the jvm handed over for them
+    pub saved: BTreeMap<String, String>,
+    // dependencies queued for retransformation but not hooked yet
+    pub pending: BTreeSet<String>,
+    // dependencies that could not be found in the jvm
+    pub unresolved: BTreeSet<String>,
+}
+
+impl SessionState {
+    // None when the output has no state yet
+    pub fn load(path: &Path) -> Result<Option<Self>, crate::error::Error> {
+        match std::fs::read(path) {
+            Ok(data) => Ok(Some(serde_json::from_slice(&data)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), crate::error::Error> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        // replaced in one step so a crash never leaves half a state behind
+        let partial = path.with_extension("partial");
+        std::fs::write(&partial, serde_json::to_vec_pretty(self)?)?;
+        std::fs::rename(&partial, path)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,85 @@
+use std::collections::BTreeMap;
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Entry {
+    class: String,
+    problems: Vec<String>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Report {
+    checked: usize,
+    suspicious: usize,
+    classes: Vec<Entry>,
+}
+
+// collects structural problems of saved classes and writes them to validation.json
+pub struct ValidationReport {
+    checked: usize,
+    failures: BTreeMap<String, Vec<String>>,
+}
+
+impl ValidationReport {
+    pub fn new() -> Self {
+        ValidationReport {
+            checked: 0,
+            failures: BTreeMap::new(),
+        }
+    }
+
+    // validate a saved class, returns the problems found
+    pub fn check(&mut self, class_name: &str, class_data: &[u8]) -> Vec<String> {
+        self.checked += 1;
+        let problems = crate::classfile::validate::validate(class_data);
+        if !problems.is_empty() {
+            self.failures
+                .insert(class_name.to_string(), problems.clone());
+        }
+
+        problems
+    }
+
+    // read back a report written by to_json
+    pub fn from_json(data: &[u8]) -> Result<Self, crate::error::Error> {
+        let report: Report = serde_json::from_slice(data)?;
+        Ok(ValidationReport {
+            checked: report.checked,
+            failures: report
+                .classes
+                .into_iter()
+                .map(|entry| (entry.class, entry.problems))
+                .collect(),
+        })
+    }
+
+    pub fn to_json(&self) -> Result<Vec<u8>, crate::error::Error> {
+        let report = Report {
+            checked: self.checked,
+            suspicious: self.failures.len(),
+            classes: self
+                .failures
+                .iter()
+                .map(|(class, problems)| Entry {
+                    class: class.clone(),
+                    problems: problems.clone(),
+                })
+                .collect(),
+        };
+
+        Ok(serde_json::to_vec_pretty(&report)?)
+    }
+}
+
+// suspicious classes get a marker file next to them listing what is wrong
+pub fn mark_suspicious(
+    sink: &mut dyn crate::sink::OutputSink,
+    origin: &str,
+    class_path: &str,
+    problems: &[String],
+) -> Result<(), crate::error::Error> {
+    sink.write_class(
+        origin,
+        &format!("{class_path}.invalid"),
+        problems.join("\n").as_bytes(),
+    )
+}
@@ -0,0 +1,354 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use crate::sink::OutputSink as _;
+
+const SERVICES_PREFIX: &str = "META-INF/services/";
+
+// jvm flags loading agents, the dumping agent among them
+const AGENT_FLAGS: [&str; 4] = ["-agentpath:", "-agentlib:", "-javaagent:", "-Xrun"];
+
+#[derive(Debug, Default, serde::Serialize)]
+pub struct RunnableSummary {
+    pub main_class: String,
+    pub classes: usize,
+    pub resources: usize,
+    pub services: usize,
+    // service providers that were never loaded, left out so ServiceLoader doesn't fail on them
+    pub dropped_providers: Vec<String>,
+    // class path entries of the jvm that aren't readable here, their resources are missing
+    pub missing_entries: Vec<String>,
+    // agent flags of the jvm left out of the launch scripts
+    pub dropped_arguments: Vec<String>,
+    pub scripts: Vec<PathBuf>,
+}
+
+// build a runnable jar from the classes of a dump and the resources of the class path
+// recorded in its session.json, next to launch scripts with the original jvm flags.
+// `main_class` overrides the one taken from the java command. agents the jvm was started with
+// are left out unless `keep_agents` is set, the launched application would dump itself again.
+pub fn export(
+    dump: &Path,
+    out: &Path,
+    main_class: Option<String>,
+    keep_agents: bool,
+) -> Result<RunnableSummary, crate::error::Error> {
+    let mut reader = crate::manifest::OutputReader::open(dump)?;
+    let manifest = reader.manifest()?;
+    let jvm = manifest.jvm.unwrap_or_default();
+    let property = |name: &str| jvm.properties.get(name).map(String::as_str);
+    let working_dir = PathBuf::from(property("user.dir").unwrap_or("."));
+
+    // the first word of the command is the main class, or the jar started with -jar
+    let (target, arguments) = jvm
+        .command
+        .as_deref()
+        .map(|command| command.split_once(' ').unwrap_or((command, "")))
+        .unwrap_or_default();
+    let main_class = match main_class {
+        Some(main_class) => main_class,
+        None if target.to_ascii_lowercase().ends_with(".jar") => {
+            jar_main_class(&working_dir.join(target))?.ok_or(
+                crate::error::Error::XValueNotOfType("main class of the jar, pass --main-class"),
+            )?
+        }
+        None if !target.is_empty() => target.to_string(),
+        None => {
+            return Err(crate::error::Error::XValueNotOfType(
+                "java command in session.json, pass --main-class",
+            ));
+        }
+    };
+
+    let mut summary = RunnableSummary {
+        main_class: main_class.clone(),
+        ..Default::default()
+    };
+    let mut classes = BTreeMap::new();
+    for (path, entry) in &manifest.files {
+        if entry.origin.is_none() || !path.ends_with(".class") {
+            continue;
+        }
+        if let Some(data) = reader.read(entry.origin.as_deref(), path)? {
+            classes.insert(path.clone(), data);
+        }
+    }
+    let main_class_path = format!("{}.class", crate::classfile::internal_name(&main_class));
+    if !classes.contains_key(&main_class_path) {
+        println!("main class {main_class} is not in the dump");
+    }
+
+    // earlier class path entries win, like for the jvm's class lookup
+    let separator = match property("os.name") {
+        Some(os) if os.starts_with("Windows") => ';',
+        _ => ':',
+    };
+    let mut resources = BTreeMap::new();
+    let mut services = BTreeMap::<String, Vec<String>>::new();
+    for entry in jvm
+        .class_path
+        .as_deref()
+        .unwrap_or_default()
+        .split(separator)
+        .filter(|entry| !entry.is_empty())
+    {
+        let Some(files) = read_entry(&working_dir.join(entry))? else {
+            summary.missing_entries.push(entry.to_string());
+            continue;
+        };
+        for (path, data) in files {
+            if let Some(service) = path.strip_prefix(SERVICES_PREFIX) {
+                let providers = services.entry(service.to_string()).or_default();
+                for provider in String::from_utf8_lossy(&data).lines() {
+                    // everything after a '#' is a comment
+                    let provider = provider.split('#').next().unwrap_or_default().trim();
+                    if !provider.is_empty() && !providers.iter().any(|known| known == provider) {
+                        providers.push(provider.to_string());
+                    }
+                }
+            } else if is_resource(&path) {
+                resources.entry(path).or_insert(data);
+            }
+        }
+    }
+
+    let jar_manifest =
+        format!("Manifest-Version: 1.0\r\nCreated-By: b_agent\r\nMain-Class: {main_class}\r\n\r\n");
+    let mut jar = crate::sink::JarSink::with_manifest(out, &jar_manifest)?;
+    for (path, data) in &classes {
+        jar.write(path, data)?;
+    }
+    summary.classes = classes.len();
+    for (path, data) in &resources {
+        jar.write(path, data)?;
+    }
+    summary.resources = resources.len();
+    for (service, providers) in services {
+        let mut kept = String::new();
+        for provider in providers {
+            let path = format!("{}.class", crate::classfile::internal_name(&provider));
+            if classes.contains_key(&path) {
+                kept.push_str(&provider);
+                kept.push('\n');
+            } else {
+                summary.dropped_providers.push(provider);
+            }
+        }
+        if !kept.is_empty() {
+            jar.write(&format!("{SERVICES_PREFIX}{service}"), kept.as_bytes())?;
+            summary.services += 1;
+        }
+    }
+    jar.finish()?;
+
+    let (input_arguments, dropped_arguments): (Vec<_>, Vec<_>) =
+        jvm.input_arguments.iter().partition(|argument| {
+            keep_agents || !AGENT_FLAGS.iter().any(|flag| argument.starts_with(flag))
+        });
+    summary.dropped_arguments = dropped_arguments.into_iter().cloned().collect();
+
+    let jar_name = out
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let java = |quote: fn(&str) -> String| {
+        let mut words = vec!["java".to_string()];
+        words.extend(input_arguments.iter().map(|argument| quote(argument)));
+        words.extend(["-cp".to_string(), quote(&jar_name), quote(&main_class)]);
+        words.extend(arguments.split_whitespace().map(quote));
+        words.join(" ")
+    };
+    let header = format!(
+        "generated by b_agent from a dump of java {} ({})",
+        jvm.version.as_deref().unwrap_or("unknown"),
+        jvm.vendor.as_deref().unwrap_or("unknown vendor"),
+    );
+
+    let shell_script = out.with_extension("sh");
+    std::fs::write(
+        &shell_script,
+        format!(
+            "#!/bin/sh\n# {header}\ncd \"$(dirname \"$0\")\" || exit 1\nexec {} \"$@\"\n",
+            java(shell_quote)
+        ),
+    )?;
+    let batch_script = out.with_extension("cmd");
+    std::fs::write(
+        &batch_script,
+        format!(
+            "@echo off\r\nrem {header}\r\ncd /d \"%~dp0\"\r\n{} %*\r\n",
+            java(batch_quote)
+        ),
+    )?;
+    summary.scripts = vec![shell_script, batch_script];
+
+    Ok(summary)
+}
+
+// files of a jar or directory on the class path, None when it doesn't exist here
+fn read_entry(path: &Path) -> Result<Option<BTreeMap<String, Vec<u8>>>, crate::error::Error> {
+    if path.is_file() {
+        return crate::manifest::read_archive(path).map(Some);
+    }
+    if !path.is_dir() {
+        return Ok(None);
+    }
+
+    let mut files = BTreeMap::new();
+    let mut directories = vec![path.to_path_buf()];
+    while let Some(directory) = directories.pop() {
+        for entry in std::fs::read_dir(&directory)? {
+            let entry_path = entry?.path();
+            if entry_path.is_dir() {
+                directories.push(entry_path);
+            } else if let Ok(relative) = entry_path.strip_prefix(path) {
+                let name = relative
+                    .components()
+                    .map(|component| component.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/");
+                files.insert(name, std::fs::read(&entry_path)?);
+            }
+        }
+    }
+    Ok(Some(files))
+}
+
+// files copied into the runnable jar. classes come from the dump only, and signatures
+// of the original jars don't match the merged one.
+fn is_resource(path: &str) -> bool {
+    let upper = path.to_ascii_uppercase();
+    let signature = upper.starts_with("META-INF/")
+        && [".SF", ".RSA", ".DSA", ".EC"]
+            .iter()
+            .any(|extension| upper.ends_with(extension));
+    !path.ends_with('/')
+        && !path.ends_with(".class")
+        && !signature
+        && !["META-INF/MANIFEST.MF", "META-INF/INDEX.LIST"].contains(&upper.as_str())
+}
+
+// Main-Class of a jar, or Start-Class for spring boot jars since their launcher
+// expects the nested layout the dump doesn't have
+fn jar_main_class(path: &Path) -> Result<Option<String>, crate::error::Error> {
+    if !path.is_file() {
+        return Ok(None);
+    }
+    let files = crate::manifest::read_archive(path)?;
+    let Some(manifest) = files.get("META-INF/MANIFEST.MF") else {
+        return Ok(None);
+    };
+
+    // long values continue on lines starting with a space
+    let mut attributes = BTreeMap::<String, String>::new();
+    let mut last = None;
+    for line in String::from_utf8_lossy(manifest).lines() {
+        if let (Some(continued), Some(name)) = (line.strip_prefix(' '), &last) {
+            if let Some(value) = attributes.get_mut(name) {
+                value.push_str(continued);
+            }
+        } else if let Some((name, value)) = line.split_once(':') {
+            attributes.insert(name.to_string(), value.trim_start().to_string());
+            last = Some(name.to_string());
+        }
+    }
+    Ok(attributes
+        .remove("Start-Class")
+        .or_else(|| attributes.remove("Main-Class")))
+}
+
+fn shell_quote(word: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "-_./:=,+@".contains(c);
+    if !word.is_empty() && word.chars().all(plain) {
+        return word.to_string();
+    }
+    format!("'{}'", word.replace('\'', "'\\''"))
+}
+
+fn batch_quote(word: &str) -> String {
+    let word = word.replace('%', "%%");
+    if !word.is_empty() && !word.contains([' ', '&', '|', '<', '>', '^', '(', ')', ';', ',', '"']) {
+        return word;
+    }
+    // the java launcher reads a doubled quote inside a quoted word as a literal one
+    format!("\"{}\"", word.replace('"', "\"\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sink::OutputSink;
+
+    #[test]
+    fn test_export_runnable() {
+        let root = std::env::temp_dir().join(format!("b_agent_runnable_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+
+        // the original application jar, its classes are ignored in favor of the dump
+        let mut app = crate::sink::JarSink::with_manifest(
+            &root.join("app.jar"),
+            "Manifest-Version: 1.0\r\nMain-Class: a.Ma\r\n in\r\n\r\n",
+        )
+        .unwrap();
+        app.write("a/Main.class", &[0]).unwrap();
+        app.write("config.properties", b"x=1").unwrap();
+        app.write("META-INF/APP.SF", b"").unwrap();
+        app.write(
+            "META-INF/services/a.Service",
+            b"# providers\na.Main\nb.Gone\n",
+        )
+        .unwrap();
+        app.finish().unwrap();
+
+        let dump = root.join("dump");
+        let mut sink = crate::manifest::HashingSink::new(Box::new(
+            crate::sink::DirectorySink::new(dump.clone()),
+        ));
+        sink.write_class("app", "a/Main.class", &[0xCA, 0xFE])
+            .unwrap();
+        let mut manifest = crate::manifest::SessionManifest::new(Default::default());
+        manifest.jvm = Some(crate::manifest::JvmInfo {
+            class_path: Some("app.jar:missing".to_string()),
+            command: Some("app.jar --port 80".to_string()),
+            input_arguments: vec![
+                "-Xmx1g".to_string(),
+                "-agentpath:b_agent.dll".to_string(),
+                "-Dname=a b".to_string(),
+            ],
+            properties: [("user.dir".to_string(), root.display().to_string())].into(),
+            ..Default::default()
+        });
+        manifest.files = sink.files().clone();
+        sink.write(
+            crate::manifest::MANIFEST_FILE_NAME,
+            &serde_json::to_vec(&manifest).unwrap(),
+        )
+        .unwrap();
+
+        let out = root.join("run.jar");
+        let summary = export(&dump, &out, None, false).unwrap();
+        assert_eq!(summary.main_class, "a.Main");
+        assert_eq!(summary.dropped_arguments, ["-agentpath:b_agent.dll"]);
+        assert_eq!(summary.dropped_providers, ["b.Gone"]);
+        assert_eq!(summary.missing_entries, ["missing"]);
+
+        let files = crate::manifest::read_archive(&out).unwrap();
+        assert_eq!(files["a/Main.class"], [0xCA, 0xFE]);
+        assert_eq!(files["config.properties"], b"x=1");
+        assert_eq!(files["META-INF/services/a.Service"], b"a.Main\n");
+        assert!(!files.contains_key("META-INF/APP.SF"));
+        assert!(
+            String::from_utf8_lossy(&files["META-INF/MANIFEST.MF"]).contains("Main-Class: a.Main")
+        );
+
+        let script = std::fs::read_to_string(root.join("run.sh")).unwrap();
+        assert!(
+            script.contains("exec java -Xmx1g '-Dname=a b' -cp run.jar a.Main --port 80 \"$@\"")
+        );
+        assert_eq!(batch_quote("-Dname=\"a\""), "\"-Dname=\"\"a\"\"\"");
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,24 @@
+use std::path::PathBuf;
+
+// loose files below a directory, the layout the agent always produced
+pub struct DirectorySink {
+    root: PathBuf,
+}
+
+impl DirectorySink {
+    pub fn new(root: PathBuf) -> Self {
+        DirectorySink { root }
+    }
+}
+
+impl super::OutputSink for DirectorySink {
+    fn write(&mut self, path: &str, data: &[u8]) -> Result<(), crate::error::Error> {
+        let mut file_path = self.root.clone();
+        file_path.extend(path.split('/'));
+        if let Some(parent) = file_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(file_path, data)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,74 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+// one jar per origin below a root directory, reports go to the root
+pub struct GroupedSink {
+    root: PathBuf,
+    reports: super::DirectorySink,
+    groups: BTreeMap<String, super::JarSink>,
+}
+
+impl GroupedSink {
+    pub fn new(root: PathBuf) -> Self {
+        GroupedSink {
+            reports: super::DirectorySink::new(root.clone()),
+            root,
+            groups: BTreeMap::new(),
+        }
+    }
+}
+
+impl super::OutputSink for GroupedSink {
+    fn write(&mut self, path: &str, data: &[u8]) -> Result<(), crate::error::Error> {
+        self.reports.write(path, data)
+    }
+
+    fn write_class(
+        &mut self,
+        origin: &str,
+        path: &str,
+        data: &[u8],
+    ) -> Result<(), crate::error::Error> {
+        if !self.groups.contains_key(origin) {
+            let sink = super::JarSink::create(&self.root.join(format!("{origin}.jar")))?;
+            self.groups.insert(origin.to_string(), sink);
+        }
+
+        self.groups.get_mut(origin).unwrap().write(path, data)
+    }
+
+    fn finish(&mut self) -> Result<(), crate::error::Error> {
+        for sink in self.groups.values_mut() {
+            sink.finish()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sink::OutputSink;
+
+    #[test]
+    fn test_jar_per_origin() {
+        let root = std::env::temp_dir().join(format!("b_agent_grouped_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+
+        let mut sink = GroupedSink::new(root.clone());
+        sink.write_class("app", "a/B.class", &[0xCA, 0xFE]).unwrap();
+        sink.write_class("lib-1.0", "b/C.class", &[0xCA]).unwrap();
+        sink.write_class("app", "a/D.class", &[0xFE]).unwrap();
+        sink.write("origins.json", b"{}").unwrap();
+        sink.finish().unwrap();
+
+        let app = crate::manifest::read_archive(&root.join("app.jar")).unwrap();
+        assert_eq!(app["a/B.class"], [0xCA, 0xFE]);
+        assert_eq!(app["a/D.class"], [0xFE]);
+        let lib = crate::manifest::read_archive(&root.join("lib-1.0.jar")).unwrap();
+        assert_eq!(lib["b/C.class"], [0xCA]);
+        assert!(root.join("origins.json").is_file());
+        assert!(!root.join("app").exists());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,54 @@
+use std::{fs::File, io::Write as _, path::Path};
+
+use zip::{ZipWriter, write::SimpleFileOptions};
+
+const MANIFEST: &str = "Manifest-Version: 1.0\r\nCreated-By: b_agent\r\n\r\n";
+
+// a single jar holding every dumped file, starting with a generated manifest
+pub struct JarSink {
+    writer: Option<ZipWriter<File>>,
+}
+
+impl JarSink {
+    pub fn create(path: &Path) -> Result<Self, crate::error::Error> {
+        Self::with_manifest(path, MANIFEST)
+    }
+
+    // a jar starting with the given manifest, e.g. one naming a main class
+    pub fn with_manifest(path: &Path, manifest: &str) -> Result<Self, crate::error::Error> {
+        let mut writer = ZipWriter::new(super::create_file(path)?);
+        writer.add_directory("META-INF/", SimpleFileOptions::default())?;
+        writer.start_file("META-INF/MANIFEST.MF", SimpleFileOptions::default())?;
+        writer.write_all(manifest.as_bytes())?;
+
+        Ok(JarSink {
+            writer: Some(writer),
+        })
+    }
+}
+
+impl super::OutputSink for JarSink {
+    fn write(&mut self, path: &str, data: &[u8]) -> Result<(), crate::error::Error> {
+        let Some(writer) = self.writer.as_mut() else {
+            return Err(crate::error::Error::XValueNotOfType("jar already finished"));
+        };
+
+        writer.start_file(path, SimpleFileOptions::default())?;
+        writer.write_all(data)?;
+        Ok(())
+    }
+
+    // the central directory is only written here, an unfinished jar is unreadable
+    fn finish(&mut self) -> Result<(), crate::error::Error> {
+        if let Some(writer) = self.writer.take() {
+            writer.finish()?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for JarSink {
+    fn drop(&mut self) {
+        let _ = super::OutputSink::finish(self);
+    }
+}
@@ -0,0 +1,30 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+
+// keeps written files in memory, clones share the same files so tests can look at them
+#[derive(Clone, Default)]
+pub struct MemorySink {
+    files: Arc<Mutex<BTreeMap<String, Vec<u8>>>>,
+}
+
+impl MemorySink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn files(&self) -> BTreeMap<String, Vec<u8>> {
+        self.files.lock().unwrap().clone()
+    }
+}
+
+impl super::OutputSink for MemorySink {
+    fn write(&mut self, path: &str, data: &[u8]) -> Result<(), crate::error::Error> {
+        self.files
+            .lock()
+            .unwrap()
+            .insert(path.to_string(), data.to_vec());
+        Ok(())
+    }
+}
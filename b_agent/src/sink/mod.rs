@@ -0,0 +1,83 @@
+use std::path::{Path, PathBuf};
+
+use crate::config::OutputKind;
+
+mod directory;
+mod grouped;
+mod jar;
+mod memory;
+pub mod socket;
+pub mod tar;
+
+pub use directory::DirectorySink;
+pub use grouped::GroupedSink;
+pub use jar::JarSink;
+pub use memory::MemorySink;
+pub use socket::SocketSink;
+pub use tar::TarSink;
+
+// destination of dumped classes and reports. paths are relative to the dump root
+// and always use '/', sinks translate them to whatever their format needs.
+pub trait OutputSink: Send {
+    fn write(&mut self, path: &str, data: &[u8]) -> Result<(), crate::error::Error>;
+
+    // a class or a file belonging to it, `origin` is the bucket of the jar or directory
+    // it was loaded from. sinks that don't group by origin store it like any other file.
+    fn write_class(
+        &mut self,
+        _origin: &str,
+        path: &str,
+        data: &[u8],
+    ) -> Result<(), crate::error::Error> {
+        self.write(path, data)
+    }
+
+    // complete the artifact, nothing is written afterwards
+    fn finish(&mut self) -> Result<(), crate::error::Error> {
+        Ok(())
+    }
+}
+
+// sink selected in the config, written to `default_location` unless the config names a path
+pub fn open(
+    config: &crate::config::OutputConfig,
+    default_location: &Path,
+) -> Result<Box<dyn OutputSink>, crate::error::Error> {
+    // the collector picks the layout, nothing is written locally
+    if let Some(address) = &config.socket {
+        println!("streaming to collector at {address}");
+        return Ok(Box::new(socket::connect(address)?));
+    }
+
+    if config.group_by_code_source {
+        // one jar per origin inside the dump directory, whatever the kind
+        let root = config
+            .path
+            .clone()
+            .unwrap_or_else(|| default_location.to_path_buf());
+        return Ok(Box::new(GroupedSink::new(root)));
+    }
+
+    let path = match (&config.path, config.kind) {
+        (Some(path), _) => path.clone(),
+        (None, OutputKind::Directory) => default_location.to_path_buf(),
+        (None, OutputKind::Jar) => default_location.with_extension("jar"),
+        (None, OutputKind::Tar) => default_location.with_extension("tar"),
+    };
+    open_kind(config.kind, path)
+}
+
+fn open_kind(kind: OutputKind, path: PathBuf) -> Result<Box<dyn OutputSink>, crate::error::Error> {
+    Ok(match kind {
+        OutputKind::Directory => Box::new(DirectorySink::new(path)),
+        OutputKind::Jar => Box::new(JarSink::create(&path)?),
+        OutputKind::Tar => Box::new(TarSink::new(std::io::BufWriter::new(create_file(&path)?))),
+    })
+}
+
+fn create_file(path: &Path) -> Result<std::fs::File, crate::error::Error> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    Ok(std::fs::File::create(path)?)
+}
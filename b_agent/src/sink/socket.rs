@@ -0,0 +1,279 @@
+use std::io::{Read, Write};
+
+// stream of a dump sent to a collector over tcp or a unix domain socket.
+// integers are big endian. the stream starts with the 4 byte magic "BDMP" and a u8 version,
+// followed by frames of
+//   u8   type: 1 file at the dump root, 2 class or file next to a class, 3 end of the dump
+//   u32  origin length, then the origin bucket as utf-8, empty unless the type is 2
+//   u32  path length, then the path relative to the dump root as utf-8, '/' separated
+//   u32  data length, then the file contents
+// the end frame has empty fields. a stream closing without one was cut off,
+// everything received up to the last complete frame is still valid.
+pub const MAGIC: &[u8; 4] = b"BDMP";
+pub const VERSION: u8 = 1;
+
+const FILE: u8 = 1;
+const CLASS: u8 = 2;
+const END: u8 = 3;
+
+// longest origin or path a collector accepts
+const MAX_PATH_LENGTH: u32 = 4096;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Frame {
+    File {
+        path: String,
+        data: Vec<u8>,
+    },
+    Class {
+        origin: String,
+        path: String,
+        data: Vec<u8>,
+    },
+    End,
+}
+
+// sends everything written to it as frames
+pub struct SocketSink<W: Write + Send> {
+    writer: Option<W>,
+}
+
+impl<W: Write + Send> SocketSink<W> {
+    pub fn new(mut writer: W) -> Result<Self, crate::error::Error> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[VERSION])?;
+        Ok(SocketSink {
+            writer: Some(writer),
+        })
+    }
+
+    fn send(
+        &mut self,
+        kind: u8,
+        origin: &str,
+        path: &str,
+        data: &[u8],
+    ) -> Result<(), crate::error::Error> {
+        let Some(writer) = self.writer.as_mut() else {
+            return Err(crate::error::Error::Stream("already finished".to_string()));
+        };
+
+        writer.write_all(&[kind])?;
+        for field in [origin.as_bytes(), path.as_bytes(), data] {
+            let length = u32::try_from(field.len())
+                .map_err(|_| crate::error::Error::Stream(format!("{path} is too large")))?;
+            writer.write_all(&length.to_be_bytes())?;
+            writer.write_all(field)?;
+        }
+        // frames still buffered are lost when the target is killed
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+impl<W: Write + Send> super::OutputSink for SocketSink<W> {
+    fn write(&mut self, path: &str, data: &[u8]) -> Result<(), crate::error::Error> {
+        self.send(FILE, "", path, data)
+    }
+
+    fn write_class(
+        &mut self,
+        origin: &str,
+        path: &str,
+        data: &[u8],
+    ) -> Result<(), crate::error::Error> {
+        self.send(CLASS, origin, path, data)
+    }
+
+    fn finish(&mut self) -> Result<(), crate::error::Error> {
+        if self.writer.is_some() {
+            self.send(END, "", "", &[])?;
+            let mut writer = self.writer.take().unwrap();
+            writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+// check the magic and version a stream starts with
+pub fn read_header(reader: &mut impl Read) -> Result<(), crate::error::Error> {
+    let mut header = [0; 5];
+    reader.read_exact(&mut header)?;
+    if &header[..4] != MAGIC {
+        return Err(crate::error::Error::Stream("not a dump stream".to_string()));
+    }
+    if header[4] != VERSION {
+        return Err(crate::error::Error::Stream(format!(
+            "unsupported version {}",
+            header[4]
+        )));
+    }
+    Ok(())
+}
+
+// the next frame, None when the stream was closed between frames
+pub fn read_frame(reader: &mut impl Read) -> Result<Option<Frame>, crate::error::Error> {
+    let mut kind = [0];
+    if reader.read(&mut kind)? == 0 {
+        return Ok(None);
+    }
+
+    // both come from the peer and end up in paths of the collector's output
+    let origin = read_path(reader)?;
+    let path = read_path(reader)?;
+    let length = read_length(reader)?;
+    let data = read_field(reader, length)?;
+    match kind[0] {
+        FILE | CLASS if path.is_empty() => {
+            Err(crate::error::Error::Stream("empty path".to_string()))
+        }
+        FILE => Ok(Some(Frame::File { path, data })),
+        CLASS => Ok(Some(Frame::Class { origin, path, data })),
+        END => Ok(Some(Frame::End)),
+        kind => Err(crate::error::Error::Stream(format!(
+            "unknown frame type {kind}"
+        ))),
+    }
+}
+
+fn read_length(reader: &mut impl Read) -> Result<u32, crate::error::Error> {
+    let mut length = [0; 4];
+    reader.read_exact(&mut length)?;
+    Ok(u32::from_be_bytes(length))
+}
+
+// grows with what arrives instead of trusting the length up front
+fn read_field(reader: &mut impl Read, length: u32) -> Result<Vec<u8>, crate::error::Error> {
+    let mut field = Vec::new();
+    reader.take(length.into()).read_to_end(&mut field)?;
+    if field.len() != length as usize {
+        return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+    }
+    Ok(field)
+}
+
+// a relative '/' separated path, without components leaving the directory it is written to
+fn read_path(reader: &mut impl Read) -> Result<String, crate::error::Error> {
+    let length = read_length(reader)?;
+    if length > MAX_PATH_LENGTH {
+        return Err(crate::error::Error::Stream(format!(
+            "path of {length} bytes is too long"
+        )));
+    }
+    let path = String::from_utf8(read_field(reader, length)?)
+        .map_err(|_| crate::error::Error::Stream("path is not utf-8".to_string()))?;
+    let escapes = |component: &str| {
+        matches!(component, "" | "." | "..") || component.contains(['\\', ':', '\0'])
+    };
+    if !path.is_empty() && path.split('/').any(escapes) {
+        return Err(crate::error::Error::Stream(format!("invalid path {path}")));
+    }
+    Ok(path)
+}
+
+// a sink streaming to a collector at "tcp:<host>:<port>" or "unix:<socket path>"
+pub fn connect(
+    address: &str,
+) -> Result<SocketSink<std::io::BufWriter<Box<dyn Write + Send>>>, crate::error::Error> {
+    let stream: Box<dyn Write + Send> = match address.split_once(':') {
+        Some(("tcp", address)) => Box::new(std::net::TcpStream::connect(address)?),
+        #[cfg(unix)]
+        Some(("unix", path)) => Box::new(std::os::unix::net::UnixStream::connect(path)?),
+        _ => return Err(unsupported_address(address)),
+    };
+    SocketSink::new(std::io::BufWriter::new(stream))
+}
+
+// where a collector accepts dump streams
+pub enum Listener {
+    Tcp(std::net::TcpListener),
+    #[cfg(unix)]
+    Unix(std::os::unix::net::UnixListener),
+}
+
+impl Listener {
+    // listen on an address in the form `connect` takes
+    pub fn bind(address: &str) -> Result<Self, crate::error::Error> {
+        match address.split_once(':') {
+            Some(("tcp", address)) => Ok(Listener::Tcp(std::net::TcpListener::bind(address)?)),
+            #[cfg(unix)]
+            Some(("unix", path)) => {
+                // a socket file left over by an earlier collector blocks binding
+                let _ = std::fs::remove_file(path);
+                Ok(Listener::Unix(std::os::unix::net::UnixListener::bind(
+                    path,
+                )?))
+            }
+            _ => Err(unsupported_address(address)),
+        }
+    }
+
+    // the next agent's stream and a description of where it comes from
+    pub fn accept(&self) -> Result<(Box<dyn Read + Send>, String), crate::error::Error> {
+        match self {
+            Listener::Tcp(listener) => {
+                let (stream, peer) = listener.accept()?;
+                Ok((Box::new(stream), peer.to_string()))
+            }
+            #[cfg(unix)]
+            Listener::Unix(listener) => {
+                let (stream, _) = listener.accept()?;
+                Ok((Box::new(stream), "unix socket".to_string()))
+            }
+        }
+    }
+}
+
+fn unsupported_address(address: &str) -> crate::error::Error {
+    crate::error::Error::Stream(format!(
+        "unsupported address {address}, expected tcp:<host>:<port> or unix:<path>"
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::OutputSink as _;
+    use super::*;
+
+    #[test]
+    fn test_frames_round_trip() {
+        let mut sink = SocketSink::new(Vec::new()).unwrap();
+        sink.write_class("foo-1.0", "a/B.class", &[0xCA, 0xFE])
+            .unwrap();
+        sink.write("origins.json", b"{}").unwrap();
+        sink.send(END, "", "", &[]).unwrap();
+        let mut stream = sink.writer.take().unwrap();
+        // cut off in the middle of a frame
+        stream.extend_from_slice(&[FILE, 0, 0]);
+
+        let mut reader = stream.as_slice();
+        read_header(&mut reader).unwrap();
+        assert_eq!(
+            read_frame(&mut reader).unwrap(),
+            Some(Frame::Class {
+                origin: "foo-1.0".to_string(),
+                path: "a/B.class".to_string(),
+                data: vec![0xCA, 0xFE],
+            })
+        );
+        assert_eq!(
+            read_frame(&mut reader).unwrap(),
+            Some(Frame::File {
+                path: "origins.json".to_string(),
+                data: b"{}".to_vec(),
+            })
+        );
+        assert_eq!(read_frame(&mut reader).unwrap(), Some(Frame::End));
+        assert!(read_frame(&mut reader).is_err());
+
+        // paths leaving the output are refused
+        for path in ["../x", "/etc/x", "a//b", "a/./b", "C:/x", "a\\..\\b"] {
+            let mut sink = SocketSink::new(Vec::new()).unwrap();
+            sink.write(path, &[]).unwrap();
+            let stream = sink.writer.take().unwrap();
+            let mut reader = stream.as_slice();
+            read_header(&mut reader).unwrap();
+            assert!(read_frame(&mut reader).is_err(), "{path}");
+        }
+    }
+}
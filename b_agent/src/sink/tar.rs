@@ -0,0 +1,192 @@
+use std::io::Write;
+
+const BLOCK_SIZE: usize = 512;
+
+// ustar stream, paths that don't fit the header get a gnu long name entry first
+pub struct TarSink<W: Write + Send> {
+    writer: Option<W>,
+}
+
+impl<W: Write + Send> TarSink<W> {
+    pub fn new(writer: W) -> Self {
+        TarSink {
+            writer: Some(writer),
+        }
+    }
+
+    // the writer with a complete archive in it
+    pub fn into_inner(mut self) -> Result<W, crate::error::Error> {
+        let Some(mut writer) = self.writer.take() else {
+            return Err(crate::error::Error::XValueNotOfType("tar already finished"));
+        };
+        end_archive(&mut writer)?;
+        Ok(writer)
+    }
+}
+
+impl<W: Write + Send> super::OutputSink for TarSink<W> {
+    fn write(&mut self, path: &str, data: &[u8]) -> Result<(), crate::error::Error> {
+        let Some(writer) = self.writer.as_mut() else {
+            return Err(crate::error::Error::XValueNotOfType("tar already finished"));
+        };
+
+        if path.len() > 100 {
+            let mut long_name = path.as_bytes().to_vec();
+            long_name.push(0);
+            write_entry(writer, "././@LongLink", b'L', &long_name)?;
+        }
+        write_entry(writer, path, b'0', data)
+    }
+
+    fn finish(&mut self) -> Result<(), crate::error::Error> {
+        if let Some(mut writer) = self.writer.take() {
+            end_archive(&mut writer)?;
+        }
+        Ok(())
+    }
+}
+
+// the end blocks are only written on finish, readers fail on an archive without them
+impl<W: Write + Send> Drop for TarSink<W> {
+    fn drop(&mut self) {
+        let _ = super::OutputSink::finish(self);
+    }
+}
+
+// two empty blocks end the archive
+fn end_archive(writer: &mut impl Write) -> Result<(), crate::error::Error> {
+    writer.write_all(&[0; BLOCK_SIZE * 2])?;
+    writer.flush()?;
+    Ok(())
+}
+
+fn write_entry(
+    writer: &mut impl Write,
+    path: &str,
+    type_flag: u8,
+    data: &[u8],
+) -> Result<(), crate::error::Error> {
+    writer.write_all(&header(path, type_flag, data.len())?)?;
+    writer.write_all(data)?;
+    let padding = (BLOCK_SIZE - data.len() % BLOCK_SIZE) % BLOCK_SIZE;
+    writer.write_all(&vec![0; padding])?;
+    Ok(())
+}
+
+fn header(path: &str, type_flag: u8, size: usize) -> Result<[u8; BLOCK_SIZE], crate::error::Error> {
+    let mut header = [0; BLOCK_SIZE];
+    // longer names were written in a long name entry already
+    let name = &path.as_bytes()[..path.len().min(100)];
+    header[..name.len()].copy_from_slice(name);
+    put_octal(&mut header[100..108], 0o644);
+    put_octal(&mut header[108..116], 0);
+    put_octal(&mut header[116..124], 0);
+    if size as u64 >= 0o77777777777 {
+        return Err(crate::error::Error::XValueNotOfType("tar entry too large"));
+    }
+    put_octal(&mut header[124..136], size as u64);
+    put_octal(&mut header[136..148], 0);
+    header[156] = type_flag;
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+
+    // the checksum is computed with its own field filled with spaces
+    header[148..156].fill(b' ');
+    let checksum = header.iter().map(|&b| b as u64).sum::<u64>();
+    put_octal(&mut header[148..155], checksum);
+    Ok(header)
+}
+
+// files of an archive written by TarSink, keyed by path
+pub fn read_entries(
+    mut reader: impl std::io::Read,
+) -> Result<std::collections::BTreeMap<String, Vec<u8>>, crate::error::Error> {
+    let mut entries = std::collections::BTreeMap::new();
+    let mut long_name = None;
+    let mut header = [0; BLOCK_SIZE];
+    loop {
+        reader.read_exact(&mut header)?;
+        // an empty block starts the end of the archive
+        if header.iter().all(|&b| b == 0) {
+            return Ok(entries);
+        }
+
+        let size = std::str::from_utf8(&header[124..135])
+            .ok()
+            .and_then(|size| usize::from_str_radix(size.trim_matches(['\0', ' ']), 8).ok())
+            .ok_or(crate::error::Error::XValueNotOfType("tar entry size"))?;
+        let mut data = vec![0; size.div_ceil(BLOCK_SIZE) * BLOCK_SIZE];
+        reader.read_exact(&mut data)?;
+        data.truncate(size);
+
+        match header[156] {
+            b'L' => {
+                let end = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+                long_name = Some(String::from_utf8_lossy(&data[..end]).into_owned());
+            }
+            _ => {
+                let path = long_name.take().unwrap_or_else(|| {
+                    let end = header[..100].iter().position(|&b| b == 0).unwrap_or(100);
+                    String::from_utf8_lossy(&header[..end]).into_owned()
+                });
+                entries.insert(path, data);
+            }
+        }
+    }
+}
+
+// zero padded octal digits followed by a nul
+fn put_octal(field: &mut [u8], value: u64) {
+    let (digits, nul) = field.split_at_mut(field.len() - 1);
+    digits.copy_from_slice(format!("{value:0width$o}", width = digits.len()).as_bytes());
+    nul[0] = 0;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sink::OutputSink;
+
+    #[test]
+    fn test_archive_layout() {
+        let mut sink = TarSink::new(Vec::new());
+        sink.write("a/B.class", &[0xCA, 0xFE]).unwrap();
+        sink.write(&format!("{}/C.class", "a".repeat(120)), &[])
+            .unwrap();
+        let archive = sink.into_inner().unwrap();
+        let entries = read_entries(archive.as_slice()).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries["a/B.class"], [0xCA, 0xFE]);
+
+        // header and one data block, long name header and block, header, end blocks
+        assert_eq!(archive.len(), BLOCK_SIZE * 7);
+        assert_eq!(&archive[..9], b"a/B.class");
+        assert_eq!(&archive[257..262], b"ustar");
+        assert_eq!(&archive[BLOCK_SIZE..BLOCK_SIZE + 2], &[0xCA, 0xFE]);
+        assert_eq!(archive[BLOCK_SIZE * 2 + 156], b'L');
+
+        let checksum = archive[..BLOCK_SIZE]
+            .iter()
+            .enumerate()
+            .map(|(i, &b)| {
+                if (148..156).contains(&i) {
+                    b' ' as u64
+                } else {
+                    b as u64
+                }
+            })
+            .sum::<u64>();
+        assert_eq!(
+            format!("{checksum:06o}\0 "),
+            String::from_utf8_lossy(&archive[148..156])
+        );
+
+        // dropped without finishing, e.g. when the dump ends with an error
+        let mut archive = Vec::new();
+        let mut sink = TarSink::new(&mut archive);
+        sink.write("a/B.class", &[0xCA, 0xFE]).unwrap();
+        drop(sink);
+        assert_eq!(archive.len(), BLOCK_SIZE * 4);
+        assert_eq!(read_entries(archive.as_slice()).unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,455 @@
+use std::{collections::HashMap, path::Path};
+
+use rusqlite::{Connection, Transaction, params};
+
+use crate::classfile::{ClassFile, Constant};
+
+// tables written by `export`. the comments are kept in sqlite_master, so
+// `SELECT sql FROM sqlite_master` documents a database on its own.
+// classes in package x loaded by loader y that reference Cipher:
+//   SELECT DISTINCT c.name FROM classes c
+//   JOIN loaders l ON l.id = c.loader_id
+//   JOIN refs r ON r.class_id = c.id
+//   WHERE c.package = 'x' AND l.name = 'y' AND r.owner = 'javax.crypto.Cipher'
+pub const SCHEMA: &str = "
+-- the dump session, one row from session.json
+CREATE TABLE session (
+    agent_version TEXT,
+    started_at INTEGER,     -- milliseconds since the unix epoch
+    finished_at INTEGER,
+    dumped INTEGER,         -- classes saved during the session
+    failed INTEGER,         -- classes that couldn't be saved
+    unresolved INTEGER,     -- referenced classes that were never found
+    jvm_version TEXT,       -- NULL for offline extraction
+    jvm_vendor TEXT,
+    command TEXT,           -- main class or jar and its arguments
+    class_path TEXT,
+    manifest TEXT           -- the whole session.json
+);
+
+-- defining class loaders, 'bootstrap' or the loader class and its identity hash
+CREATE TABLE loaders (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE
+);
+
+-- one row per dumped class file
+CREATE TABLE classes (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL,         -- binary name, e.g. java.lang.String
+    package TEXT NOT NULL,      -- '' for the unnamed package
+    path TEXT NOT NULL UNIQUE,  -- path in the dump
+    origin TEXT,                -- bucket of the jar or directory it was loaded from
+    code_source TEXT,           -- protection domain url
+    loader_id INTEGER REFERENCES loaders(id),
+    module TEXT,                -- 'unnamed' outside named modules
+    thread TEXT,                -- thread that loaded the class
+    loaded_at INTEGER,          -- milliseconds since the unix epoch
+    size INTEGER NOT NULL,
+    sha256 TEXT NOT NULL,
+    -- NULL when the bytes don't parse, see parse_error
+    super_name TEXT,
+    access_flags INTEGER,
+    is_interface INTEGER,
+    major_version INTEGER,
+    minor_version INTEGER,
+    parse_error TEXT
+);
+CREATE INDEX classes_name ON classes(name);
+CREATE INDEX classes_package ON classes(package);
+
+-- directly implemented interfaces, by binary name
+CREATE TABLE interfaces (
+    class_id INTEGER NOT NULL REFERENCES classes(id),
+    name TEXT NOT NULL
+);
+CREATE INDEX interfaces_name ON interfaces(name);
+
+-- declared fields and methods
+CREATE TABLE members (
+    id INTEGER PRIMARY KEY,
+    class_id INTEGER NOT NULL REFERENCES classes(id),
+    kind TEXT NOT NULL,         -- 'field' or 'method'
+    name TEXT NOT NULL,
+    descriptor TEXT NOT NULL,
+    access_flags INTEGER NOT NULL
+);
+CREATE INDEX members_class ON members(class_id);
+CREATE INDEX members_name ON members(name);
+
+-- classes, fields and methods named in the constant pool. arrays are reduced to their
+-- element class, arrays of primitives are left out.
+CREATE TABLE refs (
+    class_id INTEGER NOT NULL REFERENCES classes(id),
+    kind TEXT NOT NULL,         -- 'class', 'field', 'method' or 'interface_method'
+    owner TEXT NOT NULL,        -- binary name of the referenced class
+    name TEXT,                  -- member name, NULL for kind 'class'
+    descriptor TEXT             -- member descriptor, NULL for kind 'class'
+);
+CREATE INDEX refs_class ON refs(class_id);
+CREATE INDEX refs_owner ON refs(owner);
+
+-- dependency edges as followed by the dumper, one row per kind
+CREATE TABLE dependencies (
+    class_id INTEGER NOT NULL REFERENCES classes(id),
+    target TEXT NOT NULL,       -- binary name, the class may not be in the dump
+    kind TEXT NOT NULL          -- e.g. 'extends', 'calls', 'field_access'
+);
+CREATE INDEX dependencies_class ON dependencies(class_id);
+CREATE INDEX dependencies_target ON dependencies(target);
+
+-- java stack of the loading thread, depth 0 is the innermost frame
+CREATE TABLE load_stacks (
+    class_id INTEGER NOT NULL REFERENCES classes(id),
+    depth INTEGER NOT NULL,
+    frame TEXT NOT NULL
+);
+CREATE INDEX load_stacks_class ON load_stacks(class_id);
+";
+
+#[derive(Debug, Default)]
+pub struct SqliteSummary {
+    pub classes: usize,
+    // classes saved although their bytes don't parse, only their file columns are set
+    pub unparsed: usize,
+    pub members: usize,
+    pub references: usize,
+    pub dependencies: usize,
+}
+
+// write the classes of a dump and the metadata recorded with them into a new database
+pub fn export(dump: &Path, out: &Path) -> Result<SqliteSummary, crate::error::Error> {
+    let mut reader = crate::manifest::OutputReader::open(dump)?;
+    let manifest = reader.manifest()?;
+
+    if out.exists() {
+        std::fs::remove_file(out)?;
+    }
+    let mut connection = Connection::open(out)?;
+    let transaction = connection.transaction()?;
+    transaction.execute_batch(SCHEMA)?;
+
+    let jvm = manifest.jvm.clone().unwrap_or_default();
+    transaction.execute(
+        "INSERT INTO session VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+        params![
+            manifest.agent_version,
+            manifest.started_at as i64,
+            manifest.finished_at as i64,
+            manifest.dumped as i64,
+            manifest.failed as i64,
+            manifest.unresolved as i64,
+            jvm.version,
+            jvm.vendor,
+            jvm.command,
+            jvm.class_path,
+            serde_json::to_string(&manifest)?,
+        ],
+    )?;
+
+    let mut summary = SqliteSummary::default();
+    let mut loaders = HashMap::new();
+    for (path, entry) in &manifest.files {
+        if entry.origin.is_none() || !path.ends_with(".class") {
+            continue;
+        }
+        let Some(data) = reader.read(entry.origin.as_deref(), path)? else {
+            continue;
+        };
+        // written next to classes hooked in a jvm
+        let source: crate::recorder::ClassSource =
+            match reader.read(entry.origin.as_deref(), &format!("{path}.json"))? {
+                Some(sidecar) => serde_json::from_slice(&sidecar)?,
+                None => Default::default(),
+            };
+        let loader_id = match &source.loader {
+            Some(loader) => Some(loader_id(&transaction, &mut loaders, loader)?),
+            None => None,
+        };
+
+        let fallback_name = crate::classfile::binary_name(path.trim_end_matches(".class"));
+        let class_file = ClassFile::parse(&data);
+        let name = match &class_file {
+            Ok(class_file) => class_file
+                .name()
+                .map_or(fallback_name, |name| crate::classfile::binary_name(&name)),
+            Err(_) => fallback_name,
+        };
+        let package = name.rfind('.').map_or("", |index| &name[..index]);
+        transaction.execute(
+            "INSERT INTO classes (name, package, path, origin, code_source, loader_id, module,
+                thread, loaded_at, size, sha256)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            params![
+                name,
+                package,
+                path,
+                entry.origin,
+                source.code_source,
+                loader_id,
+                source.module,
+                source.thread,
+                source.loaded_at.map(|loaded_at| loaded_at as i64),
+                data.len() as i64,
+                entry.sha256,
+            ],
+        )?;
+        let class_id = transaction.last_insert_rowid();
+        for (depth, frame) in source.stack.iter().enumerate() {
+            transaction.execute(
+                "INSERT INTO load_stacks VALUES (?1, ?2, ?3)",
+                params![class_id, depth as i64, frame],
+            )?;
+        }
+        summary.classes += 1;
+
+        let parsed = class_file.and_then(|class_file| {
+            insert_class_file(&transaction, class_id, &class_file, &mut summary)
+        });
+        if let Err(e) = parsed {
+            println!("failed to parse {name}: {e}");
+            transaction.execute(
+                "UPDATE classes SET parse_error = ?1 WHERE id = ?2",
+                params![e.to_string(), class_id],
+            )?;
+            summary.unparsed += 1;
+        }
+    }
+
+    transaction.commit()?;
+    Ok(summary)
+}
+
+fn loader_id(
+    transaction: &Transaction,
+    loaders: &mut HashMap<String, i64>,
+    loader: &str,
+) -> Result<i64, crate::error::Error> {
+    if let Some(&id) = loaders.get(loader) {
+        return Ok(id);
+    }
+    transaction.execute("INSERT INTO loaders (name) VALUES (?1)", params![loader])?;
+    let id = transaction.last_insert_rowid();
+    loaders.insert(loader.to_string(), id);
+    Ok(id)
+}
+
+// structure, constant pool references and dependencies of a parsed class
+fn insert_class_file(
+    transaction: &Transaction,
+    class_id: i64,
+    class_file: &ClassFile,
+    summary: &mut SqliteSummary,
+) -> Result<(), crate::error::Error> {
+    transaction.execute(
+        "UPDATE classes SET super_name = ?1, access_flags = ?2, is_interface = ?3,
+            major_version = ?4, minor_version = ?5
+         WHERE id = ?6",
+        params![
+            class_file
+                .super_name()?
+                .map(|name| crate::classfile::binary_name(&name)),
+            class_file.access_flags,
+            class_file.is_interface(),
+            class_file.major_version,
+            class_file.minor_version,
+            class_id,
+        ],
+    )?;
+    for interface in class_file.interface_names()? {
+        transaction.execute(
+            "INSERT INTO interfaces VALUES (?1, ?2)",
+            params![class_id, crate::classfile::binary_name(&interface)],
+        )?;
+    }
+
+    let members = [
+        ("field", &class_file.fields),
+        ("method", &class_file.methods),
+    ];
+    for (kind, members) in members {
+        for member in members {
+            transaction.execute(
+                "INSERT INTO members (class_id, kind, name, descriptor, access_flags)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    class_id,
+                    kind,
+                    class_file.member_name(member)?,
+                    class_file.member_descriptor(member)?,
+                    member.access_flags,
+                ],
+            )?;
+            summary.members += 1;
+        }
+    }
+
+    let constant_pool = &class_file.constant_pool;
+    for (index, constant) in constant_pool.iter() {
+        let (kind, class_name, member) = match constant {
+            Constant::Class(_) => ("class", constant_pool.class_name(index)?, None),
+            Constant::FieldRef(..) | Constant::MethodRef(..) | Constant::InterfaceMethodRef(..) => {
+                let (owner, name, descriptor) = constant_pool.member_ref(index)?;
+                let kind = match constant {
+                    Constant::FieldRef(..) => "field",
+                    Constant::MethodRef(..) => "method",
+                    _ => "interface_method",
+                };
+                (kind, owner, Some((name, descriptor)))
+            }
+            _ => continue,
+        };
+        let Some(owner) = element_class(&class_name) else {
+            continue;
+        };
+        let (name, descriptor) = member.unzip();
+        transaction.execute(
+            "INSERT INTO refs VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![class_id, kind, owner, name, descriptor],
+        )?;
+        summary.references += 1;
+    }
+
+    for (kind, target) in crate::classfile::dependencies::dependency_edges(class_file)? {
+        transaction.execute(
+            "INSERT INTO dependencies VALUES (?1, ?2, ?3)",
+            params![class_id, target, kind.name()],
+        )?;
+        summary.dependencies += 1;
+    }
+
+    Ok(())
+}
+
+// binary name of a class constant, arrays reduced to their element class
+fn element_class(class_name: &str) -> Option<String> {
+    let element = class_name.trim_start_matches('[');
+    if element.len() == class_name.len() {
+        return Some(crate::classfile::binary_name(class_name));
+    }
+    element
+        .strip_prefix('L')
+        .and_then(|element| element.strip_suffix(';'))
+        .map(crate::classfile::binary_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::classfile::{ConstantPool, Member};
+    use crate::sink::OutputSink;
+
+    #[test]
+    fn test_export_sqlite() {
+        let root = std::env::temp_dir().join(format!("b_agent_sqlite_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+
+        // a.B implements Serializable, references Cipher.getInstance and has one field
+        let mut constant_pool = ConstantPool::new();
+        let this_class = constant_pool.add_class("a/B").unwrap();
+        let super_class = constant_pool.add_class("java/lang/Object").unwrap();
+        let serializable = constant_pool.add_class("java/io/Serializable").unwrap();
+        let cipher = constant_pool.add_class("javax/crypto/Cipher").unwrap();
+        let name = constant_pool.add_utf8("getInstance").unwrap();
+        let descriptor = constant_pool
+            .add_utf8("(Ljava/lang/String;)Ljavax/crypto/Cipher;")
+            .unwrap();
+        let name_and_type = constant_pool
+            .add(Constant::NameAndType(name, descriptor))
+            .unwrap();
+        constant_pool
+            .add(Constant::MethodRef(cipher, name_and_type))
+            .unwrap();
+        constant_pool.add_class("[[Ljava/security/Key;").unwrap();
+        let field_name = constant_pool.add_utf8("key").unwrap();
+        let field_descriptor = constant_pool.add_utf8("[B").unwrap();
+        let class_data = ClassFile {
+            minor_version: 0,
+            major_version: 52,
+            constant_pool,
+            access_flags: 0x0021,
+            this_class,
+            super_class,
+            interfaces: vec![serializable],
+            fields: vec![Member {
+                access_flags: 0x0002,
+                name_index: field_name,
+                descriptor_index: field_descriptor,
+                attributes: Vec::new(),
+            }],
+            methods: Vec::new(),
+            attributes: Vec::new(),
+        }
+        .to_bytes();
+
+        let dump = root.join("dump");
+        let mut sink = crate::manifest::HashingSink::new(Box::new(
+            crate::sink::DirectorySink::new(dump.clone()),
+        ));
+        let source = crate::recorder::ClassSource {
+            loader: Some("app@1".to_string()),
+            stack: vec!["a.Main.main([Ljava/lang/String;)V line 3".to_string()],
+            ..Default::default()
+        };
+        sink.write_class("app", "a/B.class", &class_data).unwrap();
+        sink.write_class(
+            "app",
+            "a/B.class.json",
+            &crate::recorder::metadata_sidecar("a.B", &source).unwrap(),
+        )
+        .unwrap();
+        sink.write_class("app", "a/Broken.class", &[0xCA, 0xFE])
+            .unwrap();
+        let mut manifest = crate::manifest::SessionManifest::new(Default::default());
+        manifest.files = sink.files().clone();
+        sink.write(
+            crate::manifest::MANIFEST_FILE_NAME,
+            &serde_json::to_vec(&manifest).unwrap(),
+        )
+        .unwrap();
+
+        let out = root.join("dump.db");
+        let summary = export(&dump, &out).unwrap();
+        assert_eq!(summary.classes, 2);
+        assert_eq!(summary.unparsed, 1);
+        assert_eq!(summary.members, 1);
+
+        let connection = Connection::open(&out).unwrap();
+        let users: Vec<String> = connection
+            .prepare(
+                "SELECT DISTINCT c.name FROM classes c
+                 JOIN loaders l ON l.id = c.loader_id
+                 JOIN refs r ON r.class_id = c.id
+                 WHERE c.package = 'a' AND l.name = 'app@1' AND r.owner = 'javax.crypto.Cipher'",
+            )
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(users, ["a.B"]);
+
+        let count = |sql: &str| -> i64 { connection.query_row(sql, [], |row| row.get(0)).unwrap() };
+        assert_eq!(
+            count("SELECT COUNT(*) FROM refs WHERE owner = 'java.security.Key'"),
+            1
+        );
+        assert_eq!(count("SELECT COUNT(*) FROM load_stacks"), 1);
+        assert_eq!(
+            count(
+                "SELECT COUNT(*) FROM classes WHERE parse_error IS NOT NULL AND name = 'a.Broken'"
+            ),
+            1
+        );
+        assert_eq!(
+            count(
+                "SELECT COUNT(*) FROM dependencies d JOIN interfaces i ON i.class_id = d.class_id
+                 WHERE d.target = i.name AND d.kind = 'implements'"
+            ),
+            1
+        );
+
+        drop(connection);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
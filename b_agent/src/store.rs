@@ -0,0 +1,245 @@
+use std::{
+    collections::HashSet,
+    fs::{File, OpenOptions},
+    io::{BufRead as _, BufReader, Write as _},
+    path::{Path, PathBuf},
+};
+
+use sha2::{Digest as _, Sha256};
+
+pub const INDEX_FILE_NAME: &str = "index.jsonl";
+
+// one stored class of a session, appended to the index as a json line
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct IndexEntry {
+    pub session: String,
+    pub class: String,
+    pub sha256: String,
+    pub size: usize,
+    // loader, code source and load context, the loader is None for offline extraction
+    #[serde(flatten)]
+    pub source: crate::recorder::ClassSource,
+    // seconds since the unix epoch
+    pub stored_at: u64,
+}
+
+// class bytes stored once under their sha-256, with an index of which session and loader
+// saw which class. layout below the root:
+//   objects/<first two hex digits>/<hash>   class bytes
+//   index.jsonl                             one IndexEntry per line
+//   sessions/<session>/                     reports of the session
+pub struct ClassStore {
+    root: PathBuf,
+    session: String,
+    index: File,
+    // hashes known to be in objects/, saves a file system lookup per class
+    stored: HashSet<String>,
+}
+
+impl ClassStore {
+    pub fn open(root: &Path, session: String) -> Result<Self, crate::error::Error> {
+        std::fs::create_dir_all(root.join("objects"))?;
+        std::fs::create_dir_all(session_dir(root, &session))?;
+        let index = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(root.join(INDEX_FILE_NAME))?;
+
+        Ok(ClassStore {
+            root: root.to_path_buf(),
+            session,
+            index,
+            stored: HashSet::new(),
+        })
+    }
+
+    pub fn session(&self) -> &str {
+        &self.session
+    }
+
+    // directory the reports of this session are written to
+    pub fn session_dir(&self) -> PathBuf {
+        session_dir(&self.root, &self.session)
+    }
+
+    // store class bytes unless an earlier session or loader already did, and index them
+    pub fn put(
+        &mut self,
+        class_name: &str,
+        class_data: &[u8],
+        source: &crate::recorder::ClassSource,
+    ) -> Result<IndexEntry, crate::error::Error> {
+        let hash = sha256_hex(class_data);
+        let object = object_path(&self.root, &hash);
+        if !self.stored.contains(&hash) && !object.exists() {
+            std::fs::create_dir_all(object.parent().unwrap())?;
+            // written under a temporary name so a crash never leaves a truncated object
+            let partial = object.with_extension("partial");
+            std::fs::write(&partial, class_data)?;
+            std::fs::rename(&partial, &object)?;
+        }
+        self.stored.insert(hash.clone());
+
+        let entry = IndexEntry {
+            session: self.session.clone(),
+            class: class_name.to_string(),
+            sha256: hash,
+            size: class_data.len(),
+            source: source.clone(),
+            stored_at: unix_time(),
+        };
+        let mut line = serde_json::to_vec(&entry)?;
+        line.push(b'\n');
+        self.index.write_all(&line)?;
+        Ok(entry)
+    }
+}
+
+// every entry of a store's index, in the order they were stored
+pub fn read_index(root: &Path) -> Result<Vec<IndexEntry>, crate::error::Error> {
+    let file = File::open(root.join(INDEX_FILE_NAME))?;
+    let mut entries = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            entries.push(serde_json::from_str(&line)?);
+        }
+    }
+    Ok(entries)
+}
+
+// sessions of a store, oldest first
+pub fn sessions(root: &Path) -> Result<Vec<String>, crate::error::Error> {
+    let mut sessions = Vec::<String>::new();
+    for entry in read_index(root)? {
+        if !sessions.contains(&entry.session) {
+            sessions.push(entry.session);
+        }
+    }
+    Ok(sessions)
+}
+
+#[derive(Debug, Default)]
+pub struct ExportSummary {
+    pub classes: usize,
+    // classes of the same name from another loader, a tree holds only one of them
+    pub shadowed: Vec<IndexEntry>,
+}
+
+// write the classes and reports of a session into a sink, the first class of a name wins
+pub fn export(
+    root: &Path,
+    session: &str,
+    sink: &mut dyn crate::sink::OutputSink,
+) -> Result<ExportSummary, crate::error::Error> {
+    let mut summary = ExportSummary::default();
+    let mut exported = HashSet::new();
+    for entry in read_index(root)? {
+        if entry.session != session {
+            continue;
+        }
+        if !exported.insert(entry.class.clone()) {
+            summary.shadowed.push(entry);
+            continue;
+        }
+
+        let class_data = std::fs::read(object_path(root, &entry.sha256))?;
+        if sha256_hex(&class_data) != entry.sha256 {
+            return Err(crate::error::Error::ClassFormat(format!(
+                "stored object of {} does not match its hash",
+                entry.class
+            )));
+        }
+        let origin = entry.source.code_source.as_deref().map_or(
+            crate::origin::NO_CODE_SOURCE.to_string(),
+            crate::origin::bucket_name,
+        );
+        let save_path = format!("{}.class", entry.class.replace('.', "/"));
+        sink.write_class(&origin, &save_path, &class_data)?;
+        if entry.source.loader.is_some() {
+            sink.write_class(
+                &origin,
+                &format!("{save_path}.json"),
+                &crate::recorder::metadata_sidecar(&entry.class, &entry.source)?,
+            )?;
+        }
+        summary.classes += 1;
+    }
+
+    let reports = session_dir(root, session);
+    if reports.is_dir() {
+        for report in std::fs::read_dir(reports)? {
+            let report = report?;
+            if report.file_type()?.is_file() {
+                let name = report.file_name().to_string_lossy().into_owned();
+                sink.write(&name, &std::fs::read(report.path())?)?;
+            }
+        }
+    }
+    sink.finish()?;
+
+    Ok(summary)
+}
+
+pub fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+// session names sort in the order they were started
+pub fn new_session_name() -> String {
+    format!("{}-{}", unix_time(), std::process::id())
+}
+
+fn object_path(root: &Path, hash: &str) -> PathBuf {
+    root.join("objects").join(&hash[..2]).join(hash)
+}
+
+fn session_dir(root: &Path, session: &str) -> PathBuf {
+    root.join("sessions").join(session)
+}
+
+fn unix_time() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dedupe_and_export() {
+        let root = std::env::temp_dir().join(format!("b_agent_store_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        let source = crate::recorder::ClassSource::default();
+
+        for session in ["1", "2"] {
+            let mut store = ClassStore::open(&root, session.to_string()).unwrap();
+            store.put("a.B", &[0xCA, 0xFE], &source).unwrap();
+            store.put("a.C", session.as_bytes(), &source).unwrap();
+        }
+
+        // a.B is shared by both sessions
+        let objects = std::fs::read_dir(root.join("objects"))
+            .unwrap()
+            .map(|dir| std::fs::read_dir(dir.unwrap().path()).unwrap().count())
+            .sum::<usize>();
+        assert_eq!(objects, 3);
+        assert_eq!(sessions(&root).unwrap(), ["1", "2"]);
+        assert_eq!(
+            sha256_hex(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+
+        let sink = crate::sink::MemorySink::new();
+        let summary = export(&root, "2", &mut sink.clone()).unwrap();
+        assert_eq!(summary.classes, 2);
+        assert_eq!(sink.files()["a/C.class"], b"2");
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -10,6 +10,23 @@ public class Retransformer {
     // this is called by rust side
     public static String[] getAllDependencies(byte[] bytes) {
         Set<String> dependencies = new TreeSet<>();
+        for (String edge : collect(bytes)) {
+            // annotations are only loaded when read reflectively, so they are not followed
+            if (!edge.startsWith("annotation ")) {
+                dependencies.add(edge.substring(edge.indexOf(' ') + 1));
+            }
+        }
+
+        return dependencies.toArray(new String[0]);
+    }
+
+    // this is called by rust side, each entry is "<kind> <class name>"
+    public static String[] getDependencyEdges(byte[] bytes) {
+        return collect(bytes).toArray(new String[0]);
+    }
+
+    private static Set<String> collect(byte[] bytes) {
+        Set<String> edges = new TreeSet<>();
 
         ClassReader reader = new ClassReader(bytes);
 
@@ -17,111 +34,134 @@ public class Retransformer {
             @Override
             public void visit(int version, int access, String name, String signature, String superName, String[] interfaces) {
                 if (superName != null) {
-                    addName(superName);
+                    addName("extends", superName);
                 }
                 if (interfaces != null) {
                     for (String iface : interfaces) {
-                        addName(iface);
+                        addName("implements", iface);
                     }
                 }
                 super.visit(version, access, name, signature, superName, interfaces);
             }
 
+            @Override
+            public AnnotationVisitor visitAnnotation(String descriptor, boolean visible) {
+                addDesc("annotation", descriptor);
+                return super.visitAnnotation(descriptor, visible);
+            }
+
             @Override
             public FieldVisitor visitField(int access, String name, String descriptor, String signature, Object value) {
-                addDesc(descriptor);
-                return super.visitField(access, name, descriptor, signature, value);
+                addDesc("field_type", descriptor);
+                return new FieldVisitor(Opcodes.ASM9) {
+
+                    @Override
+                    public AnnotationVisitor visitAnnotation(String descriptor, boolean visible) {
+                        addDesc("annotation", descriptor);
+                        return super.visitAnnotation(descriptor, visible);
+                    }
+                };
             }
 
             @Override
             public MethodVisitor visitMethod(int access, String name, String descriptor, String signature, String[] exceptions) {
-                addMethodDesc(descriptor);
+                addMethodDesc("signature", descriptor);
                 if (exceptions != null) {
                     for (String exc : exceptions) {
-                        addName(exc);
+                        addName("throws", exc);
                     }
                 }
 
                 return new MethodVisitor(Opcodes.ASM9) {
 
+                    @Override
+                    public AnnotationVisitor visitAnnotation(String descriptor, boolean visible) {
+                        addDesc("annotation", descriptor);
+                        return super.visitAnnotation(descriptor, visible);
+                    }
+
+                    @Override
+                    public AnnotationVisitor visitParameterAnnotation(int parameter, String descriptor, boolean visible) {
+                        addDesc("annotation", descriptor);
+                        return super.visitParameterAnnotation(parameter, descriptor, visible);
+                    }
+
                     @Override
                     public void visitTypeInsn(int opcode, String type) {
-                        addName(type);
+                        addName(opcode == Opcodes.NEW ? "instantiates" : "type_check", type);
                         super.visitTypeInsn(opcode, type);
                     }
 
                     @Override
                     public void visitFieldInsn(int opcode, String owner, String name, String descriptor) {
-                        addName(owner);
-                        addDesc(descriptor);
+                        addName("field_access", owner);
+                        addDesc("field_access", descriptor);
                         super.visitFieldInsn(opcode, owner, name, descriptor);
                     }
 
                     @Override
                     public void visitMethodInsn(int opcode, String owner, String name, String descriptor, boolean isInterface) {
-                        addName(owner);
-                        addMethodDesc(descriptor);
+                        addName("calls", owner);
+                        addMethodDesc("calls", descriptor);
                         super.visitMethodInsn(opcode, owner, name, descriptor, isInterface);
                     }
 
                     @Override
                     public void visitLdcInsn(Object value) {
                         if (value instanceof Type) {
-                            addType((Type) value);
+                            addType("class_literal", (Type) value);
                         }
                         super.visitLdcInsn(value);
                     }
 
                     @Override
                     public void visitMultiANewArrayInsn(String descriptor, int dims) {
-                        addDesc(descriptor);
+                        addDesc("instantiates", descriptor);
                         super.visitMultiANewArrayInsn(descriptor, dims);
                     }
 
                     @Override
                     public void visitTryCatchBlock(Label start, Label end, Label handler, String type) {
                         if (type != null) {
-                            addName(type);
+                            addName("catches", type);
                         }
                         super.visitTryCatchBlock(start, end, handler, type);
                     }
                 };
             }
 
-            private void addName(String name) {
-                if (name == null || name.startsWith("[")) {
+            private void addName(String kind, String name) {
+                if (name == null || name.startsWith("[") || name.equals("java/lang/Object")) {
                     return;
                 }
-                dependencies.add(name.replace('/', '.'));
+                edges.add(kind + " " + name.replace('/', '.'));
             }
 
-            private void addDesc(String desc) {
-                addType(Type.getType(desc));
+            private void addDesc(String kind, String desc) {
+                addType(kind, Type.getType(desc));
             }
 
-            private void addMethodDesc(String methodDesc) {
-                addType(Type.getReturnType(methodDesc));
+            private void addMethodDesc(String kind, String methodDesc) {
+                addType(kind, Type.getReturnType(methodDesc));
                 for (Type type : Type.getArgumentTypes(methodDesc)) {
-                    addType(type);
+                    addType(kind, type);
                 }
             }
 
-            private void addType(Type type) {
+            private void addType(String kind, Type type) {
                 switch (type.getSort()) {
                     case Type.ARRAY:
-                        addType(type.getElementType());
+                        addType(kind, type.getElementType());
                         break;
                     case Type.OBJECT:
-                        addName(type.getInternalName());
+                        addName(kind, type.getInternalName());
                         break;
                 }
             }
 
         }, ClassReader.SKIP_DEBUG | ClassReader.SKIP_FRAMES);
 
-        dependencies.remove("java.lang.Object");
-
-        return dependencies.toArray(new String[0]);
+        return edges;
     }
 
 }